@@ -0,0 +1,170 @@
+///
+/// The compress module compresses hoisted data for REMOTE ringname
+/// COMPRESS codec and decompresses it on the receiving side.  The data
+/// are in the codec's standard streaming format: lz4 frames or a zstd
+/// stream, so the lz4 and zstd command line tools can decompress
+/// captured data too.
+///
+/// Data written to a Compressor are sent by the next flush; the ring
+/// master flushes after each chunk the hoister gives it so a slow ring's
+/// data are not held back waiting to fill a block.
+///
+pub mod compress {
+    use lz4_flex::frame::{FrameDecoder, FrameEncoder};
+    use std::io::{self, BufReader, Read, Write};
+
+    const ZSTD_LEVEL: i32 = 3; // zstd's own default.
+
+    ///
+    /// The supported compression codecs.
+    ///
+    #[derive(Copy, Clone, Debug, PartialEq)]
+    pub enum Codec {
+        Lz4,
+        Zstd,
+    }
+    impl Codec {
+        ///
+        /// The codec named by a REMOTE request (lz4 or zstd).
+        ///
+        pub fn parse(name: &str) -> Result<Codec, String> {
+            match name {
+                "lz4" => Ok(Codec::Lz4),
+                "zstd" => Ok(Codec::Zstd),
+                _ => Err(format!("Unsupported compression {}", name)),
+            }
+        }
+        ///
+        /// The first supported codec in a comma separated list of codecs
+        /// in order of preference, e.g. zstd,lz4.  Err if none are.
+        ///
+        pub fn choose(names: &str) -> Result<Codec, String> {
+            names
+                .split(',')
+                .find_map(|name| Codec::parse(name).ok())
+                .ok_or_else(|| format!("None of the compressions {} are supported", names))
+        }
+        ///
+        /// The name of the codec as the reply to a REMOTE request gives it.
+        ///
+        pub fn name(&self) -> &'static str {
+            match self {
+                Codec::Lz4 => "lz4",
+                Codec::Zstd => "zstd",
+            }
+        }
+    }
+    ///
+    /// Compresses what's written to it onto an underlying writer.
+    ///
+    pub enum Compressor<W: Write> {
+        Lz4(FrameEncoder<W>),
+        Zstd(zstd::Encoder<'static, W>),
+    }
+    impl<W: Write> Compressor<W> {
+        pub fn new(codec: Codec, output: W) -> io::Result<Compressor<W>> {
+            Ok(match codec {
+                Codec::Lz4 => Compressor::Lz4(FrameEncoder::new(output)),
+                Codec::Zstd => Compressor::Zstd(zstd::Encoder::new(output, ZSTD_LEVEL)?),
+            })
+        }
+        ///
+        /// End the compressed stream, returning the underlying writer.
+        ///
+        pub fn finish(self) -> io::Result<W> {
+            match self {
+                Compressor::Lz4(encoder) => encoder.finish().map_err(io::Error::other),
+                Compressor::Zstd(encoder) => encoder.finish(),
+            }
+        }
+    }
+    impl<W: Write> Write for Compressor<W> {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            match self {
+                Compressor::Lz4(encoder) => encoder.write(buf),
+                Compressor::Zstd(encoder) => encoder.write(buf),
+            }
+        }
+        fn flush(&mut self) -> io::Result<()> {
+            match self {
+                Compressor::Lz4(encoder) => encoder.flush(),
+                Compressor::Zstd(encoder) => encoder.flush(),
+            }
+        }
+    }
+    ///
+    /// Decompresses what's read from an underlying reader; for the
+    /// receiving side of a compressed REMOTE request.
+    ///
+    pub enum Decompressor<R: Read> {
+        Lz4(FrameDecoder<R>),
+        Zstd(zstd::Decoder<'static, BufReader<R>>),
+    }
+    impl<R: Read> Decompressor<R> {
+        pub fn new(codec: Codec, input: R) -> io::Result<Decompressor<R>> {
+            Ok(match codec {
+                Codec::Lz4 => Decompressor::Lz4(FrameDecoder::new(input)),
+                Codec::Zstd => Decompressor::Zstd(zstd::Decoder::new(input)?),
+            })
+        }
+    }
+    impl<R: Read> Read for Decompressor<R> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            match self {
+                Decompressor::Lz4(decoder) => decoder.read(buf),
+                Decompressor::Zstd(decoder) => decoder.read(buf),
+            }
+        }
+    }
+    #[cfg(test)]
+    mod compress_tests {
+        use super::*;
+
+        #[test]
+        fn codec_1() {
+            assert_eq!(Codec::Lz4, Codec::parse("lz4").unwrap());
+            assert_eq!(Codec::Zstd, Codec::parse("zstd").unwrap());
+            assert!(Codec::parse("gzip").is_err());
+            assert_eq!(Codec::Zstd, Codec::choose("gzip,zstd,lz4").unwrap());
+            assert!(Codec::choose("gzip,bzip2").is_err());
+            assert_eq!("zstd", Codec::Zstd.name());
+        }
+        #[test]
+        fn roundtrip_1() {
+            let data: Vec<u8> = (0..100000u32).map(|i| (i % 251) as u8).collect();
+            for codec in [Codec::Lz4, Codec::Zstd] {
+                let mut compressor = Compressor::new(codec, Vec::new()).unwrap();
+                compressor.write_all(&data).unwrap();
+                let compressed = compressor.finish().unwrap();
+                assert!(compressed.len() < data.len());
+
+                let mut result = Vec::new();
+                Decompressor::new(codec, compressed.as_slice())
+                    .unwrap()
+                    .read_to_end(&mut result)
+                    .unwrap();
+                assert_eq!(data, result);
+            }
+        }
+        #[test]
+        fn flush_1() {
+            // What's flushed can be decompressed before the stream ends:
+
+            for codec in [Codec::Lz4, Codec::Zstd] {
+                let mut compressor = Compressor::new(codec, Vec::new()).unwrap();
+                compressor.write_all(b"first").unwrap();
+                compressor.flush().unwrap();
+                let sent = match &compressor {
+                    Compressor::Lz4(encoder) => encoder.get_ref().clone(),
+                    Compressor::Zstd(encoder) => encoder.get_ref().clone(),
+                };
+                let mut result = [0u8; 5];
+                Decompressor::new(codec, sent.as_slice())
+                    .unwrap()
+                    .read_exact(&mut result)
+                    .unwrap();
+                assert_eq!(b"first", &result);
+            }
+        }
+    }
+}
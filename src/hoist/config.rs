@@ -0,0 +1,77 @@
+///
+/// The config module parses the hoist configuration file: the hoists the
+/// ring master sets up when it starts.  Each non blank line that does not
+/// start with # is a request as it would be sent to the ring master:
+///
+/// *  PUSH ringname host:port ?options? - push a ring (see the push
+///    module).
+/// *  PROXY host ringname ?options? - fill a proxy ring from a remote ring
+///    (see the proxy module).
+///
+pub mod config {
+    use crate::hoist::proxy::proxy::ProxyTarget;
+    use crate::hoist::push::push::PushTarget;
+
+    ///
+    /// The hoists in a configuration file.
+    ///
+    #[derive(Clone, Debug, Default, PartialEq)]
+    pub struct HoistConfig {
+        pub pushes: Vec<PushTarget>,
+        pub proxies: Vec<ProxyTarget>,
+    }
+    impl HoistConfig {
+        pub fn new() -> HoistConfig {
+            HoistConfig::default()
+        }
+        ///
+        /// Parse the text of a configuration file.  Err describes the
+        /// first bad line.
+        ///
+        pub fn parse(text: &str) -> Result<HoistConfig, String> {
+            let mut result = HoistConfig::new();
+            for (number, line) in text.lines().enumerate() {
+                let words = line.split_whitespace().map(String::from).collect::<Vec<String>>();
+                if words.is_empty() || words[0].starts_with('#') {
+                    continue;
+                }
+                let entry = match words[0].as_str() {
+                    "PUSH" => PushTarget::parse(&words[1..]).map(|target| result.pushes.push(target)),
+                    "PROXY" => ProxyTarget::parse(&words[1..]).map(|target| result.proxies.push(target)),
+                    _ => Err(format!("{} is not a hoist configuration request", words[0])),
+                };
+                entry.map_err(|e| format!("Line {}: {}", number + 1, e))?;
+            }
+            Ok(result)
+        }
+    }
+    #[cfg(test)]
+    mod config_tests {
+        use super::*;
+
+        #[test]
+        fn parse_1() {
+            let config = HoistConfig::parse(
+                "# Pushes to the event builder\n\
+                 \n\
+                 PUSH fox evb:5000\n\
+                 PUSH cat evb:5001 COMPRESS zstd RATE 10M\n\
+                 PROXY spdaq fox\n",
+            )
+            .unwrap();
+            assert_eq!(2, config.pushes.len());
+            assert_eq!("fox@spdaq", config.proxies[0].proxy_ring());
+            assert_eq!("fox", config.pushes[0].ring);
+            assert_eq!(5001, config.pushes[1].port);
+            assert_eq!(Some(10 * 1024 * 1024), config.pushes[1].options.rate);
+        }
+        #[test]
+        fn parse_2() {
+            let e = HoistConfig::parse("PUSH fox evb:5000\nPUSH fox\n").unwrap_err();
+            assert!(e.starts_with("Line 2:"), "{}", e);
+            assert!(HoistConfig::parse("PULL fox evb:5000").is_err());
+            assert!(HoistConfig::parse("PROXY spdaq").is_err());
+            assert_eq!(HoistConfig::new(), HoistConfig::parse("").unwrap());
+        }
+    }
+}
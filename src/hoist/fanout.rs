@@ -0,0 +1,343 @@
+///
+/// The fanout module lets many remote hoists of a ring share one
+/// hoister, and so one of the ring's consumer slots.  The hoister's ring
+/// items are published to a Fanout, which queues a copy of each for every
+/// subscriber.  The hoister is never held back by subscribers, so each
+/// subscriber's queue is its buffer:
+///
+/// *  Sample subscribers skip items that don't fit in their queue (or
+///    their rate).
+/// *  Block subscribers must get every item, so one that falls a full
+///    queue behind is cut off: its queue is closed and it should
+///    disconnect.  It can always reconnect.
+///
+/// HoistMux keeps the Fanout of each ring being hoisted.  The first
+/// subscriber to a ring must start its hoister; when the last one leaves
+/// the Fanout's stopper is called to stop the hoister.
+///
+pub mod fanout {
+    use crate::hoist::queue::queue::HoistQueue;
+    use crate::hoist::throttle::throttle::{HoistLimits, Policy, Throttle};
+    use std::collections::HashMap;
+    use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+    use std::sync::{Arc, Mutex};
+    use std::time::Instant;
+
+    pub type Stopper = Box<dyn FnOnce() + Send>;
+
+    ///
+    /// A subscriber's end of a Fanout: the queue to take items from and
+    /// what happened to it.
+    ///
+    pub struct Subscription {
+        pub id: u64,
+        pub queue: Arc<HoistQueue>,
+        pub cut: Arc<AtomicBool>,
+        pub skipped: Arc<AtomicU64>,
+    }
+
+    struct Subscriber {
+        id: u64,
+        queue: Arc<HoistQueue>,
+        cut: Arc<AtomicBool>,
+        skipped: Arc<AtomicU64>,
+        policy: Policy,
+        throttle: Option<Throttle>,
+    }
+    #[derive(Default)]
+    struct FanoutState {
+        subscribers: Vec<Subscriber>,
+        next_id: u64,
+        closed: bool,
+        stopper: Option<Stopper>,
+    }
+    ///
+    /// Distributes one ring's items to its subscribers.
+    ///
+    #[derive(Default)]
+    pub struct Fanout {
+        state: Mutex<FanoutState>,
+    }
+    impl Fanout {
+        pub fn new() -> Fanout {
+            Fanout::default()
+        }
+        ///
+        /// Add a subscriber with its limits and queue capacity in bytes.
+        /// Sample subscribers' rates are applied here; block subscribers
+        /// must meter themselves.  None if the Fanout is closed.
+        ///
+        pub fn subscribe(&self, limits: HoistLimits, capacity: usize, now: Instant) -> Option<Subscription> {
+            let mut state = self.state.lock().unwrap();
+            if state.closed {
+                return None;
+            }
+            let id = state.next_id;
+            state.next_id += 1;
+            let subscriber = Subscriber {
+                id,
+                queue: Arc::new(HoistQueue::new(capacity)),
+                cut: Arc::new(AtomicBool::new(false)),
+                skipped: Arc::new(AtomicU64::new(0)),
+                policy: limits.policy,
+                throttle: match limits.policy {
+                    Policy::Sample => limits.rate.map(|rate| Throttle::new(rate, now)),
+                    Policy::Block => None,
+                },
+            };
+            let result = Subscription {
+                id,
+                queue: Arc::clone(&subscriber.queue),
+                cut: Arc::clone(&subscriber.cut),
+                skipped: Arc::clone(&subscriber.skipped),
+            };
+            state.subscribers.push(subscriber);
+            Some(result)
+        }
+        ///
+        /// Remove a subscriber.  When the last one goes the Fanout is
+        /// closed and the stopper is called.
+        ///
+        pub fn unsubscribe(&self, id: u64) {
+            let stopper = {
+                let mut state = self.state.lock().unwrap();
+                state.subscribers.retain(|subscriber| {
+                    if subscriber.id == id {
+                        subscriber.queue.close();
+                    }
+                    subscriber.id != id
+                });
+                if state.subscribers.is_empty() {
+                    state.closed = true;
+                    state.stopper.take()
+                } else {
+                    None
+                }
+            };
+            if let Some(stop) = stopper {
+                stop();
+            }
+        }
+        ///
+        /// Queue a copy of an item for each subscriber that can take it.
+        /// Block subscribers that can't are cut off.
+        ///
+        pub fn publish(&self, item: &[u8], now: Instant) {
+            let mut state = self.state.lock().unwrap();
+            state.subscribers.retain_mut(|subscriber| {
+                let allowed = match subscriber.throttle.as_mut() {
+                    Some(throttle) => throttle.allow(item.len(), now),
+                    None => true,
+                };
+                if allowed && subscriber.queue.offer(item.to_vec()) {
+                    return true;
+                }
+                match subscriber.policy {
+                    Policy::Sample => {
+                        subscriber.skipped.fetch_add(1, Ordering::SeqCst);
+                        true
+                    }
+                    Policy::Block => {
+                        subscriber.cut.store(true, Ordering::SeqCst);
+                        subscriber.queue.close();
+                        false
+                    }
+                }
+            });
+        }
+        ///
+        /// Set what stops the hoister.  If everyone's already gone it's
+        /// called right away.
+        ///
+        pub fn set_stopper(&self, stopper: Stopper) {
+            let mut state = self.state.lock().unwrap();
+            if state.closed {
+                drop(state);
+                stopper();
+            } else {
+                state.stopper = Some(stopper);
+            }
+        }
+        ///
+        /// The hoister is done: close the subscribers' queues (they get
+        /// what's queued) and take no more subscribers.
+        ///
+        pub fn close(&self) {
+            let mut state = self.state.lock().unwrap();
+            state.closed = true;
+            state.stopper = None;
+            for subscriber in state.subscribers.drain(..) {
+                subscriber.queue.close();
+            }
+        }
+        pub fn is_closed(&self) -> bool {
+            self.state.lock().unwrap().closed
+        }
+        pub fn subscriber_count(&self) -> usize {
+            self.state.lock().unwrap().subscribers.len()
+        }
+    }
+    ///
+    /// The Fanouts of the rings being hoisted.
+    ///
+    #[derive(Default)]
+    pub struct HoistMux {
+        fanouts: Mutex<HashMap<String, Arc<Fanout>>>,
+    }
+    impl HoistMux {
+        pub fn new() -> HoistMux {
+            HoistMux::default()
+        }
+        ///
+        /// Subscribe to a ring's Fanout, making one if need be.  The bool
+        /// is true if the Fanout is new and the caller must start its
+        /// hoister (and give it a stopper).
+        ///
+        pub fn subscribe(&self, ring: &str, limits: HoistLimits, capacity: usize) -> (Arc<Fanout>, Subscription, bool) {
+            let now = Instant::now();
+            let mut fanouts = self.fanouts.lock().unwrap();
+            if let Some(fanout) = fanouts.get(ring) {
+                if let Some(subscription) = fanout.subscribe(limits, capacity, now) {
+                    return (Arc::clone(fanout), subscription, false);
+                }
+            }
+            let fanout = Arc::new(Fanout::new());
+            let subscription = fanout.subscribe(limits, capacity, now).unwrap();
+            fanouts.insert(String::from(ring), Arc::clone(&fanout));
+            (fanout, subscription, true)
+        }
+        ///
+        /// Remove a subscriber; a ring with no subscribers left is
+        /// forgotten.
+        ///
+        pub fn unsubscribe(&self, ring: &str, fanout: &Arc<Fanout>, id: u64) {
+            fanout.unsubscribe(id);
+            if fanout.is_closed() {
+                self.forget(ring, fanout);
+            }
+        }
+        ///
+        /// A ring's hoister is done: close its Fanout and forget it.
+        ///
+        pub fn retire(&self, ring: &str, fanout: &Arc<Fanout>) {
+            fanout.close();
+            self.forget(ring, fanout);
+        }
+        ///
+        /// Number of subscribers to a ring.
+        ///
+        pub fn subscriber_count(&self, ring: &str) -> usize {
+            self.fanouts
+                .lock()
+                .unwrap()
+                .get(ring)
+                .map_or(0, |fanout| fanout.subscriber_count())
+        }
+        // Forget a ring's Fanout if it's still this one.
+
+        fn forget(&self, ring: &str, fanout: &Arc<Fanout>) {
+            let mut fanouts = self.fanouts.lock().unwrap();
+            if fanouts.get(ring).is_some_and(|current| Arc::ptr_eq(current, fanout)) {
+                fanouts.remove(ring);
+            }
+        }
+    }
+    #[cfg(test)]
+    mod fanout_tests {
+        use super::*;
+
+        fn limits(policy: Policy) -> HoistLimits {
+            HoistLimits { rate: None, policy }
+        }
+        #[test]
+        fn publish_1() {
+            let fanout = Fanout::new();
+            let now = Instant::now();
+            let a = fanout.subscribe(limits(Policy::Block), 100, now).unwrap();
+            let b = fanout.subscribe(limits(Policy::Sample), 100, now).unwrap();
+            fanout.publish(&[1; 60], now);
+            assert_eq!(Some(vec![1; 60]), a.queue.try_take());
+            assert_eq!(Some(vec![1; 60]), b.queue.try_take());
+
+            // Fill them up; the sampler skips, the blocker is cut off:
+
+            fanout.publish(&[2; 60], now);
+            fanout.publish(&[3; 60], now);
+            assert_eq!(1, b.skipped.load(Ordering::SeqCst));
+            assert!(!b.cut.load(Ordering::SeqCst));
+            assert!(a.cut.load(Ordering::SeqCst));
+            assert_eq!(1, fanout.subscriber_count());
+
+            // The blocker gets what was queued before it was cut:
+
+            assert_eq!(Some(vec![2; 60]), a.queue.take());
+            assert!(a.queue.take().is_none());
+        }
+        #[test]
+        fn publish_2() {
+            // Sample subscribers' rates are applied:
+
+            let fanout = Fanout::new();
+            let now = Instant::now();
+            let subscription = fanout
+                .subscribe(HoistLimits { rate: Some(100), policy: Policy::Sample }, 1000, now)
+                .unwrap();
+            fanout.publish(&[1; 80], now);
+            fanout.publish(&[2; 80], now);
+            assert_eq!(Some(vec![1; 80]), subscription.queue.try_take());
+            assert!(subscription.queue.try_take().is_none());
+            assert_eq!(1, subscription.skipped.load(Ordering::SeqCst));
+        }
+        #[test]
+        fn stop_1() {
+            let fanout = Fanout::new();
+            let now = Instant::now();
+            let stopped = Arc::new(AtomicBool::new(false));
+            let a = fanout.subscribe(limits(Policy::Block), 100, now).unwrap();
+            let b = fanout.subscribe(limits(Policy::Block), 100, now).unwrap();
+            {
+                let stopped = Arc::clone(&stopped);
+                fanout.set_stopper(Box::new(move || stopped.store(true, Ordering::SeqCst)));
+            }
+            fanout.unsubscribe(a.id);
+            assert!(!stopped.load(Ordering::SeqCst));
+            fanout.unsubscribe(b.id);
+            assert!(stopped.load(Ordering::SeqCst));
+            assert!(fanout.is_closed());
+            assert!(fanout.subscribe(limits(Policy::Block), 100, now).is_none());
+
+            // Stoppers set after everyone's gone are called right away:
+
+            let stopped = Arc::new(AtomicBool::new(false));
+            let flag = Arc::clone(&stopped);
+            fanout.set_stopper(Box::new(move || flag.store(true, Ordering::SeqCst)));
+            assert!(stopped.load(Ordering::SeqCst));
+        }
+        #[test]
+        fn mux_1() {
+            let mux = HoistMux::new();
+            let (fanout, a, new) = mux.subscribe("fox", limits(Policy::Block), 100);
+            assert!(new);
+            let (same, b, new) = mux.subscribe("fox", limits(Policy::Sample), 100);
+            assert!(!new);
+            assert!(Arc::ptr_eq(&fanout, &same));
+            assert_eq!(2, mux.subscriber_count("fox"));
+
+            mux.unsubscribe("fox", &fanout, a.id);
+            mux.unsubscribe("fox", &fanout, b.id);
+            assert_eq!(0, mux.subscriber_count("fox"));
+
+            // Next time round there's a new Fanout:
+
+            let (other, c, new) = mux.subscribe("fox", limits(Policy::Block), 100);
+            assert!(new);
+            assert!(!Arc::ptr_eq(&fanout, &other));
+
+            // Retiring closes subscribers' queues:
+
+            mux.retire("fox", &other);
+            assert!(c.queue.take().is_none());
+            assert_eq!(0, mux.subscriber_count("fox"));
+        }
+    }
+}
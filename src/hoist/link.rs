@@ -0,0 +1,395 @@
+///
+/// The link module keeps the state of a connection the ring master makes
+/// to another host and keeps making: a push of one of our rings or a
+/// proxy ring filled from a remote ring master.  The thread doing the
+/// work updates the Link as it connects, moves data and fails; requests
+/// read its status or stop it.
+///
+/// *  LinkState - where the link is at.
+/// *  LinkStatus - the state and its history, for status requests.
+/// *  Link - the status plus what's needed to stop the link.
+/// *  LinkWriter and LinkReader count the data through a link.
+/// *  Backoff spaces out reconnection attempts.
+/// *  set_keepalive has TCP notice when the other end of a quiet
+///    connection has gone away.
+///
+pub mod link {
+    use crate::tcllist::TclList;
+    use serde_json::{Map, Value};
+    use std::io::{self, Read, Write};
+    use std::net::{Shutdown, TcpStream};
+    use std::os::unix::io::AsRawFd;
+    use std::sync::{Condvar, Mutex};
+    use std::time::Duration;
+
+    ///
+    /// Where a link is at:
+    ///
+    /// *  Connecting - trying to connect to the remote.
+    /// *  Connected - moving data.
+    /// *  Waiting - to try again after failing to connect or losing the
+    ///    connection.
+    ///
+    #[derive(Copy, Clone, Debug, PartialEq)]
+    pub enum LinkState {
+        Connecting,
+        Connected,
+        Waiting,
+    }
+    impl LinkState {
+        pub fn name(&self) -> &'static str {
+            match self {
+                LinkState::Connecting => "connecting",
+                LinkState::Connected => "connected",
+                LinkState::Waiting => "waiting",
+            }
+        }
+    }
+    ///
+    /// The status of a link:
+    ///
+    /// *  state - see LinkState.
+    /// *  since - when it last connected or lost its connection (or was
+    ///    started) in seconds since the epoch.  Retries don't change it.
+    /// *  connects - the number of times it's connected.
+    /// *  bytes - bytes moved over all connections.
+    /// *  error - why it last failed if it has.
+    ///
+    #[derive(Clone, Debug, PartialEq)]
+    pub struct LinkStatus {
+        pub state: LinkState,
+        pub since: u64,
+        pub connects: u64,
+        pub bytes: u64,
+        pub error: Option<String>,
+    }
+    impl LinkStatus {
+        ///
+        /// Add the status to a Tcl list of key value pairs.  A link that
+        /// has not failed has an empty error.
+        ///
+        pub fn add_tcl(&self, list: &mut TclList) {
+            list.add_element("state")
+                .add_element(self.state.name())
+                .add_element("since")
+                .add_element(&self.since.to_string())
+                .add_element("connects")
+                .add_element(&self.connects.to_string())
+                .add_element("bytes")
+                .add_element(&self.bytes.to_string())
+                .add_element("error")
+                .add_quoted_element(self.error.as_deref().unwrap_or(""));
+        }
+        ///
+        /// Add the status to a JSON object with the same keys as add_tcl.
+        /// A link that has not failed has a null error.
+        ///
+        pub fn add_json(&self, object: &mut Map<String, Value>) {
+            object.insert(String::from("state"), Value::from(self.state.name()));
+            object.insert(String::from("since"), Value::from(self.since));
+            object.insert(String::from("connects"), Value::from(self.connects));
+            object.insert(String::from("bytes"), Value::from(self.bytes));
+            object.insert(String::from("error"), Value::from(self.error.clone()));
+        }
+    }
+    ///
+    /// A link's status and the means to stop it.  Share it with an Arc
+    /// (usually inside whatever owns it).
+    ///
+    pub struct Link {
+        status: Mutex<LinkStatus>,
+        stopped: Mutex<bool>,
+        wake: Condvar,
+        connection: Mutex<Option<TcpStream>>,
+    }
+    impl Link {
+        pub fn new(now: u64) -> Link {
+            Link {
+                status: Mutex::new(LinkStatus {
+                    state: LinkState::Connecting,
+                    since: now,
+                    connects: 0,
+                    bytes: 0,
+                    error: None,
+                }),
+                stopped: Mutex::new(false),
+                wake: Condvar::new(),
+                connection: Mutex::new(None),
+            }
+        }
+        pub fn status(&self) -> LinkStatus {
+            self.status.lock().unwrap().clone()
+        }
+        pub fn connecting(&self, now: u64) {
+            self.set_state(LinkState::Connecting, now);
+        }
+        ///
+        /// We're connected.  connection is a handle on the connection
+        /// stop can shut down.  If we were stopped while connecting it's
+        /// shut down now.
+        ///
+        pub fn connected(&self, connection: Option<TcpStream>, now: u64) {
+            *self.connection.lock().unwrap() = connection;
+            if self.is_stopped() {
+                if let Some(connection) = self.connection.lock().unwrap().take() {
+                    let _ = connection.shutdown(Shutdown::Both);
+                }
+            }
+            let mut status = self.status.lock().unwrap();
+            status.connects += 1;
+            status.error = None;
+            status.state = LinkState::Connected;
+            status.since = now;
+        }
+        ///
+        /// We failed and will try again.
+        ///
+        pub fn waiting(&self, error: &str, now: u64) {
+            *self.connection.lock().unwrap() = None;
+            self.status.lock().unwrap().error = Some(String::from(error));
+            self.set_state(LinkState::Waiting, now);
+        }
+        pub fn add_bytes(&self, bytes: usize) {
+            self.status.lock().unwrap().bytes += bytes as u64;
+        }
+        ///
+        /// Stop the link: wake its thread if it's waiting and shut down its
+        /// connection if it has one.
+        ///
+        pub fn stop(&self) {
+            *self.stopped.lock().unwrap() = true;
+            self.wake.notify_all();
+            if let Some(connection) = self.connection.lock().unwrap().take() {
+                let _ = connection.shutdown(Shutdown::Both);
+            }
+        }
+        pub fn is_stopped(&self) -> bool {
+            *self.stopped.lock().unwrap()
+        }
+        ///
+        /// Wait a while (e.g. before reconnecting).  true if we were
+        /// stopped.
+        ///
+        pub fn pause(&self, delay: Duration) -> bool {
+            let stopped = self.stopped.lock().unwrap();
+            let (stopped, _) = self.wake.wait_timeout_while(stopped, delay, |stopped| !*stopped).unwrap();
+            *stopped
+        }
+        fn set_state(&self, state: LinkState, now: u64) {
+            let mut status = self.status.lock().unwrap();
+            if (status.state == LinkState::Connected) != (state == LinkState::Connected) {
+                status.since = now;
+            }
+            status.state = state;
+        }
+    }
+    ///
+    /// A writer that counts what it writes into a Link's bytes.
+    ///
+    pub struct LinkWriter<'a, W: Write> {
+        output: W,
+        link: &'a Link,
+    }
+    impl<'a, W: Write> LinkWriter<'a, W> {
+        pub fn new(output: W, link: &'a Link) -> LinkWriter<'a, W> {
+            LinkWriter { output, link }
+        }
+    }
+    impl<W: Write> Write for LinkWriter<'_, W> {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            let n = self.output.write(buf)?;
+            self.link.add_bytes(n);
+            Ok(n)
+        }
+        fn flush(&mut self) -> io::Result<()> {
+            self.output.flush()
+        }
+    }
+    ///
+    /// A reader that counts what it reads into a Link's bytes.
+    ///
+    pub struct LinkReader<'a, R: Read> {
+        input: R,
+        link: &'a Link,
+    }
+    impl<'a, R: Read> LinkReader<'a, R> {
+        pub fn new(input: R, link: &'a Link) -> LinkReader<'a, R> {
+            LinkReader { input, link }
+        }
+    }
+    impl<R: Read> Read for LinkReader<'_, R> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let n = self.input.read(buf)?;
+            self.link.add_bytes(n);
+            Ok(n)
+        }
+    }
+    ///
+    /// Exponential backoff between attempts: min, doubling up to max.
+    ///
+    pub struct Backoff {
+        min: Duration,
+        max: Duration,
+        next: Duration,
+    }
+    impl Backoff {
+        pub fn new(min: Duration, max: Duration) -> Backoff {
+            Backoff { min, max, next: min }
+        }
+        ///
+        /// How long to wait before the next attempt.
+        ///
+        pub fn next_delay(&mut self) -> Duration {
+            let result = self.next;
+            self.next = (self.next * 2).min(self.max);
+            result
+        }
+        ///
+        /// An attempt worked, start over.
+        ///
+        pub fn reset(&mut self) {
+            self.next = self.min;
+        }
+    }
+    ///
+    /// Turn on TCP keepalive for a connection: after idle without
+    /// traffic, probe every interval and give up on the connection after
+    /// probes unanswered probes.  Reads then fail rather than waiting
+    /// forever on a remote that's gone.
+    ///
+    pub fn set_keepalive(connection: &TcpStream, idle: Duration, interval: Duration, probes: u32) -> io::Result<()> {
+        let fd = connection.as_raw_fd();
+        let seconds = |d: Duration| d.as_secs().clamp(1, libc::c_int::MAX as u64) as libc::c_int;
+        set_option(fd, libc::SOL_SOCKET, libc::SO_KEEPALIVE, 1)?;
+        set_option(fd, libc::IPPROTO_TCP, libc::TCP_KEEPIDLE, seconds(idle))?;
+        set_option(fd, libc::IPPROTO_TCP, libc::TCP_KEEPINTVL, seconds(interval))?;
+        set_option(fd, libc::IPPROTO_TCP, libc::TCP_KEEPCNT, probes.min(libc::c_int::MAX as u32) as libc::c_int)
+    }
+    // Set an integer socket option.
+
+    fn set_option(fd: libc::c_int, level: libc::c_int, name: libc::c_int, value: libc::c_int) -> io::Result<()> {
+        // Safe: value outlives the call and its size is what we pass.
+
+        let status = unsafe {
+            libc::setsockopt(
+                fd,
+                level,
+                name,
+                &value as *const libc::c_int as *const libc::c_void,
+                std::mem::size_of::<libc::c_int>() as libc::socklen_t,
+            )
+        };
+        if status == 0 {
+            Ok(())
+        } else {
+            Err(io::Error::last_os_error())
+        }
+    }
+    #[cfg(test)]
+    mod link_tests {
+        use super::*;
+        use std::net::TcpListener;
+        use std::sync::Arc;
+        use std::thread;
+        use std::time::Instant;
+
+        #[test]
+        fn status_1() {
+            let link = Link::new(100);
+            assert_eq!(LinkState::Connecting, link.status().state);
+            link.connected(None, 110);
+            link.add_bytes(1000);
+            link.waiting("Connection reset", 120);
+            link.connecting(130);
+            assert_eq!(120, link.status().since);
+            link.connected(None, 140);
+
+            let status = link.status();
+            assert_eq!(LinkState::Connected, status.state);
+            assert_eq!(140, status.since);
+            assert_eq!(2, status.connects);
+            assert_eq!(1000, status.bytes);
+            assert!(status.error.is_none());
+
+            let mut list = TclList::new();
+            status.add_tcl(&mut list);
+            assert_eq!("{state connected since 140 connects 2 bytes 1000 error {} }", list.to_string());
+            link.waiting("Connection reset", 150);
+            let mut object = Map::new();
+            link.status().add_json(&mut object);
+            assert_eq!("waiting", object["state"]);
+            assert_eq!("Connection reset", object["error"]);
+        }
+        #[test]
+        fn stop_1() {
+            // Stopping wakes a paused link and shuts down its connection:
+
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            let connection = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+            let (mut remote, _) = listener.accept().unwrap();
+
+            let link = Arc::new(Link::new(100));
+            link.connected(Some(connection.try_clone().unwrap()), 100);
+            assert!(!link.pause(Duration::from_millis(1)));
+            let stopper = {
+                let link = Arc::clone(&link);
+                thread::spawn(move || {
+                    thread::sleep(Duration::from_millis(50));
+                    link.stop();
+                })
+            };
+            let start = Instant::now();
+            assert!(link.pause(Duration::from_secs(10)));
+            assert!(start.elapsed() < Duration::from_secs(10));
+            stopper.join().unwrap();
+            assert!(link.is_stopped());
+            let mut buffer = [0u8; 1];
+            assert_eq!(0, remote.read(&mut buffer).unwrap());
+        }
+        #[test]
+        fn count_1() {
+            let link = Link::new(100);
+            let mut writer = LinkWriter::new(Vec::new(), &link);
+            writer.write_all(b"hello").unwrap();
+            let mut reader = LinkReader::new(&b"world!"[..], &link);
+            let mut buffer = Vec::new();
+            reader.read_to_end(&mut buffer).unwrap();
+            assert_eq!(11, link.status().bytes);
+        }
+        #[test]
+        fn keepalive_1() {
+            let get = |connection: &TcpStream, level: libc::c_int, name: libc::c_int| {
+                let mut value: libc::c_int = 0;
+                let mut size = std::mem::size_of::<libc::c_int>() as libc::socklen_t;
+                let status = unsafe {
+                    libc::getsockopt(
+                        connection.as_raw_fd(),
+                        level,
+                        name,
+                        &mut value as *mut libc::c_int as *mut libc::c_void,
+                        &mut size,
+                    )
+                };
+                assert_eq!(0, status);
+                value
+            };
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            let connection = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+            assert_eq!(0, get(&connection, libc::SOL_SOCKET, libc::SO_KEEPALIVE));
+
+            set_keepalive(&connection, Duration::from_secs(30), Duration::from_secs(10), 3).unwrap();
+            assert_eq!(1, get(&connection, libc::SOL_SOCKET, libc::SO_KEEPALIVE));
+            assert_eq!(30, get(&connection, libc::IPPROTO_TCP, libc::TCP_KEEPIDLE));
+            assert_eq!(10, get(&connection, libc::IPPROTO_TCP, libc::TCP_KEEPINTVL));
+            assert_eq!(3, get(&connection, libc::IPPROTO_TCP, libc::TCP_KEEPCNT));
+        }
+        #[test]
+        fn backoff_1() {
+            let mut backoff = Backoff::new(Duration::from_secs(1), Duration::from_secs(5));
+            let delays = (0..4).map(|_| backoff.next_delay().as_secs()).collect::<Vec<u64>>();
+            assert_eq!(vec![1, 2, 4, 5], delays);
+            backoff.reset();
+            assert_eq!(Duration::from_secs(1), backoff.next_delay());
+        }
+    }
+}
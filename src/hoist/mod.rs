@@ -0,0 +1,41 @@
+//! The *hoist* module supports REMOTE requests, which hoist the data in
+//! one of our rings to a remote system:
+//!
+//! *  Parsing the options a REMOTE request can give after the ring name.
+//! *  TLS encryption of the hoisted data using rustls.  Both ends are
+//!    here: the ring master's side and the receiving side.
+//! *  Compression of the hoisted data (lz4 or zstd) and its decompression
+//!    on the receiving side.
+//! *  Limiting a hoist's bandwidth and, for monitoring, sampling the data
+//!    rather than holding back the ring when the remote can't keep up.
+//! *  Splitting hoisted data into ring items and queueing them.
+//! *  Fanning one hoister's data out to many remotes so they share one
+//!    consumer slot.
+//! *  Pushing a ring's data out to a remote host rather than waiting to be
+//!    asked for it.
+//! *  Proxy rings: local rings filled from a remote ring master's ring
+//!    with REMOTE.
+//! *  Tracking the connections the ring master makes (and remakes) to
+//!    other hosts.
+//! *  Parsing the file of hoists to set up at startup.
+//!
+pub mod compress;
+pub mod config;
+pub mod fanout;
+pub mod link;
+pub mod proxy;
+pub mod push;
+pub mod queue;
+pub mod remote;
+pub mod throttle;
+pub mod tls;
+pub use self::compress::compress::*;
+pub use self::config::config::*;
+pub use self::fanout::fanout::*;
+pub use self::link::link::*;
+pub use self::proxy::proxy::*;
+pub use self::push::push::*;
+pub use self::queue::queue::*;
+pub use self::remote::remote::*;
+pub use self::throttle::throttle::*;
+pub use self::tls::tls::*;
@@ -0,0 +1,350 @@
+///
+/// The proxy module supports proxy rings: local rings the ring master
+/// fills with the data of a ring on another host.  This is the receiving
+/// side of REMOTE.  The ring master looks up the remote ring master with
+/// the remote host's port manager, asks it for the ring with REMOTE and
+/// puts what comes back into the proxy ring as its producer.
+///
+/// *  ProxyTarget says what to proxy and how: the host (and its port
+///    manager's port if that's not ours), the ring, the remote ring
+///    master's service name and REMOTE's options.
+/// *  Proxy is a ProxyTarget and its Link (see the link module).
+/// *  ProxyTable holds the proxies by proxy ring.
+/// *  find_service asks a port manager where a service is.
+/// *  RemoteReply parses the ring master's replies to REMOTE.
+///
+/// Proxy rings are named ring@host as NSCLDAQ names them.
+///
+pub mod proxy {
+    use crate::hoist::compress::compress::Codec;
+    use crate::hoist::link::link::{Link, LinkStatus};
+    use crate::hoist::push::push::parse_address;
+    use crate::hoist::remote::remote::RemoteOptions;
+    use crate::tcllist::TclList;
+    use serde_json::{Map, Value};
+    use std::collections::BTreeMap;
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::{TcpStream, ToSocketAddrs};
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    ///
+    /// What to proxy.
+    ///
+    #[derive(Clone, Debug, PartialEq)]
+    pub struct ProxyTarget {
+        pub host: String,
+        pub portman: Option<u16>,
+        pub ring: String,
+        pub service: Option<String>,
+        pub options: RemoteOptions,
+    }
+    impl ProxyTarget {
+        ///
+        /// Parse host ringname ?SERVICE name? ?options? as given to PROXY.
+        /// The host can be host:port if its port manager is not on the
+        /// same port as ours (bracket IPv6 addresses then: \[::1\]:30000).
+        /// The other options are REMOTE's.
+        ///
+        pub fn parse(words: &[String]) -> Result<ProxyTarget, String> {
+            if words.len() < 2 {
+                return Err(String::from("PROXY needs a host and a ring name"));
+            }
+            let (host, portman) = if words[0].starts_with('[') || words[0].matches(':').count() == 1 {
+                let (host, port) = parse_address(&words[0])?;
+                (host, Some(port))
+            } else {
+                (words[0].clone(), None)
+            };
+            if host.is_empty() || words[1].is_empty() || words[1].contains('/') {
+                return Err(format!("Invalid proxy of {} from {}", words[1], words[0]));
+            }
+            let mut service = None;
+            let mut rest = &words[2..];
+            if rest.first().map(|w| w.as_str()) == Some("SERVICE") {
+                match rest.get(1) {
+                    Some(name) => service = Some(name.clone()),
+                    None => return Err(String::from("SERVICE needs the remote ring master's service name")),
+                }
+                rest = &rest[2..];
+            }
+            Ok(ProxyTarget {
+                host,
+                portman,
+                ring: words[1].clone(),
+                service,
+                options: RemoteOptions::parse(rest)?,
+            })
+        }
+        ///
+        /// The name of the local proxy ring: ring@host.
+        ///
+        pub fn proxy_ring(&self) -> String {
+            format!("{}@{}", self.ring, self.host)
+        }
+        ///
+        /// The REMOTE request that asks for the ring's data.
+        ///
+        pub fn remote_request(&self) -> String {
+            let mut words = vec![String::from("REMOTE"), self.ring.clone()];
+            words.extend(self.options.to_words());
+            words.join(" ")
+        }
+    }
+    ///
+    /// The status of a proxy: the proxy ring, the host and ring it
+    /// proxies and how its link is doing.
+    ///
+    #[derive(Clone, Debug, PartialEq)]
+    pub struct ProxyStatus {
+        pub ring: String,
+        pub host: String,
+        pub remote: String,
+        pub link: LinkStatus,
+    }
+    impl ProxyStatus {
+        ///
+        /// Render as a Tcl list of key value pairs: ring, host, remote and
+        /// those of LinkStatus.
+        ///
+        pub fn to_tcl(&self) -> TclList {
+            let mut result = TclList::new();
+            result
+                .add_element("ring")
+                .add_quoted_element(&self.ring)
+                .add_element("host")
+                .add_quoted_element(&self.host)
+                .add_element("remote")
+                .add_quoted_element(&self.remote);
+            self.link.add_tcl(&mut result);
+            result
+        }
+        ///
+        /// Render as a JSON object with the same keys as to_tcl.
+        ///
+        pub fn to_json(&self) -> Value {
+            let mut result = Map::new();
+            result.insert(String::from("ring"), Value::from(self.ring.as_str()));
+            result.insert(String::from("host"), Value::from(self.host.as_str()));
+            result.insert(String::from("remote"), Value::from(self.remote.as_str()));
+            self.link.add_json(&mut result);
+            Value::Object(result)
+        }
+    }
+    ///
+    /// A proxy in progress.
+    ///
+    pub struct Proxy {
+        pub target: ProxyTarget,
+        pub link: Link,
+    }
+    impl Proxy {
+        pub fn new(target: ProxyTarget, now: u64) -> Proxy {
+            Proxy {
+                target,
+                link: Link::new(now),
+            }
+        }
+        pub fn status(&self) -> ProxyStatus {
+            ProxyStatus {
+                ring: self.target.proxy_ring(),
+                host: self.target.host.clone(),
+                remote: self.target.ring.clone(),
+                link: self.link.status(),
+            }
+        }
+    }
+    ///
+    /// The proxies by proxy ring.  A ring has one producer so it can be
+    /// the proxy of only one remote ring.
+    ///
+    #[derive(Default)]
+    pub struct ProxyTable {
+        proxies: Mutex<BTreeMap<String, Arc<Proxy>>>,
+    }
+    impl ProxyTable {
+        pub fn new() -> ProxyTable {
+            ProxyTable::default()
+        }
+        ///
+        /// Add a proxy.  Err if its proxy ring is already being filled.
+        ///
+        pub fn add(&self, target: ProxyTarget, now: u64) -> Result<Arc<Proxy>, String> {
+            let ring = target.proxy_ring();
+            let mut proxies = self.proxies.lock().unwrap();
+            if proxies.contains_key(&ring) {
+                return Err(format!("{} is already a proxy ring", ring));
+            }
+            let proxy = Arc::new(Proxy::new(target, now));
+            proxies.insert(ring, Arc::clone(&proxy));
+            Ok(proxy)
+        }
+        ///
+        /// Remove and stop the proxy filling a ring.  Err if there's no
+        /// such proxy.
+        ///
+        pub fn remove(&self, ring: &str) -> Result<(), String> {
+            match self.proxies.lock().unwrap().remove(ring) {
+                Some(proxy) => {
+                    proxy.link.stop();
+                    Ok(())
+                }
+                None => Err(format!("{} is not a proxy ring", ring)),
+            }
+        }
+        ///
+        /// Whether a proxy is filling a ring.
+        ///
+        pub fn contains(&self, ring: &str) -> bool {
+            self.proxies.lock().unwrap().contains_key(ring)
+        }
+        ///
+        /// The status of each proxy, by proxy ring.
+        ///
+        pub fn list(&self) -> Vec<ProxyStatus> {
+            self.proxies.lock().unwrap().values().map(|proxy| proxy.status()).collect()
+        }
+    }
+    ///
+    /// Ask the port manager on host:port for the port of a service.  The
+    /// port manager replies to LIST with OK and the number of services,
+    /// then a port service user line for each.  If several users advertise
+    /// the service the first is used.
+    ///
+    pub fn find_service(host: &str, port: u16, service: &str, timeout: Duration) -> Result<u16, String> {
+        let address = (host, port)
+            .to_socket_addrs()
+            .map_err(|e| format!("Unable to look up {}: {}", host, e))?
+            .next()
+            .ok_or_else(|| format!("{} has no addresses", host))?;
+        let mut connection = TcpStream::connect_timeout(&address, timeout)
+            .map_err(|e| format!("Unable to reach the port manager on {}: {}", host, e))?;
+        let _ = connection.set_read_timeout(Some(timeout));
+        let failed = |e: std::io::Error| format!("Port manager on {} failed: {}", host, e);
+        connection.write_all(b"LIST\n").map_err(failed)?;
+        let mut reader = BufReader::new(connection);
+        let mut line = String::new();
+        reader.read_line(&mut line).map_err(failed)?;
+        let count = match line.split_whitespace().collect::<Vec<&str>>()[..] {
+            ["OK", count] => count.parse::<usize>().ok(),
+            _ => None,
+        }
+        .ok_or_else(|| format!("Port manager on {} gave an unexpected reply: {}", host, line.trim()))?;
+        for _ in 0..count {
+            line.clear();
+            reader.read_line(&mut line).map_err(failed)?;
+            if let [port, name, ..] = line.split_whitespace().collect::<Vec<&str>>()[..] {
+                if name == service {
+                    return port
+                        .parse::<u16>()
+                        .map_err(|_| format!("Port manager on {} gave an invalid port {}", host, port));
+                }
+            }
+        }
+        Err(format!("No {} is advertised on {}", service, host))
+    }
+    ///
+    /// The ring master's replies to REMOTE.  FAIL replies are Errs.
+    ///
+    #[derive(Copy, Clone, Debug, PartialEq)]
+    pub enum RemoteReply {
+        Tls,
+        BinaryFollows(Option<Codec>),
+    }
+    impl RemoteReply {
+        pub fn parse(line: &str) -> Result<RemoteReply, String> {
+            let words = line.split_whitespace().collect::<Vec<&str>>();
+            match words[..] {
+                ["OK", "TLS"] => Ok(RemoteReply::Tls),
+                ["OK", "BINARY", "FOLLOWS"] => Ok(RemoteReply::BinaryFollows(None)),
+                ["OK", "BINARY", "FOLLOWS", "COMPRESS", codec] => {
+                    Ok(RemoteReply::BinaryFollows(Some(Codec::parse(codec)?)))
+                }
+                ["FAIL", ..] => Err(format!("REMOTE failed: {}", words[1..].join(" "))),
+                _ => Err(format!("Unexpected reply to REMOTE: {}", line.trim())),
+            }
+        }
+    }
+    #[cfg(test)]
+    mod proxy_tests {
+        use super::*;
+        use std::net::TcpListener;
+        use std::thread;
+
+        fn words(text: &str) -> Vec<String> {
+            text.split_whitespace().map(String::from).collect()
+        }
+        #[test]
+        fn parse_1() {
+            let target = ProxyTarget::parse(&words("spdaq fox")).unwrap();
+            assert_eq!("spdaq", target.host);
+            assert_eq!(None, target.portman);
+            assert_eq!("fox@spdaq", target.proxy_ring());
+            assert_eq!("REMOTE fox", target.remote_request());
+
+            let target = ProxyTarget::parse(&words("spdaq:30001 fox SERVICE RingMaster_daq COMPRESS zstd")).unwrap();
+            assert_eq!(Some(30001), target.portman);
+            assert_eq!(Some(String::from("RingMaster_daq")), target.service);
+            assert_eq!("fox@spdaq", target.proxy_ring());
+            assert_eq!("REMOTE fox COMPRESS zstd", target.remote_request());
+
+            assert_eq!("::1", ProxyTarget::parse(&words("::1 fox")).unwrap().host);
+            assert_eq!(Some(30001), ProxyTarget::parse(&words("[::1]:30001 fox")).unwrap().portman);
+            assert!(ProxyTarget::parse(&words("spdaq")).is_err());
+            assert!(ProxyTarget::parse(&words("spdaq ../fox")).is_err());
+            assert!(ProxyTarget::parse(&words("spdaq fox SERVICE")).is_err());
+            assert!(ProxyTarget::parse(&words("spdaq fox JUNK")).is_err());
+        }
+        #[test]
+        fn table_1() {
+            let table = ProxyTable::new();
+            let proxy = table.add(ProxyTarget::parse(&words("spdaq fox")).unwrap(), 100).unwrap();
+            assert!(table.add(ProxyTarget::parse(&words("spdaq:30001 fox")).unwrap(), 100).is_err());
+            assert!(table.contains("fox@spdaq"));
+            assert!(!table.contains("fox"));
+            proxy.link.connected(None, 110);
+            assert_eq!(
+                "{ring fox@spdaq host spdaq remote fox state connected since 110 connects 1 bytes 0 error {} }",
+                table.list()[0].to_tcl().to_string()
+            );
+            assert_eq!("fox", table.list()[0].to_json()["remote"]);
+            assert!(table.remove("fox@other").is_err());
+            table.remove("fox@spdaq").unwrap();
+            assert!(proxy.link.is_stopped());
+            assert!(table.list().is_empty());
+            assert!(!table.contains("fox@spdaq"));
+        }
+        #[test]
+        fn find_1() {
+            // A port manager with two services:
+
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            let port = listener.local_addr().unwrap().port();
+            let portman = thread::spawn(move || {
+                for _ in 0..2 {
+                    let (mut client, _) = listener.accept().unwrap();
+                    let mut line = String::new();
+                    BufReader::new(client.try_clone().unwrap()).read_line(&mut line).unwrap();
+                    assert_eq!("LIST\n", line);
+                    client.write_all(b"OK 2\n31000 Other fox\n31001 RingMaster fox\n").unwrap();
+                }
+            });
+            let timeout = Duration::from_secs(5);
+            assert_eq!(Ok(31001), find_service("127.0.0.1", port, "RingMaster", timeout));
+            assert!(find_service("127.0.0.1", port, "Missing", timeout).is_err());
+            portman.join().unwrap();
+        }
+        #[test]
+        fn reply_1() {
+            assert_eq!(Ok(RemoteReply::Tls), RemoteReply::parse("OK TLS\r\n"));
+            assert_eq!(Ok(RemoteReply::BinaryFollows(None)), RemoteReply::parse("OK BINARY FOLLOWS\r\n"));
+            assert_eq!(
+                Ok(RemoteReply::BinaryFollows(Some(Codec::Zstd))),
+                RemoteReply::parse("OK BINARY FOLLOWS COMPRESS zstd\r\n")
+            );
+            let e = RemoteReply::parse("FAIL fox is not in the ring master's inventory\r\n").unwrap_err();
+            assert!(e.ends_with("fox is not in the ring master's inventory"), "{}", e);
+            assert!(RemoteReply::parse("").is_err());
+        }
+    }
+}
@@ -0,0 +1,241 @@
+///
+/// The push module keeps track of outbound hoists: rather than waiting
+/// for a remote to ask for a ring with REMOTE, the ring master connects
+/// to a remote host and port and pushes the ring's data there.  The data
+/// are the same as what follows the OK BINARY FOLLOWS reply to REMOTE, so
+/// anything that can take that (e.g. stdin2ring) can be the receiver.
+///
+/// *  PushTarget says what to push where and how: the ring, the host and
+///    port, and REMOTE's options (other than TLS).
+/// *  Push is a PushTarget and its Link (see the link module), shared
+///    between the thread doing the push and those asking about it or
+///    stopping it.
+/// *  PushTable holds the pushes by ring and address.
+///
+pub mod push {
+    use crate::hoist::link::link::{Link, LinkStatus};
+    use crate::hoist::remote::remote::RemoteOptions;
+    use crate::tcllist::TclList;
+    use serde_json::{Map, Value};
+    use std::collections::BTreeMap;
+    use std::sync::{Arc, Mutex};
+
+    ///
+    /// What to push where.
+    ///
+    #[derive(Clone, Debug, PartialEq)]
+    pub struct PushTarget {
+        pub ring: String,
+        pub host: String,
+        pub port: u16,
+        pub options: RemoteOptions,
+    }
+    impl PushTarget {
+        ///
+        /// Parse ringname host:port ?options? as given to PUSH.  IPv6
+        /// addresses are bracketed: \[::1\]:5000.
+        ///
+        pub fn parse(words: &[String]) -> Result<PushTarget, String> {
+            if words.len() < 2 {
+                return Err(String::from("PUSH needs a ring name and a host:port"));
+            }
+            let (host, port) = parse_address(&words[1])?;
+            let options = RemoteOptions::parse(&words[2..])?;
+            if options.tls {
+                return Err(String::from("TLS is not supported for PUSH"));
+            }
+            Ok(PushTarget {
+                ring: words[0].clone(),
+                host,
+                port,
+                options,
+            })
+        }
+        ///
+        /// host:port, bracketing IPv6 addresses.
+        ///
+        pub fn address(&self) -> String {
+            format_address(&self.host, self.port)
+        }
+    }
+    ///
+    /// Split host:port into its parts.
+    ///
+    pub fn parse_address(address: &str) -> Result<(String, u16), String> {
+        let invalid = || format!("Invalid address {}: must be host:port", address);
+        let (host, port) = address.rsplit_once(':').ok_or_else(invalid)?;
+        let host = host.trim_start_matches('[').trim_end_matches(']');
+        match port.parse::<u16>() {
+            Ok(port) if port > 0 && !host.is_empty() => Ok((String::from(host), port)),
+            _ => Err(invalid()),
+        }
+    }
+    fn format_address(host: &str, port: u16) -> String {
+        if host.contains(':') {
+            format!("[{}]:{}", host, port)
+        } else {
+            format!("{}:{}", host, port)
+        }
+    }
+    ///
+    /// The status of a push: what's pushed where and how its link is
+    /// doing.
+    ///
+    #[derive(Clone, Debug, PartialEq)]
+    pub struct PushStatus {
+        pub ring: String,
+        pub address: String,
+        pub link: LinkStatus,
+    }
+    impl PushStatus {
+        ///
+        /// Render as a Tcl list of key value pairs: ring, address and
+        /// those of LinkStatus.
+        ///
+        pub fn to_tcl(&self) -> TclList {
+            let mut result = TclList::new();
+            result
+                .add_element("ring")
+                .add_quoted_element(&self.ring)
+                .add_element("address")
+                .add_quoted_element(&self.address);
+            self.link.add_tcl(&mut result);
+            result
+        }
+        ///
+        /// Render as a JSON object with the same keys as to_tcl.
+        ///
+        pub fn to_json(&self) -> Value {
+            let mut result = Map::new();
+            result.insert(String::from("ring"), Value::from(self.ring.as_str()));
+            result.insert(String::from("address"), Value::from(self.address.as_str()));
+            self.link.add_json(&mut result);
+            Value::Object(result)
+        }
+    }
+    ///
+    /// A push in progress.
+    ///
+    pub struct Push {
+        pub target: PushTarget,
+        pub link: Link,
+    }
+    impl Push {
+        pub fn new(target: PushTarget, now: u64) -> Push {
+            Push {
+                target,
+                link: Link::new(now),
+            }
+        }
+        pub fn status(&self) -> PushStatus {
+            PushStatus {
+                ring: self.target.ring.clone(),
+                address: self.target.address(),
+                link: self.link.status(),
+            }
+        }
+    }
+    ///
+    /// The pushes, by ring and address.
+    ///
+    #[derive(Default)]
+    pub struct PushTable {
+        pushes: Mutex<BTreeMap<String, Arc<Push>>>,
+    }
+    impl PushTable {
+        pub fn new() -> PushTable {
+            PushTable::default()
+        }
+        ///
+        /// Add a push.  Err if that ring is already pushed to that address.
+        ///
+        pub fn add(&self, target: PushTarget, now: u64) -> Result<Arc<Push>, String> {
+            let key = format!("{} {}", target.ring, target.address());
+            let mut pushes = self.pushes.lock().unwrap();
+            if pushes.contains_key(&key) {
+                return Err(format!("{} is already pushed to {}", target.ring, target.address()));
+            }
+            let push = Arc::new(Push::new(target, now));
+            pushes.insert(key, Arc::clone(&push));
+            Ok(push)
+        }
+        ///
+        /// Remove and stop a push.  Err if there's no such push.
+        ///
+        pub fn remove(&self, ring: &str, address: &str) -> Result<(), String> {
+            let (host, port) = parse_address(address)?;
+            let address = format_address(&host, port);
+            match self.pushes.lock().unwrap().remove(&format!("{} {}", ring, address)) {
+                Some(push) => {
+                    push.link.stop();
+                    Ok(())
+                }
+                None => Err(format!("{} is not pushed to {}", ring, address)),
+            }
+        }
+        ///
+        /// The status of each push, by ring then address.
+        ///
+        pub fn list(&self) -> Vec<PushStatus> {
+            self.pushes.lock().unwrap().values().map(|push| push.status()).collect()
+        }
+    }
+    #[cfg(test)]
+    mod push_tests {
+        use super::*;
+        use crate::hoist::compress::compress::Codec;
+
+        fn words(text: &str) -> Vec<String> {
+            text.split_whitespace().map(String::from).collect()
+        }
+        #[test]
+        fn parse_1() {
+            let target = PushTarget::parse(&words("fox evb.lab:5000 COMPRESS lz4")).unwrap();
+            assert_eq!("fox", target.ring);
+            assert_eq!("evb.lab", target.host);
+            assert_eq!(5000, target.port);
+            assert_eq!(Some(Codec::Lz4), target.options.compress);
+            assert_eq!("evb.lab:5000", target.address());
+
+            let target = PushTarget::parse(&words("fox [::1]:5000")).unwrap();
+            assert_eq!("::1", target.host);
+            assert_eq!("[::1]:5000", target.address());
+
+            assert!(PushTarget::parse(&words("fox")).is_err());
+            assert!(PushTarget::parse(&words("fox evb.lab")).is_err());
+            assert!(PushTarget::parse(&words("fox evb.lab:0")).is_err());
+            assert!(PushTarget::parse(&words("fox :5000")).is_err());
+            assert!(PushTarget::parse(&words("fox evb.lab:5000 TLS")).is_err());
+        }
+        #[test]
+        fn status_1() {
+            let push = Push::new(PushTarget::parse(&words("fox evb:5000")).unwrap(), 100);
+            push.link.connected(None, 140);
+            push.link.add_bytes(1000);
+            assert_eq!(
+                "{ring fox address evb:5000 state connected since 140 connects 1 bytes 1000 error {} }",
+                push.status().to_tcl().to_string()
+            );
+            push.link.waiting("Connection reset", 150);
+            let json = push.status().to_json();
+            assert_eq!("evb:5000", json["address"]);
+            assert_eq!("waiting", json["state"]);
+            assert_eq!("Connection reset", json["error"]);
+        }
+        #[test]
+        fn table_1() {
+            let table = PushTable::new();
+            let target = PushTarget::parse(&words("fox evb:5000")).unwrap();
+            let push = table.add(target.clone(), 100).unwrap();
+            assert!(table.add(target, 100).is_err());
+            table.add(PushTarget::parse(&words("cat evb:5000")).unwrap(), 100).unwrap();
+            let rings = table.list().iter().map(|s| s.ring.clone()).collect::<Vec<String>>();
+            assert_eq!(vec!["cat", "fox"], rings);
+
+            assert!(table.remove("fox", "evb:5001").is_err());
+            table.remove("fox", "evb:5000").unwrap();
+            assert!(push.link.is_stopped());
+            assert_eq!(1, table.list().len());
+        }
+    }
+}
@@ -0,0 +1,262 @@
+///
+/// The queue module holds hoisted data between reading it from the
+/// hoister and sending it to the remote, so the hoister can be drained
+/// even while the remote is slow:
+///
+/// *  ItemSplitter splits the hoister's byte stream into ring items.  Each
+///    ring item starts with its size in bytes (a native u32 that counts
+///    itself), so dropping whole items keeps the stream parseable.  A size
+///    over the splitter's maximum means the stream is garbage.
+/// *  HoistQueue is a queue of items with a capacity in bytes.  Items that
+///    don't fit are refused rather than waited for.
+///
+pub mod queue {
+    use std::collections::VecDeque;
+    use std::convert::TryInto;
+    use std::io;
+    use std::mem::size_of;
+    use std::sync::{Condvar, Mutex};
+
+    const SIZE_BYTES: usize = size_of::<u32>();
+
+    ///
+    /// Splits a byte stream into ring items no bigger than max_item bytes.
+    ///
+    pub struct ItemSplitter {
+        pending: Vec<u8>,
+        start: usize,
+        max_item: usize,
+    }
+    impl ItemSplitter {
+        pub fn new(max_item: usize) -> ItemSplitter {
+            ItemSplitter {
+                pending: Vec::new(),
+                start: 0,
+                max_item,
+            }
+        }
+        ///
+        /// Add data from the stream.
+        ///
+        pub fn push(&mut self, data: &[u8]) {
+            if self.start > 0 {
+                self.pending.drain(..self.start);
+                self.start = 0;
+            }
+            self.pending.extend_from_slice(data);
+        }
+        ///
+        /// The next complete item, if there is one.  A size smaller than
+        /// the size field itself can't be right; it's taken to be just the
+        /// size field so we keep going.  A size bigger than the maximum is
+        /// an InvalidData error rather than a reason to buffer that much:
+        /// there's no telling where the next item starts so the stream
+        /// can't be used.
+        ///
+        pub fn next_item(&mut self) -> io::Result<Option<Vec<u8>>> {
+            let available = &self.pending[self.start..];
+            if available.len() < SIZE_BYTES {
+                return Ok(None);
+            }
+            let size = u32::from_ne_bytes(available[..SIZE_BYTES].try_into().unwrap()) as usize;
+            let size = size.max(SIZE_BYTES);
+            if size > self.max_item {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("A ring item of {} bytes is bigger than the {} byte maximum", size, self.max_item),
+                ));
+            }
+            if available.len() < size {
+                return Ok(None);
+            }
+            let result = available[..size].to_vec();
+            self.start += size;
+            Ok(Some(result))
+        }
+        ///
+        /// Bytes of incomplete items held.
+        ///
+        pub fn pending(&self) -> usize {
+            self.pending.len() - self.start
+        }
+    }
+
+    struct QueueState {
+        items: VecDeque<Vec<u8>>,
+        bytes: usize,
+        closed: bool,
+    }
+    ///
+    /// A queue of items bounded in bytes.  One thread offers, another
+    /// takes.  Share it with an Arc.
+    ///
+    pub struct HoistQueue {
+        state: Mutex<QueueState>,
+        ready: Condvar,
+        capacity: usize,
+    }
+    impl HoistQueue {
+        pub fn new(capacity: usize) -> HoistQueue {
+            HoistQueue {
+                state: Mutex::new(QueueState {
+                    items: VecDeque::new(),
+                    bytes: 0,
+                    closed: false,
+                }),
+                ready: Condvar::new(),
+                capacity,
+            }
+        }
+        ///
+        /// Queue an item if it fits.  An empty queue takes any item so big
+        /// items aren't refused forever.  false if the item was refused
+        /// (it doesn't fit or the queue is closed).
+        ///
+        pub fn offer(&self, item: Vec<u8>) -> bool {
+            let mut state = self.state.lock().unwrap();
+            if state.closed || (!state.items.is_empty() && state.bytes + item.len() > self.capacity) {
+                return false;
+            }
+            state.bytes += item.len();
+            state.items.push_back(item);
+            self.ready.notify_one();
+            true
+        }
+        ///
+        /// Wait for the next item.  None once the queue is closed and
+        /// empty.
+        ///
+        pub fn take(&self) -> Option<Vec<u8>> {
+            let mut state = self.state.lock().unwrap();
+            loop {
+                if let Some(item) = state.items.pop_front() {
+                    state.bytes -= item.len();
+                    return Some(item);
+                }
+                if state.closed {
+                    return None;
+                }
+                state = self.ready.wait(state).unwrap();
+            }
+        }
+        ///
+        /// The next item if there is one now.
+        ///
+        pub fn try_take(&self) -> Option<Vec<u8>> {
+            let mut state = self.state.lock().unwrap();
+            let item = state.items.pop_front()?;
+            state.bytes -= item.len();
+            Some(item)
+        }
+        ///
+        /// No more items will be queued.  Those queued can still be taken.
+        ///
+        pub fn close(&self) {
+            self.state.lock().unwrap().closed = true;
+            self.ready.notify_all();
+        }
+        ///
+        /// Bytes queued.
+        ///
+        pub fn bytes(&self) -> usize {
+            self.state.lock().unwrap().bytes
+        }
+    }
+    #[cfg(test)]
+    mod queue_tests {
+        use super::*;
+        use std::sync::Arc;
+        use std::thread;
+
+        fn item(size: u32) -> Vec<u8> {
+            let mut result = size.to_ne_bytes().to_vec();
+            result.resize(size as usize, 0xaa);
+            result
+        }
+        #[test]
+        fn split_1() {
+            let mut stream = item(10);
+            stream.extend(item(20));
+            stream.extend(item(30));
+
+            // Dribble it in:
+
+            let mut splitter = ItemSplitter::new(30);
+            let mut items = Vec::new();
+            for chunk in stream.chunks(7) {
+                splitter.push(chunk);
+                while let Some(item) = splitter.next_item().unwrap() {
+                    items.push(item);
+                }
+            }
+            assert_eq!(vec![item(10), item(20), item(30)], items);
+            assert_eq!(0, splitter.pending());
+        }
+        #[test]
+        fn split_2() {
+            // A bad size doesn't wedge us:
+
+            let mut splitter = ItemSplitter::new(100);
+            splitter.push(&0u32.to_ne_bytes());
+            assert_eq!(Some(0u32.to_ne_bytes().to_vec()), splitter.next_item().unwrap());
+            splitter.push(&[1, 2]);
+            assert!(splitter.next_item().unwrap().is_none());
+            assert_eq!(2, splitter.pending());
+        }
+        #[test]
+        fn split_3() {
+            // A size over the maximum is an error as soon as it's seen,
+            // without waiting for (or holding) the item:
+
+            let mut splitter = ItemSplitter::new(100);
+            splitter.push(&item(100));
+            splitter.push(&u32::MAX.to_ne_bytes());
+            assert_eq!(Some(item(100)), splitter.next_item().unwrap());
+            let error = splitter.next_item().unwrap_err();
+            assert_eq!(io::ErrorKind::InvalidData, error.kind());
+            assert_eq!(SIZE_BYTES, splitter.pending());
+        }
+        #[test]
+        fn queue_1() {
+            let queue = HoistQueue::new(100);
+            assert!(queue.offer(item(60)));
+            assert!(!queue.offer(item(60)));
+            assert!(queue.offer(item(40)));
+            assert_eq!(100, queue.bytes());
+            assert_eq!(Some(item(60)), queue.try_take());
+            assert_eq!(Some(item(40)), queue.take());
+            assert!(queue.try_take().is_none());
+
+            // Empty queues take anything:
+
+            assert!(queue.offer(item(200)));
+            queue.close();
+            assert!(!queue.offer(item(10)));
+            assert_eq!(Some(item(200)), queue.take());
+            assert!(queue.take().is_none());
+        }
+        #[test]
+        fn queue_2() {
+            // take waits for offers:
+
+            let queue = Arc::new(HoistQueue::new(100));
+            let taker = {
+                let queue = Arc::clone(&queue);
+                thread::spawn(move || {
+                    let mut result = Vec::new();
+                    while let Some(item) = queue.take() {
+                        result.push(item);
+                    }
+                    result
+                })
+            };
+            for size in [10, 20, 30] {
+                while !queue.offer(item(size)) {
+                    thread::yield_now();
+                }
+            }
+            queue.close();
+            assert_eq!(vec![item(10), item(20), item(30)], taker.join().unwrap());
+        }
+    }
+}
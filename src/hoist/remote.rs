@@ -0,0 +1,133 @@
+///
+/// The remote module parses the options that can follow the ring name in
+/// a REMOTE request:
+///
+/// *  TLS - encrypt the hoisted data.  The ring master replies OK TLS and
+///    the client starts a TLS handshake on the connection.  The rest of
+///    the exchange (the OK BINARY FOLLOWS reply and the data) is encrypted.
+/// *  COMPRESS codecs - compress the hoisted data.  codecs is a comma
+///    separated list of the codecs the client can decompress in order of
+///    preference (e.g. zstd,lz4).  The ring master uses the first it
+///    supports and names it in the reply: OK BINARY FOLLOWS COMPRESS zstd.
+/// *  RATE size - the most bytes/second to hoist (k, M and G suffixes
+///    are allowed).
+/// *  POLICY block|sample - what to do if the remote falls behind; see
+///    the throttle module.
+///
+pub mod remote {
+    use crate::hoist::compress::compress::Codec;
+    use crate::hoist::throttle::throttle::{HoistLimits, Policy};
+    use crate::logging::parse_size;
+
+    ///
+    /// How a REMOTE request wants its data hoisted.
+    ///
+    #[derive(Clone, Debug, Default, PartialEq)]
+    pub struct RemoteOptions {
+        pub tls: bool,
+        pub compress: Option<Codec>,
+        pub rate: Option<u64>,
+        pub policy: Option<Policy>,
+    }
+    impl RemoteOptions {
+        ///
+        /// Parse the words after the ring name.  Err describes the first
+        /// word that's not understood.
+        ///
+        pub fn parse(words: &[String]) -> Result<RemoteOptions, String> {
+            let mut result = RemoteOptions::default();
+            let mut words = words.iter();
+            while let Some(word) = words.next() {
+                match word.as_str() {
+                    "TLS" => result.tls = true,
+                    "COMPRESS" => match words.next() {
+                        Some(codecs) => result.compress = Some(Codec::choose(codecs)?),
+                        None => return Err(String::from("COMPRESS needs a compression, e.g. lz4 or zstd")),
+                    },
+                    "RATE" => match words.next().map(|size| parse_size(size)) {
+                        Some(Ok(0)) => return Err(String::from("RATE must be more than 0")),
+                        Some(rate) => result.rate = Some(rate?),
+                        None => return Err(String::from("RATE needs bytes/second")),
+                    },
+                    "POLICY" => match words.next() {
+                        Some(policy) => result.policy = Some(Policy::parse(policy)?),
+                        None => return Err(String::from("POLICY needs block or sample")),
+                    },
+                    _ => return Err(format!("Invalid REMOTE option {}", word)),
+                }
+            }
+            Ok(result)
+        }
+        ///
+        /// The words that ask for these options in a REMOTE request.
+        ///
+        pub fn to_words(&self) -> Vec<String> {
+            let mut result = Vec::new();
+            if self.tls {
+                result.push(String::from("TLS"));
+            }
+            if let Some(codec) = self.compress {
+                result.extend([String::from("COMPRESS"), String::from(codec.name())]);
+            }
+            if let Some(rate) = self.rate {
+                result.extend([String::from("RATE"), rate.to_string()]);
+            }
+            if let Some(policy) = self.policy {
+                result.extend([String::from("POLICY"), String::from(policy.name())]);
+            }
+            result
+        }
+        ///
+        /// The limits for the hoist: the configured limits tightened by
+        /// what was asked for.
+        ///
+        pub fn limits(&self, configured: &HoistLimits) -> HoistLimits {
+            configured.tightened(self.rate, self.policy)
+        }
+    }
+    #[cfg(test)]
+    mod remote_tests {
+        use super::*;
+
+        fn words(text: &str) -> Vec<String> {
+            text.split_whitespace().map(String::from).collect()
+        }
+        #[test]
+        fn parse_1() {
+            assert_eq!(RemoteOptions::default(), RemoteOptions::parse(&[]).unwrap());
+            assert!(RemoteOptions::parse(&words("TLS")).unwrap().tls);
+            assert!(RemoteOptions::parse(&words("TLS junk")).is_err());
+        }
+        #[test]
+        fn parse_2() {
+            let options = RemoteOptions::parse(&words("COMPRESS lz4 TLS")).unwrap();
+            assert!(options.tls);
+            assert_eq!(Some(Codec::Lz4), options.compress);
+            let options = RemoteOptions::parse(&words("COMPRESS gzip,zstd")).unwrap();
+            assert_eq!(Some(Codec::Zstd), options.compress);
+            assert!(RemoteOptions::parse(&words("COMPRESS")).is_err());
+            assert!(RemoteOptions::parse(&words("COMPRESS gzip")).is_err());
+        }
+        #[test]
+        fn parse_3() {
+            let options = RemoteOptions::parse(&words("RATE 10k POLICY sample")).unwrap();
+            assert_eq!(Some(10240), options.rate);
+            assert_eq!(Some(Policy::Sample), options.policy);
+            let limits = options.limits(&HoistLimits::default());
+            assert_eq!(Some(10240), limits.rate);
+            assert_eq!(Policy::Sample, limits.policy);
+            assert!(RemoteOptions::parse(&words("RATE")).is_err());
+            assert!(RemoteOptions::parse(&words("RATE 0")).is_err());
+            assert!(RemoteOptions::parse(&words("RATE fast")).is_err());
+            assert!(RemoteOptions::parse(&words("POLICY drop")).is_err());
+        }
+        #[test]
+        fn words_1() {
+            let options = RemoteOptions::parse(&words("POLICY sample TLS RATE 1k COMPRESS gzip,lz4")).unwrap();
+            let request = words("TLS COMPRESS lz4 RATE 1024 POLICY sample");
+            assert_eq!(request, options.to_words());
+            assert_eq!(options, RemoteOptions::parse(&request).unwrap());
+            assert!(RemoteOptions::default().to_words().is_empty());
+        }
+    }
+}
@@ -0,0 +1,188 @@
+///
+/// The throttle module limits how fast and how hard a hoist can pull on
+/// its ring:
+///
+/// *  A maximum bandwidth in bytes/second.
+/// *  A policy for when the remote can't keep up: Block (wait for it,
+///    which holds back the hoister's consumer slot and eventually the
+///    ring's producer) or Sample (skip ring items so the hoister never
+///    holds back the ring).
+///
+/// The ring master has configured limits; a REMOTE request can tighten
+/// them but not loosen them.
+///
+pub mod throttle {
+    use std::time::{Duration, Instant};
+
+    ///
+    /// What to do when the remote falls behind.
+    ///
+    #[derive(Copy, Clone, Debug, PartialEq)]
+    pub enum Policy {
+        Block,
+        Sample,
+    }
+    impl Policy {
+        pub fn parse(name: &str) -> Result<Policy, String> {
+            match name {
+                "block" => Ok(Policy::Block),
+                "sample" => Ok(Policy::Sample),
+                _ => Err(format!("Invalid hoist policy {}: must be block or sample", name)),
+            }
+        }
+        pub fn name(&self) -> &'static str {
+            match self {
+                Policy::Block => "block",
+                Policy::Sample => "sample",
+            }
+        }
+    }
+    ///
+    /// The limits on a hoist: bytes/second (None for no limit) and the
+    /// policy.
+    ///
+    #[derive(Copy, Clone, Debug, PartialEq)]
+    pub struct HoistLimits {
+        pub rate: Option<u64>,
+        pub policy: Policy,
+    }
+    impl HoistLimits {
+        ///
+        /// These limits tightened by what a request asks for: the lower
+        /// of the rates, and Sample if either policy is Sample.
+        ///
+        pub fn tightened(&self, rate: Option<u64>, policy: Option<Policy>) -> HoistLimits {
+            HoistLimits {
+                rate: match (self.rate, rate) {
+                    (Some(mine), Some(theirs)) => Some(mine.min(theirs)),
+                    (mine, theirs) => mine.or(theirs),
+                },
+                policy: if policy == Some(Policy::Sample) {
+                    Policy::Sample
+                } else {
+                    self.policy
+                },
+            }
+        }
+        ///
+        /// True if hoisting is not limited at all.
+        ///
+        pub fn unlimited(&self) -> bool {
+            self.rate.is_none() && self.policy == Policy::Block
+        }
+    }
+    impl Default for HoistLimits {
+        fn default() -> Self {
+            HoistLimits {
+                rate: None,
+                policy: Policy::Block,
+            }
+        }
+    }
+    ///
+    /// A token bucket that meters bytes out at a rate.  Up to a second's
+    /// worth can go in a burst.
+    ///
+    pub struct Throttle {
+        rate: f64,
+        tokens: f64,
+        last: Instant,
+    }
+    impl Throttle {
+        pub fn new(rate: u64, now: Instant) -> Throttle {
+            Throttle {
+                rate: rate as f64,
+                tokens: rate as f64,
+                last: now,
+            }
+        }
+        ///
+        /// Block policy: take bytes, returning how long to wait before
+        /// sending them to stay within the rate.
+        ///
+        pub fn delay(&mut self, bytes: usize, now: Instant) -> Duration {
+            self.refill(now);
+            self.tokens -= bytes as f64;
+            if self.tokens < 0.0 {
+                Duration::from_secs_f64(-self.tokens / self.rate)
+            } else {
+                Duration::ZERO
+            }
+        }
+        ///
+        /// Sample policy: take bytes if they can be sent now.  Something
+        /// bigger than a burst goes when the bucket is full and leaves it
+        /// in debt.
+        ///
+        pub fn allow(&mut self, bytes: usize, now: Instant) -> bool {
+            self.refill(now);
+            if self.tokens >= (bytes as f64).min(self.rate) {
+                self.tokens -= bytes as f64;
+                true
+            } else {
+                false
+            }
+        }
+        fn refill(&mut self, now: Instant) {
+            let elapsed = now.saturating_duration_since(self.last).as_secs_f64();
+            self.tokens = (self.tokens + elapsed * self.rate).min(self.rate);
+            self.last = now;
+        }
+    }
+    #[cfg(test)]
+    mod throttle_tests {
+        use super::*;
+
+        #[test]
+        fn limits_1() {
+            let configured = HoistLimits {
+                rate: Some(1000),
+                policy: Policy::Block,
+            };
+            assert_eq!(configured, configured.tightened(None, None));
+            assert_eq!(configured, configured.tightened(Some(2000), Some(Policy::Block)));
+            let limits = configured.tightened(Some(500), Some(Policy::Sample));
+            assert_eq!(Some(500), limits.rate);
+            assert_eq!(Policy::Sample, limits.policy);
+
+            // A sampling ring master can't be asked to block:
+
+            let configured = HoistLimits {
+                rate: None,
+                policy: Policy::Sample,
+            };
+            assert_eq!(Policy::Sample, configured.tightened(None, Some(Policy::Block)).policy);
+            assert_eq!(Some(10), configured.tightened(Some(10), None).rate);
+            assert!(HoistLimits::default().unlimited());
+            assert!(!configured.unlimited());
+        }
+        #[test]
+        fn delay_1() {
+            let start = Instant::now();
+            let mut throttle = Throttle::new(1000, start);
+            assert_eq!(Duration::ZERO, throttle.delay(1000, start));
+            assert_eq!(Duration::from_millis(500), throttle.delay(500, start));
+
+            // After the wait we're square:
+
+            let later = start + Duration::from_millis(500);
+            assert_eq!(Duration::ZERO, throttle.delay(0, later));
+        }
+        #[test]
+        fn allow_1() {
+            let start = Instant::now();
+            let mut throttle = Throttle::new(1000, start);
+            assert!(throttle.allow(600, start));
+            assert!(!throttle.allow(600, start));
+            assert!(throttle.allow(400, start));
+            assert!(!throttle.allow(1, start));
+            assert!(throttle.allow(100, start + Duration::from_millis(100)));
+
+            // Big items go when the bucket is full:
+
+            let later = start + Duration::from_secs(5);
+            assert!(throttle.allow(5000, later));
+            assert!(!throttle.allow(1, later + Duration::from_secs(3)));
+        }
+    }
+}
@@ -0,0 +1,296 @@
+///
+/// The tls module wraps rustls for encrypted REMOTE hoisting.  TlsServer
+/// is the ring master's side: a certificate and key, and optionally a CA
+/// that client certificates must be signed by.  TlsClient is the receiving
+/// side: the CA(s) to trust and optionally a client certificate and key.
+/// Certificates and keys are PEM.
+///
+/// Both sides take over an already connected stream and do the handshake
+/// before returning, so failures show up as errors rather than on the
+/// first read or write.
+///
+pub mod tls {
+    use rustls::crypto::ring::default_provider;
+    use rustls::crypto::CryptoProvider;
+    use rustls::server::WebPkiClientVerifier;
+    use rustls::{
+        ClientConfig, ClientConnection, RootCertStore, ServerConfig, ServerConnection, StreamOwned,
+    };
+    use rustls_pki_types::pem::PemObject;
+    use rustls_pki_types::{CertificateDer, PrivateKeyDer, ServerName};
+    use std::convert::TryFrom;
+    use std::fmt;
+    use std::fs;
+    use std::io::{Read, Write};
+    use std::sync::Arc;
+
+    pub type TlsServerStream<S> = StreamOwned<ServerConnection, S>;
+    pub type TlsClientStream<S> = StreamOwned<ClientConnection, S>;
+
+    ///
+    /// The ring master's TLS configuration.  Cloning is cheap.
+    ///
+    #[derive(Clone)]
+    pub struct TlsServer {
+        config: Arc<ServerConfig>,
+        client_auth: bool,
+    }
+    impl TlsServer {
+        ///
+        /// Load the configuration from PEM files.
+        ///
+        pub fn load(cert: &str, key: &str, client_ca: Option<&str>) -> Result<TlsServer, String> {
+            let client_ca = match client_ca {
+                Some(path) => Some(read_pem(path)?),
+                None => None,
+            };
+            TlsServer::from_pem(&read_pem(cert)?, &read_pem(key)?, client_ca.as_deref())
+        }
+        ///
+        /// Make the configuration from PEM text: the certificate chain,
+        /// its private key and, if clients must present certificates, the
+        /// CA(s) those must be signed by.
+        ///
+        pub fn from_pem(cert: &[u8], key: &[u8], client_ca: Option<&[u8]>) -> Result<TlsServer, String> {
+            let provider = provider();
+            let builder = ServerConfig::builder_with_provider(Arc::clone(&provider))
+                .with_safe_default_protocol_versions()
+                .map_err(|e| e.to_string())?;
+            let builder = match client_ca {
+                Some(ca) => {
+                    let verifier = WebPkiClientVerifier::builder_with_provider(Arc::new(roots(ca)?), provider)
+                        .build()
+                        .map_err(|e| format!("Invalid TLS client CA: {}", e))?;
+                    builder.with_client_cert_verifier(verifier)
+                }
+                None => builder.with_no_client_auth(),
+            };
+            let config = builder
+                .with_single_cert(certificates(cert)?, private_key(key)?)
+                .map_err(|e| format!("Invalid TLS certificate or key: {}", e))?;
+            Ok(TlsServer {
+                config: Arc::new(config),
+                client_auth: client_ca.is_some(),
+            })
+        }
+        ///
+        /// True if clients must present a certificate.
+        ///
+        pub fn client_auth(&self) -> bool {
+            self.client_auth
+        }
+        ///
+        /// Do the server side of a handshake on a connected stream.
+        ///
+        pub fn accept<S: Read + Write>(&self, stream: S) -> Result<TlsServerStream<S>, String> {
+            let connection = ServerConnection::new(Arc::clone(&self.config)).map_err(|e| e.to_string())?;
+            let mut result = StreamOwned::new(connection, stream);
+            while result.conn.is_handshaking() {
+                result
+                    .conn
+                    .complete_io(&mut result.sock)
+                    .map_err(|e| format!("TLS handshake failed: {}", e))?;
+            }
+            Ok(result)
+        }
+    }
+    impl fmt::Debug for TlsServer {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.debug_struct("TlsServer")
+                .field("client_auth", &self.client_auth)
+                .finish()
+        }
+    }
+    ///
+    /// The receiving side's TLS configuration.
+    ///
+    #[derive(Clone)]
+    pub struct TlsClient {
+        config: Arc<ClientConfig>,
+    }
+    impl TlsClient {
+        ///
+        /// Load the configuration from PEM files.  identity is the client
+        /// certificate and key files for ring masters that want them.
+        ///
+        pub fn load(ca: &str, identity: Option<(&str, &str)>) -> Result<TlsClient, String> {
+            let identity = match identity {
+                Some((cert, key)) => Some((read_pem(cert)?, read_pem(key)?)),
+                None => None,
+            };
+            TlsClient::from_pem(
+                &read_pem(ca)?,
+                identity.as_ref().map(|(cert, key)| (cert.as_slice(), key.as_slice())),
+            )
+        }
+        ///
+        /// Make the configuration from PEM text.
+        ///
+        pub fn from_pem(ca: &[u8], identity: Option<(&[u8], &[u8])>) -> Result<TlsClient, String> {
+            let builder = ClientConfig::builder_with_provider(provider())
+                .with_safe_default_protocol_versions()
+                .map_err(|e| e.to_string())?
+                .with_root_certificates(roots(ca)?);
+            let config = match identity {
+                Some((cert, key)) => builder
+                    .with_client_auth_cert(certificates(cert)?, private_key(key)?)
+                    .map_err(|e| format!("Invalid TLS certificate or key: {}", e))?,
+                None => builder.with_no_client_auth(),
+            };
+            Ok(TlsClient {
+                config: Arc::new(config),
+            })
+        }
+        ///
+        /// Do the client side of a handshake on a connected stream.
+        /// server is the host name the server's certificate must have.
+        ///
+        pub fn connect<S: Read + Write>(&self, server: &str, stream: S) -> Result<TlsClientStream<S>, String> {
+            let name = ServerName::try_from(String::from(server))
+                .map_err(|e| format!("Invalid TLS server name {}: {}", server, e))?;
+            let connection = ClientConnection::new(Arc::clone(&self.config), name).map_err(|e| e.to_string())?;
+            let mut result = StreamOwned::new(connection, stream);
+            while result.conn.is_handshaking() {
+                result
+                    .conn
+                    .complete_io(&mut result.sock)
+                    .map_err(|e| format!("TLS handshake failed: {}", e))?;
+            }
+            Ok(result)
+        }
+    }
+    impl fmt::Debug for TlsClient {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.debug_struct("TlsClient").finish()
+        }
+    }
+
+    fn provider() -> Arc<CryptoProvider> {
+        Arc::new(default_provider())
+    }
+    fn read_pem(path: &str) -> Result<Vec<u8>, String> {
+        fs::read(path).map_err(|e| format!("Unable to read {}: {}", path, e))
+    }
+    fn certificates(pem: &[u8]) -> Result<Vec<CertificateDer<'static>>, String> {
+        let result = CertificateDer::pem_slice_iter(pem)
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| format!("Invalid TLS certificate: {}", e))?;
+        if result.is_empty() {
+            Err(String::from("No TLS certificates found"))
+        } else {
+            Ok(result)
+        }
+    }
+    fn private_key(pem: &[u8]) -> Result<PrivateKeyDer<'static>, String> {
+        PrivateKeyDer::from_pem_slice(pem).map_err(|e| format!("Invalid TLS private key: {}", e))
+    }
+    fn roots(pem: &[u8]) -> Result<RootCertStore, String> {
+        let mut result = RootCertStore::empty();
+        for cert in certificates(pem)? {
+            result
+                .add(cert)
+                .map_err(|e| format!("Invalid TLS CA certificate: {}", e))?;
+        }
+        Ok(result)
+    }
+    #[cfg(test)]
+    mod tls_tests {
+        use super::*;
+        use rcgen::generate_simple_self_signed;
+        use std::io::{BufRead, BufReader};
+        use std::net::{TcpListener, TcpStream};
+        use std::thread;
+
+        // A self-signed certificate and key for localhost as PEM.
+
+        fn identity() -> (String, String) {
+            let certified = generate_simple_self_signed(vec![String::from("localhost")]).unwrap();
+            (certified.cert.pem(), certified.signing_key.serialize_pem())
+        }
+        // Serve one TLS connection that's sent a line.  The result is
+        // what accept said.
+
+        fn serve(server: TlsServer) -> (u16, thread::JoinHandle<Result<(), String>>) {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            let port = listener.local_addr().unwrap().port();
+            let handle = thread::spawn(move || {
+                let (socket, _) = listener.accept().unwrap();
+                let mut stream = server.accept(socket)?;
+                stream.write_all(b"hello\n").map_err(|e| e.to_string())?;
+                stream.flush().map_err(|e| e.to_string())
+            });
+            (port, handle)
+        }
+        #[test]
+        fn load_1() {
+            let (cert, key) = identity();
+            assert!(TlsServer::from_pem(b"junk", key.as_bytes(), None).is_err());
+            assert!(TlsServer::from_pem(cert.as_bytes(), b"junk", None).is_err());
+            assert!(TlsServer::load("/no/such/cert", "/no/such/key", None).is_err());
+            let server = TlsServer::from_pem(cert.as_bytes(), key.as_bytes(), None).unwrap();
+            assert!(!server.client_auth());
+        }
+        #[test]
+        fn handshake_1() {
+            let (cert, key) = identity();
+            let server = TlsServer::from_pem(cert.as_bytes(), key.as_bytes(), None).unwrap();
+            let (port, handle) = serve(server);
+
+            let client = TlsClient::from_pem(cert.as_bytes(), None).unwrap();
+            let stream = client
+                .connect("localhost", TcpStream::connect(("127.0.0.1", port)).unwrap())
+                .unwrap();
+            let mut line = String::new();
+            BufReader::new(stream).read_line(&mut line).unwrap();
+            assert_eq!("hello\n", line);
+            assert!(handle.join().unwrap().is_ok());
+        }
+        #[test]
+        fn handshake_2() {
+            // The wrong server name is refused:
+
+            let (cert, key) = identity();
+            let server = TlsServer::from_pem(cert.as_bytes(), key.as_bytes(), None).unwrap();
+            let (port, handle) = serve(server);
+
+            let client = TlsClient::from_pem(cert.as_bytes(), None).unwrap();
+            assert!(client
+                .connect("elsewhere", TcpStream::connect(("127.0.0.1", port)).unwrap())
+                .is_err());
+            assert!(handle.join().unwrap().is_err());
+        }
+        #[test]
+        fn client_auth_1() {
+            let (cert, key) = identity();
+            let (client_cert, client_key) = identity();
+            let server = TlsServer::from_pem(cert.as_bytes(), key.as_bytes(), Some(client_cert.as_bytes())).unwrap();
+            assert!(server.client_auth());
+
+            // Without a certificate we're refused:
+
+            let (port, handle) = serve(server.clone());
+            let client = TlsClient::from_pem(cert.as_bytes(), None).unwrap();
+            if let Ok(stream) = client.connect("localhost", TcpStream::connect(("127.0.0.1", port)).unwrap()) {
+                let mut line = String::new();
+                assert!(BufReader::new(stream).read_line(&mut line).is_err() || line.is_empty());
+            }
+            assert!(handle.join().unwrap().is_err());
+
+            // With one we're in:
+
+            let (port, handle) = serve(server);
+            let client = TlsClient::from_pem(
+                cert.as_bytes(),
+                Some((client_cert.as_bytes(), client_key.as_bytes())),
+            )
+            .unwrap();
+            let stream = client
+                .connect("localhost", TcpStream::connect(("127.0.0.1", port)).unwrap())
+                .unwrap();
+            let mut line = String::new();
+            BufReader::new(stream).read_line(&mut line).unwrap();
+            assert_eq!("hello\n", line);
+            assert!(handle.join().unwrap().is_ok());
+        }
+    }
+}
@@ -36,7 +36,136 @@
 //! which is where Linux keeps its POSIX shared memory regions.
 //! *   --log-file   - The file in which the ring master will make its
 //! logs.
+//! *   --log-target - Where logs go: file (the default) writes them to
+//!     the --log-file, syslog sends them to the local syslog daemon's
+//!     /dev/log socket as facility daemon and stderr writes them to stderr
+//!     with the <_priority_> prefixes journald understands.  Only file
+//!     logs are rotated and reopened (see below).
+//! *   --log-level - The most detailed messages logged: off, error, warn,
+//!     info (the default), debug or trace.
+//! *   --log-format - text (the default) or json.  JSON logs are one object
+//!     per line with time, level, thread and message keys plus fields such
+//!     as event (e.g. request, request_failed, pid_spoof, alarm_raised),
+//!     verb, ring, pid, peer and outcome.
+//! *   --log-max-size - Rotate the log file before it would grow past this
+//!     many bytes (a k, M or G suffix multiplies by powers of 1024).
+//! *   --log-max-age - Rotate the log file once it has been written to for
+//!     this many seconds.
+//! *   --log-keep - The number of rotated log files kept (default 5).  The
+//!     newest is _logfile_.1, the oldest _logfile_._keep_.
+//!
+//! The log file is appended to.  On SIGHUP the ring master closes it and
+//! opens it again by name so that logrotate can move it and signal.
+//! *   --reap-interval - Seconds between sweeps of the rings for producer
+//!     and consumer slots held by processes that no longer exist (e.g. clients
+//!     that crashed without ever talking to the ring master).  Such slots
+//!     are freed and logged.  Process start times are compared so that a
+//!     reused pid does not keep a slot alive.  Defaults to 60, 0 disables the sweep.
+//! *   --reap-dry-run - If present, the sweep only logs the slots it would free.
+//! *   --unregister-policy - What UNREGISTER does to the client processes of
+//!     the ring by default: kill (the default), signal or detach.  See UNREGISTER
+//!     below.
+//! *   --kill-grace - Seconds a client being killed has to exit after SIGTERM
+//!     before it is sent SIGKILL.  Defaults to 2.
+//! *   --ring-mode - Octal permissions given to ring buffer files made by
+//!     CREATE.  Defaults to 666.
+//! *   --listen - An address on which to accept requests, IPv4 or IPv6
+//!     (e.g. 10.0.0.1, ::1 or \[::\]).  May be given more than once to listen
+//!     on several addresses.  Defaults to 0.0.0.0, all IPv4 interfaces.
+//!     \[::\] is dual-stack where the system allows it, accepting IPv4 clients
+//!     as IPv4-mapped addresses (e.g. ::ffff:127.0.0.1).  Requests that must
+//!     be local must come from a loopback address, IPv4-mapped loopback
+//!     included.
+//! *   --service-name - The service name the ring master advertises with
+//!     the port manager (default RingMaster).  Separate ring masters, e.g.
+//!     for a production and a test ring directory, need separate names.
+//!     A ring master refuses to start if its service name is already
+//!     advertised.  Clients find a non-default ring master by asking the
+//!     port manager for its service name.  REMOTE then needs a ring2stdout
+//!     that takes --service (see REMOTE); an older one exits at once, and
+//!     what it says on stderr is logged.
+//! *   --per-user - Qualify the service name with the username as per-user
+//!     services are named, e.g. RingMaster_fox for user fox.
+//! *   --tls-cert, --tls-key - PEM certificate (chain) and private key used
+//!     for REMOTE ringname TLS.  Without them TLS is not offered.
+//! *   --tls-client-ca - PEM CA certificate(s).  If given, REMOTE TLS clients
+//!     must present a certificate signed by one of them.
+//! *   --tls-required - Refuse REMOTE requests that don't ask for TLS, so
+//!     ring data never leave in cleartext.
+//! *   --hoist-rate - The most bytes/second a REMOTE hoist may send (k, M,
+//!     G suffixes allowed).  Unlimited by default.
+//! *   --hoist-policy - What a REMOTE hoist does when the remote falls
+//!     behind: block (the default) or sample.  See REMOTE below.
+//! *   --hoist-buffer - Bytes of data a sampled or fan-out REMOTE hoist can
+//!     fall behind by (k, M, G suffixes allowed).  Defaults to 4M.  Such a
+//!     hoist ends (and is logged) if it meets a ring item bigger than this.
+//! *   --hoist-fanout - Share one hoister, and so one consumer slot, among
+//!     all the REMOTE hoists of a ring.  See REMOTE below.
+//! *   --hoist-config - A file of hoists to start with.  Each line that is
+//!     not blank or a # comment is a PUSH or PROXY request (see below),
+//!     e.g. PUSH fox evb.lab:5000 COMPRESS zstd or PROXY spdaq fox.  These
+//!     pushes start even if their rings don't exist yet; they wait for them.
+//! *   --proxy-size - Bytes of data in the proxy rings PROXY makes (k, M, G
+//!     suffixes allowed).  Defaults to 8M.
+//! *   --proxy-tls-ca - PEM CA certificate(s) for PROXY ... TLS: remote ring
+//!     masters' certificates must be signed by one of them.
+//! *   --lock-dir - Directory holding the lock files that keep two ring
+//!     masters from serving the same ring directory (default /run/lock).
+//!     The ring master holds an flock on a file named for its ring
+//!     directory (e.g. ringmaster_dev_shm.pid for /dev/shm) containing its
+//!     pid.  A lock file left by a ring master that died is reused.
+//! *   --takeover - If another ring master holds the lock, send it SIGTERM
+//!     (SIGKILL after --kill-grace seconds) and take over rather than
+//!     failing.
+//! *   --alarm-free - Raise a FREE alarm when a ring's free space falls below
+//!     this fraction of its size (the producer is about to block).
+//! *   --alarm-backlog - Raise a BACKLOG alarm when a consumer's backlog
+//!     exceeds this fraction of the ring size.
+//! *   --alarm-stall - Raise a STALL alarm when a consumer that has data
+//!     to get has not gotten any for this many seconds.
+//! *   --alarm-config - A file of per ring thresholds.  Each line is a ring
+//!     name pattern (as for LIST) followed by any of free=_fraction_,
+//!     backlog=_fraction_ and stall=_seconds_; a value of off disables that
+//!     alarm.  The first line whose pattern matches a ring applies, and
+//!     thresholds it does not give come from the options above.  Lines
+//!     starting with # are comments.
+//! *   --alarm-interval - Seconds between alarm checks.  Defaults to 5,
+//!     0 disables alarms.  Without any thresholds there are no checks.
+//! *   --alarm-command - A command run whenever an alarm is raised.  Its
+//!     parameters are the ring name, the pid (-1 if there is none), the
+//!     condition (FREE, BACKLOG or STALL) and the value.  Alarms are also
+//!     logged as they are raised and cleared.
+//! *   --stats-interval - Seconds between samples of the producer and
+//!     consumer positions from which STATS computes data rates.  Defaults
+//!     to 1, 0 disables statistics.
+//! *   --stats-history - Number of rate samples kept for each client.
+//!     Defaults to 60.
 //!      
+//! ## Port manager restarts
+//!
+//! The ring master checks every 5 seconds that the port manager still
+//! advertises it.  If the port manager has gone away or forgotten it, the
+//! ring master asks it for a service port again, retrying with a backoff
+//! of 1 second doubling to at most a minute.  If the new port differs from
+//! the old one, the ring master listens on the new port and stops
+//! listening on the old one.  Clients already connected are not affected.
+//!
+//! ## Running under systemd
+//!
+//! If NOTIFY_SOCKET is set (a Type=notify service), the ring master sends
+//! READY=1 once it has taken its inventory and is listening for requests,
+//! and a STATUS= line with the number of rings and clients every 10 seconds.
+//! If the service has WatchdogSec set, WATCHDOG=1 is sent every half
+//! watchdog period, but only after getting hold of the ring inventory, so a
+//! hung ring master is restarted.
+//!
+//! The ring master can also be socket activated (LISTEN_FDS/LISTEN_PID):
+//! it then accepts requests on the listening socket systemd passes rather
+//! than binding one itself.  It still gets its port from the port manager
+//! and advertises it under its service name; since the port manager chooses that
+//! port, if it differs from the activated socket's port the ring master
+//! listens on both.
+//!
 //! ## Ringmaster Application Protocol
 //!
 //! Clients of the ring master communicate with it via ASCII text
@@ -104,28 +233,80 @@
 //!
 //! Once the reply is issued, the connection is dropped.
 //!
-//! ### UNREGISTER ringname
+//! ### UNREGISTER ringname ?KILL|SIGNAL|NOKILL?
 //!
 //! When ring buffer is destroyed, this request is issued to the ringbuffer.
 //! The ringmaster:
 //!
 //! 1.   Stops/joins all threads monitoring remaining ring clients.
 //! 2.   If possible kills any client processes (in general Ringmaster
-//! must be running as root to allow this).
+//! must be running as root to allow this).  A process is only killed
+//!      if it is still the process that connected: a pid that was reused by
+//!      some other program is left alone.
 //! 3.   Removes any knowledge of the ringbuffer from internal data
 //! structures.
 //!
+//! What happens to the client processes in step 2 depends on the kill policy.
+//! The policy comes from the optional last word of the request or, if it's
+//! omitted, the --unregister-policy option:
+//!
+//! *   KILL - SIGTERM, then SIGKILL if the process has not exited after
+//!     --kill-grace seconds.
+//! *   SIGNAL - SIGTERM only.
+//! *   NOKILL - The clients are forgotten but not signalled.  Use this when
+//!     a ring is just being re-created.
+//!
 //! Once all these actions are taken, the reply to the client is issued
 //! and the connection to the client dropped.  Possible replies
 //! are:
 //!
-//! *   OK\n  - The success comppleted successfuly.
+//! *   OK ?pid...?\n  - The success comppleted successfuly.  The pids of
+//!     any processes that were signalled follow the OK.
 //! *   ERROR error reason string -  The request failed.  This can happen
 //! because:
 //!     -   The request was from a remote host.
 //!     -   The ringname was not know to the server.
 //!
-//! ### REMOTE ringname
+//! ### CREATE ringname size maxconsumers
+//!
+//! Creates a new ring buffer backing file named _ringname_ in the ring
+//! directory with _size_ bytes of data area and _maxconsumers_ consumer
+//! slots (at most 100), gives it the --ring-mode permissions and adds it
+//! to the inventory.  This lets rings be made without the NSCLDAQ
+//! ring buffer utilities.  The request must be local.
+//!
+//! Possible replies are:
+//!
+//! *   OK\n - on success.
+//! *   ERROR reason string - The following are reasons this request can
+//!     fail:
+//!     -   The request came from a remote host.
+//!     -   The ring name is not a plain file name or already exists.
+//!     -   The size or number of consumers is not valid.
+//!     -   The file could not be made.
+//!
+//! ### DELETE ringname ?FORCE?
+//!
+//! Removes the ring buffer file for _ringname_ and removes it from the
+//! inventory.  If the ring has a producer or consumers the request is
+//! refused unless FORCE is given.  With FORCE the clients are dealt with
+//! according to the --unregister-policy as for UNREGISTER.  A proxy ring
+//! can't be deleted while a proxy fills it (see PROXY); UNPROXY it first.
+//! The request must be local.
+//!
+//! Possible replies are:
+//!
+//! *   OK ?pid...?\n - on success.  The pids of any clients that were
+//!     signalled follow the OK.
+//! *   ERROR reason string - The following are reasons this request can
+//!     fail:
+//!     -   The request came from a remote host.
+//!     -   The ring is not known to the ringmaster.
+//!     -   The ring is being filled by a proxy.
+//!     -   The ring has clients and FORCE was not given.
+//!     -   The file could not be removed.
+//!
+//! ### REMOTE ringname ?TLS? ?COMPRESS codecs? ?RATE bytes? ?POLICY block|sample?
 //!
 //! This request must not come from a local host.  It is used to set
 //! up hoisting of ring data from a local ring to a remote system. Generally
@@ -136,7 +317,12 @@
 //! The ringmaster replies about the success or failure of the operation
 //! and then forks off a subprocess that will inherit the socket
 //! to actuall spew the data from the ring.  The subprocess will
-//! register with the ring master as an ordinary consumer client.
+//! register with the ring master as an ordinary consumer client.  If the
+//! ring master is not advertised as RingMaster, the subprocess is passed
+//! --service with the service name so it registers with this ring master.
+//! That needs a ring2stdout whose --help lists --service; with an older one
+//! the remote gets no data.  The subprocess's stderr and, if it fails, its
+//! exit status are logged.
 //!
 //! Possible replies are:
 //!
@@ -151,6 +337,172 @@
 //! they are not built with the NSCLDAQ libraries.
 //!     -   The subprocess to hoist the data could not be started for
 //! some reason.
+//!     -   TLS was asked for but the ring master has no --tls-cert, or it
+//! was not asked for and the ring master has --tls-required.
+//!
+//! With TLS the data are encrypted.  The ring master first replies
+//!
+//!   OK TLS\r\n
+//!
+//! and the client then starts a TLS handshake on the connection (the
+//! ring master's certificate is for its host name).  The OK BINARY FOLLOWS
+//! reply and the data then come over TLS.  With --tls-client-ca the client
+//! must present a certificate signed by that CA.  The crate's
+//! hoist::tls::TlsClient does the client side.
+//!
+//! With COMPRESS the data are compressed.  codecs is a comma separated
+//! list of the compressions the client can take in order of preference;
+//! lz4 and zstd are supported.  The ring master uses the first of these it
+//! supports (FAIL if none) and names it in the reply:
+//!
+//!   OK BINARY FOLLOWS COMPRESS zstd\r\n
+//!
+//! The data following the reply are an lz4 frame stream or a zstd stream,
+//! flushed as the ring's data arrive so compression doesn't delay them.
+//! The crate's hoist::compress::Decompressor decompresses them.  COMPRESS
+//! and TLS can be combined; the data are compressed, then encrypted.
+//!
+//! RATE limits the hoist to that many bytes/second (k, M and G suffixes
+//! are allowed) and POLICY says what happens when the remote can't keep
+//! up, either because of the rate or because it is slow:
+//!
+//! *   block - the hoist waits for the remote.  Its consumer slot then
+//! holds back the ring and, once the ring fills, the producer.  This is
+//! the default.
+//! *   sample - the hoist keeps up with the ring and skips whole ring items
+//! that the remote has no room for (up to --hoist-buffer bytes are held
+//! for the remote).
+//! The ring is never held back, so this is the policy for monitoring.
+//!
+//! The --hoist-rate and --hoist-policy options set these for all hoists.
+//! A request can only tighten them: ask for a lower rate or for sample.
+//!
+//! With --hoist-fanout all REMOTE hoists of a ring share one ring2stdout,
+//! so they use one of the ring's consumer slots rather than one each.
+//! Remotes that join get the ring's data from then on.  The shared hoister
+//! is never held back by its remotes (so neither is the ring); instead
+//! each remote has --hoist-buffer bytes of buffering.  A sample remote
+//! skips ring items that don't fit; a block remote that falls that far
+//! behind is disconnected (and logged) so it can't hold back the others.
+//! The hoister stops when its last remote disconnects.
+//!
+//! ### PUSH ringname host:port ?COMPRESS codec? ?RATE bytes? ?POLICY block|sample?
+//!
+//! REMOTE hoists when the remote asks.  PUSH hoists the other way round:
+//! the ring master connects to _host_:_port_ (an IPv6 address goes in
+//! brackets, e.g. \[::1\]:5000) and sends it _ringname_'s data.  This is
+//! for when a firewall lets the ring's host connect out but not the
+//! remote connect in.  The request must be local and the ring must be
+//! in the inventory.
+//!
+//! The remote gets exactly what follows OK BINARY FOLLOWS in a REMOTE
+//! reply, with no reply line: the ring items, compressed if COMPRESS names
+//! a codec (lz4 or zstd).  Anything that can read that from a socket (e.g.
+//! stdin2ring fed by a TCP listener) can receive it.  RATE and POLICY (and
+//! --hoist-rate, --hoist-policy and --hoist-fanout) work as for REMOTE.
+//! TLS is not supported.
+//!
+//! If the ring master can't connect, or the connection drops, it tries
+//! again, waiting 1 second at first and doubling the wait each time up to
+//! a minute.  This goes on until UNPUSH.  A remote that goes away while
+//! the ring is quiet is noticed by TCP keepalive within about a minute:
+//! PUSHES then shows the push waiting and it reconnects once there are
+//! data to send.
+//!
+//! Possible replies are:
+//!
+//! *   OK\n - the push has started.  See PUSHES for how it's going.
+//! *   ERROR reason string - The following are reasons this request can
+//!     fail:
+//!     -   The request came from a remote host.
+//!     -   The ring is not known to the ringmaster.
+//!     -   The ring is already pushed to that host and port.
+//!     -   The address or options are not valid.
+//!
+//! ### UNPUSH ringname host:port
+//!
+//! Stops pushing _ringname_ to _host_:_port_.  The request must be local.
+//! The reply is OK or ERROR if there's no such push.
+//!
+//! ### PUSHES ?TCL|JSON?
+//!
+//! This can be performed from local or remote hosts.  It returns
+//!
+//!   OK\n pushlist\n
+//!
+//! where pushlist describes the pushes.  By default it is a Tcl list with
+//! a key value list per push; with JSON it is a JSON array of objects with
+//! the same keys:
+//!
+//! *   ring, address - the ring and the host:port it's pushed to.
+//! *   state - connecting, connected or waiting (to try again).
+//! *   since - when the push last connected or lost its connection (or
+//!     started) in seconds since the epoch.
+//! *   connects - how many times the push has connected.
+//! *   bytes - how many bytes it has sent over all its connections.
+//! *   error - why it last failed, empty (null) if it hasn't.
+//!
+//! ### PROXY host ringname ?SERVICE name? ?TLS? ?COMPRESS codecs? ?RATE bytes? ?POLICY block|sample?
+//!
+//! Makes a local proxy ring named _ringname_@_host_ and fills it with the
+//! data of _ringname_ on _host_.  This is the receiving side of REMOTE, so
+//! NSCLDAQ's proxy ring programs are not needed.  The request must be
+//! local.
+//!
+//! The proxy ring is made with --proxy-size bytes of data and the
+//! --ring-mode permissions unless it already exists.  The ring master
+//! then:
+//!
+//! 1.   Looks up the remote ring master with _host_'s port manager.  That
+//!      is on the same port as ours unless _host_ is given as host:port.
+//!      The service looked up is RingMaster unless SERVICE names another.
+//! 2.   Sends REMOTE _ringname_ with the rest of the options (see REMOTE).
+//!      TLS needs --proxy-tls-ca.
+//! 3.   Puts the ring items it gets into the proxy ring as the ring's
+//!      producer.  Only whole ring items go in.  While the proxy ring is
+//!      full, reading from the remote stops, so the remote is held back
+//!      unless it was asked for POLICY sample.
+//!
+//! If any of this fails or the connection drops, the ring master tries
+//! again.  It waits 1 second at first and doubles the wait each time, up
+//! to a minute.  If the proxy ring was deleted it is made again.  This
+//! goes on until UNPROXY.  A remote that goes away while its ring is quiet
+//! is noticed by TCP keepalive within about a minute; until then PROXIES
+//! still shows the proxy connected.
+//!
+//! Possible replies are:
+//!
+//! *   OK\n - the proxy ring exists and the proxy has started.  See
+//!     PROXIES for how it's going.
+//! *   ERROR reason string - The following are reasons this request can
+//!     fail:
+//!     -   The request came from a remote host.
+//!     -   The proxy ring is already being filled by a proxy.
+//!     -   The proxy ring could not be made.
+//!     -   TLS was asked for and there's no --proxy-tls-ca.
+//!     -   The host, ring name or options are not valid.
+//!
+//! ### UNPROXY host ringname
+//!
+//! Stops filling _ringname_@_host_.  The proxy ring is left in place.  The
+//! request must be local.  The reply is OK or ERROR if there's no such
+//! proxy.
+//!
+//! ### PROXIES ?TCL|JSON?
+//!
+//! This can be performed from local or remote hosts.  It returns
+//!
+//!   OK\n proxylist\n
+//!
+//! where proxylist describes the proxies.  By default it is a Tcl list
+//! with a key value list per proxy; with JSON it is a JSON array of objects
+//! with the same keys:
+//!
+//! *   ring - the proxy ring.
+//! *   host, remote - the host and ring the proxy ring is filled from.
+//! *   state, since, connects, error - as for PUSHES.
+//! *   bytes - how many bytes the proxy has received over all its
+//!     connections (compressed if they were).
 //!
 //! ### LIST
 //!
@@ -178,7 +530,143 @@
 //! empty has an element for each consumer.  The elements of each consumer sublist are:
 //!         *  The consumer's process id
 //!         *  The number of bytes of backlog for that consumer.
+//!
+//! ### LIST ?VERBOSE? ?pattern?
+//!
+//! If a _pattern_ is given, only rings whose names match it are listed.
+//! The pattern is a glob (*, ? and [...] as in the shell) or, if written
+//! between slashes, e.g. /^e17/, an unanchored regular expression.  A
+//! lone VERBOSE is not a pattern; to match a ring named VERBOSE use a glob
+//! like VERBOS[E].  FAIL is returned for patterns that don't compile.
+//!
+//! With VERBOSE, the listing has more about the clients, looked up from
+//! the process table.  The elements LIST returns are all in the same
+//! positions so LIST parsers will work unchanged:
+//!
+//! *   Each consumer sublist has three more elements: the consumer's slot
+//!     index, the name of its executable and the user running it.
+//! *   The list of information about each ring has one more element,
+//!     a two element sublist with the executable name and user of the
+//!     producer.  This is empty if there is no producer.
+//!
+//! Names and users of processes that can't be found are empty.
+//!
+//! ### WATCH ?interval? ?pattern? ?TCL|JSON?
+//!
+//! This can be performed from local or remote hosts.  Rather than polling
+//! with LIST, the client holds the connection open and the ringmaster
+//! pushes lines to it until it closes the connection.  The reply is
+//!
+//!   OK\n
+//!
+//! followed by lines of two sorts:
+//!
+//! *   SNAPSHOT listing - every _interval_ seconds (default 1, from 0.1
+//!     to 86400, fractions allowed).  listing is what LIST replies with.
+//! *   EVENT type ringname ?connection pid? - as soon as something happens:
+//!     -   REGISTER ringname - a ring was registered or created.
+//!     -   UNREGISTER ringname - a ring was unregistered, deleted or
+//!         found to have vanished.
+//!     -   CONNECT ringname producer|consumer.n pid - a client connected.
+//!     -   DISCONNECT ringname producer|consumer.n pid - a client
+//!         disconnected, exited or had its slot reaped.
+//!
+//! With a _pattern_ (as for LIST) only matching rings are in snapshots and
+//! events.  With JSON, each line is instead a JSON object.  Snapshots are
+//! {"type": "snapshot", "rings": [...]} where each ring has the keys
+//! name, size, free_space, max_consumers, producer (null if none),
+//! max_queued, min_get and consumers (pid, backlog and slot).  Events
+//! are {"type": "event", "event": ..., "ring": ...} with client, slot
+//! and pid keys for client events.
+//!
+//! A leading number is always taken as the interval, and a trailing TCL
+//! or JSON as the format.
+//!
+//! Watchers must keep reading.  The connection is closed on a watcher that
+//! lets 1024 events pile up or blocks a write for 30 seconds.
+//!
+//! ### ALARMS ?pattern? ?TCL|JSON?
+//!
+//! This can be performed from local or remote hosts.  It returns
+//!
+//!   OK\n alarmlist\n
+//!
+//! where alarmlist describes the alarms that are currently raised for the
+//! rings matching _pattern_ (all rings if there is none).  By default it
+//! is a Tcl list with a key value list per alarm; with JSON it is a JSON
+//! array of objects with the same keys:
+//!
+//! *   ring - the ring.
+//! *   condition - FREE, BACKLOG or STALL (see --alarm-free, --alarm-backlog
+//!     and --alarm-stall).
+//! *   slot - the consumer slot of BACKLOG and STALL alarms, -1 (null) for FREE.
+//! *   pid - the consumer or, for FREE, the producer, -1 (null) if none.
+//! *   value - the free or backlog fraction, or the seconds stalled, as of
+//!     the latest check.
+//! *   since - when the alarm was raised in seconds since the epoch.
+//!
+//! ### STATUS ringname ?TCL|JSON?
+//!
+//! This can be performed from local or remote hosts.  It returns
+//!
+//!   OK\n statusline\n
+//!
+//! where statusline describes _ringname_ in more detail than LIST does.
+//! By default (or with TCL) it is a Tcl list of key value pairs (a dict);
+//! with JSON it is a JSON object with the same keys:
+//!
+//! *   name, file - the ring name and the path to its backing file.
+//! *   file_size, mtime - size of the file and its modification time in
+//!     seconds since the epoch.
+//! *   data_bytes, max_consumers, free_space - the ring geometry and the
+//!     bytes that can be put before the producer would stall.
+//! *   producer - pid, name, user and command line of the producer.  Empty
+//!     (null in JSON) if there is none.
+//! *   consumers - one element per used consumer slot with the slot, pid,
+//!     name, user, command, backlog in bytes and percent_full (backlog as a
+//!     percentage of data_bytes).
+//! *   clients - the clients the ringmaster knows about from CONNECT:
+//!     type (producer or consumer), slot for consumers, pid and the
+//!     process start_time used to detect pid reuse (-1/null if unknown).
+//!
+//! Process names, users and commands are empty (null in JSON) for processes
+//! that no longer exist.  FAIL is returned if the ring is not in the
+//! inventory or its file can no longer be mapped.
+//!
+//! ### STATS ringname ?TCL|JSON?
+//!
+//! This can be performed from local or remote hosts.  It returns
+//!
+//!   OK\n statsline\n
+//!
+//! where statsline gives the data rates of _ringname_'s clients computed
+//! from samples of their positions (see --stats-interval).  By default it
+//! is a Tcl dict; with JSON a JSON object with the same keys:
+//!
+//! *   ring - the ring name.
+//! *   producer - statistics of the producer, empty (null) if there is none.
+//! *   consumers - statistics of each consumer.
+//!
+//! The statistics of a client are:
+//!
+//! *   slot, pid - the consumer slot (0 for the producer) and process.
+//! *   rate - bytes per second put or gotten as of the latest sample.
+//! *   average - average bytes per second over the history.
+//! *   idle - seconds since the client last put or got data.
+//! *   total - bytes put or gotten since sampling of the client began.
+//! *   backlog - bytes a consumer has yet to get (0 for the producer).
+//! *   history - {time rate} pairs, oldest first, time in seconds since the
+//!     epoch (JSON objects with time and rate keys).
+//!
+//! A client that laps the ring between samples is under-reported.  FAIL is
+//! returned if the ring is not in the inventory or has not been sampled yet.
 pub mod tcllist;
 pub use tcllist::*;
 pub mod rings;
 pub use rings::*;
+pub mod logging;
+pub use logging::*;
+pub mod systemd;
+pub use systemd::*;
+pub mod hoist;
+pub use hoist::*;
@@ -0,0 +1,640 @@
+use log::kv::{self, Key, Value, VisitSource, VisitValue};
+use log::{Level, LevelFilter, Log, Metadata, Record};
+use serde_json::{Map, Value as JsonValue};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::os::unix::net::UnixDatagram;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+///
+/// The layout of log lines.
+///
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum LogFormat {
+    Text,
+    Json,
+}
+impl LogFormat {
+    ///
+    /// Parse a format name: text or json (case blind).
+    ///
+    pub fn parse(name: &str) -> Result<LogFormat, String> {
+        match name.to_lowercase().as_str() {
+            "text" => Ok(LogFormat::Text),
+            "json" => Ok(LogFormat::Json),
+            _ => Err(format!("Invalid log format {}: must be text or json", name)),
+        }
+    }
+}
+///
+/// Where log lines go:
+///
+/// *  File - a LogFile.
+/// *  Syslog - the local syslog daemon via its /dev/log socket.
+/// *  Stderr - stderr with the <n> priority prefixes journald understands
+///    (e.g. when run as a systemd service).
+///
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum LogTarget {
+    File,
+    Syslog,
+    Stderr,
+}
+impl LogTarget {
+    ///
+    /// Parse a target name: file, syslog or stderr (case blind).
+    ///
+    pub fn parse(name: &str) -> Result<LogTarget, String> {
+        match name.to_lowercase().as_str() {
+            "file" => Ok(LogTarget::File),
+            "syslog" => Ok(LogTarget::Syslog),
+            "stderr" => Ok(LogTarget::Stderr),
+            _ => Err(format!("Invalid log target {}: must be file, syslog or stderr", name)),
+        }
+    }
+}
+///
+/// Parse a log level name: off, error, warn, info, debug or trace (case
+/// blind).
+///
+pub fn parse_level(name: &str) -> Result<LevelFilter, String> {
+    name.parse::<LevelFilter>().map_err(|_| {
+        format!(
+            "Invalid log level {}: must be off, error, warn, info, debug or trace",
+            name
+        )
+    })
+}
+static LOGGER: OnceLock<Logger> = OnceLock::new();
+
+///
+/// Somewhere log lines can be written.
+///
+pub trait LogSink: Send {
+    ///
+    /// Write one line (without a newline) logged at level.
+    ///
+    fn write_line(&mut self, level: Level, line: &str) -> io::Result<()>;
+    ///
+    /// Whether text lines need our timestamp (false if the sink adds
+    /// its own).
+    ///
+    fn timestamps(&self) -> bool {
+        true
+    }
+}
+///
+/// A logger that formats records and writes them to a sink.
+///
+pub struct Logger {
+    format: LogFormat,
+    start: Instant,
+    timestamps: bool,
+    sink: Mutex<Box<dyn LogSink>>,
+}
+impl Logger {
+    pub fn new(sink: Box<dyn LogSink>, format: LogFormat) -> Logger {
+        Logger {
+            format,
+            start: Instant::now(),
+            timestamps: sink.timestamps(),
+            sink: Mutex::new(sink),
+        }
+    }
+    ///
+    /// Produce the line (without the newline) that logs a record.
+    ///
+    pub fn format(&self, record: &Record) -> String {
+        let mut fields = Fields(Vec::new());
+        let _ = record.key_values().visit(&mut fields);
+        match self.format {
+            LogFormat::Text => {
+                let mut line = String::new();
+                if self.timestamps {
+                    let elapsed = self.start.elapsed();
+                    let seconds = elapsed.as_secs();
+                    line.push_str(&format!(
+                        "[{:02}:{:02}:{:02}.{:03}] ",
+                        seconds / 3600,
+                        (seconds / 60) % 60,
+                        seconds % 60,
+                        elapsed.subsec_millis()
+                    ));
+                }
+                line.push_str(&format!("({}) {:6} {}", thread_name(), record.level(), record.args()));
+                for (key, value) in fields.0 {
+                    let text = match value {
+                        JsonValue::Null => continue,
+                        JsonValue::String(text) => text,
+                        value => value.to_string(),
+                    };
+                    if text.is_empty() || text.contains(char::is_whitespace) {
+                        line.push_str(&format!(" {}={:?}", key, text));
+                    } else {
+                        line.push_str(&format!(" {}={}", key, text));
+                    }
+                }
+                line
+            }
+            LogFormat::Json => {
+                let time = SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .map(|d| d.as_secs_f64())
+                    .unwrap_or(0.0);
+                let mut object = Map::new();
+                object.insert(String::from("time"), JsonValue::from(time));
+                object.insert(String::from("level"), JsonValue::from(record.level().as_str()));
+                object.insert(String::from("thread"), JsonValue::from(thread_name()));
+                object.insert(String::from("message"), JsonValue::from(record.args().to_string()));
+                for (key, value) in fields.0 {
+                    object.insert(key, value);
+                }
+                JsonValue::Object(object).to_string()
+            }
+        }
+    }
+}
+impl Log for Logger {
+    // The log crate's max level does the filtering.
+
+    fn enabled(&self, _: &Metadata) -> bool {
+        true
+    }
+    fn log(&self, record: &Record) {
+        let line = self.format(record);
+        let _ = self.sink.lock().unwrap().write_line(record.level(), &line);
+    }
+    fn flush(&self) {}
+}
+///
+/// When a LogFile is rotated:
+///
+/// *  max_bytes - when it would grow past this many bytes.
+/// *  max_age - when it has been open this long.
+///
+/// keep is the number of rotated files kept: path.1 (the newest) through
+/// path.keep.  Without max_bytes or max_age there's no rotation.
+///
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Rotation {
+    pub max_bytes: Option<u64>,
+    pub max_age: Option<Duration>,
+    pub keep: usize,
+}
+impl Default for Rotation {
+    fn default() -> Self {
+        Rotation {
+            max_bytes: None,
+            max_age: None,
+            keep: 5,
+        }
+    }
+}
+///
+/// A log file sink.  The file is appended to and rotated as its Rotation
+/// says.  When the reopen flag is set (e.g. by a SIGHUP handler) the file
+/// is closed and opened again by name before the next line is written;
+/// this supports logrotate's move and signal scheme.
+///
+pub struct LogFile {
+    path: PathBuf,
+    file: File,
+    written: u64,
+    opened: Instant,
+    rotation: Rotation,
+    reopen: Arc<AtomicBool>,
+    line_start: bool,
+}
+impl LogFile {
+    ///
+    /// Open (creating if need be) the log file for append.
+    ///
+    pub fn open(path: &str, rotation: Rotation) -> io::Result<LogFile> {
+        let file = OpenOptions::new().append(true).create(true).open(path)?;
+        let written = file.metadata()?.len();
+        Ok(LogFile {
+            path: PathBuf::from(path),
+            file,
+            written,
+            opened: Instant::now(),
+            rotation,
+            reopen: Arc::new(AtomicBool::new(false)),
+            line_start: true,
+        })
+    }
+    ///
+    /// The flag that, when set, makes the file reopen before the next
+    /// line.
+    ///
+    pub fn reopen_flag(&self) -> Arc<AtomicBool> {
+        Arc::clone(&self.reopen)
+    }
+    // Close and open the file again by name.
+
+    fn reopen(&mut self) -> io::Result<()> {
+        self.file = OpenOptions::new().append(true).create(true).open(&self.path)?;
+        self.written = self.file.metadata()?.len();
+        self.opened = Instant::now();
+        Ok(())
+    }
+    // Is it time to rotate before writing bytes more bytes?
+
+    fn rotation_due(&self, bytes: usize) -> bool {
+        let too_big = self
+            .rotation
+            .max_bytes
+            .is_some_and(|max| self.written > 0 && self.written + bytes as u64 > max);
+        let too_old = self
+            .rotation
+            .max_age
+            .is_some_and(|age| self.opened.elapsed() >= age);
+        too_big || too_old
+    }
+    // Shift path.n to path.n+1 dropping the oldest, move the file to
+    // path.1 and start a new one.
+
+    fn rotate(&mut self) -> io::Result<()> {
+        let rotated = |n: usize| {
+            let mut name = self.path.clone().into_os_string();
+            name.push(format!(".{}", n));
+            PathBuf::from(name)
+        };
+        if self.rotation.keep == 0 {
+            fs::remove_file(&self.path)?;
+        } else {
+            let _ = fs::remove_file(rotated(self.rotation.keep));
+            for n in (1..self.rotation.keep).rev() {
+                let _ = fs::rename(rotated(n), rotated(n + 1));
+            }
+            fs::rename(&self.path, rotated(1))?;
+        }
+        self.reopen()
+    }
+}
+impl Write for LogFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.line_start {
+            if self.reopen.swap(false, Ordering::SeqCst) {
+                self.reopen()?;
+            }
+            if self.rotation_due(buf.len()) {
+                self.rotate()?;
+            }
+        }
+        let n = self.file.write(buf)?;
+        self.written += n as u64;
+        self.line_start = n > 0 && buf[n - 1] == b'\n';
+        Ok(n)
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+impl LogSink for LogFile {
+    // One write per line so rotation never splits a line.
+
+    fn write_line(&mut self, _: Level, line: &str) -> io::Result<()> {
+        self.write_all(format!("{}\n", line).as_bytes())?;
+        self.flush()
+    }
+}
+///
+/// The syslog severity of a log level.
+///
+pub fn severity(level: Level) -> u8 {
+    match level {
+        Level::Error => 3,
+        Level::Warn => 4,
+        Level::Info => 6,
+        Level::Debug | Level::Trace => 7,
+    }
+}
+const SYSLOG_PATH: &str = "/dev/log";
+const LOG_DAEMON: u8 = 3; // syslog facility.
+
+///
+/// Logs to the local syslog daemon as facility daemon.  Messages are
+/// <priority>tag[pid]: line; the daemon adds the time and host.  If the
+/// daemon restarts we connect again.
+///
+pub struct SyslogSink {
+    path: String,
+    socket: UnixDatagram,
+    tag: String,
+}
+impl SyslogSink {
+    ///
+    /// Connect to the syslog daemon's /dev/log.
+    ///
+    pub fn connect(tag: &str) -> io::Result<SyslogSink> {
+        SyslogSink::connect_to(SYSLOG_PATH, tag)
+    }
+    ///
+    /// Connect to a syslog socket at path.
+    ///
+    pub fn connect_to(path: &str, tag: &str) -> io::Result<SyslogSink> {
+        let socket = UnixDatagram::unbound()?;
+        socket.connect(path)?;
+        Ok(SyslogSink {
+            path: String::from(path),
+            socket,
+            tag: String::from(tag),
+        })
+    }
+}
+impl LogSink for SyslogSink {
+    fn write_line(&mut self, level: Level, line: &str) -> io::Result<()> {
+        let message = format!(
+            "<{}>{}[{}]: {}",
+            LOG_DAEMON * 8 + severity(level),
+            self.tag,
+            std::process::id(),
+            line
+        );
+        if self.socket.send(message.as_bytes()).is_err() {
+            let socket = UnixDatagram::unbound()?;
+            socket.connect(&self.path)?;
+            self.socket = socket;
+            self.socket.send(message.as_bytes())?;
+        }
+        Ok(())
+    }
+    fn timestamps(&self) -> bool {
+        false
+    }
+}
+///
+/// Logs to stderr prefixing each line with <severity> as journald (and
+/// systemd's capture of service output) understands.
+///
+pub struct StderrSink;
+impl LogSink for StderrSink {
+    fn write_line(&mut self, level: Level, line: &str) -> io::Result<()> {
+        let mut stderr = io::stderr().lock();
+        writeln!(stderr, "<{}>{}", severity(level), line)?;
+        stderr.flush()
+    }
+    fn timestamps(&self) -> bool {
+        false
+    }
+}
+///
+/// Parse a size in bytes with an optional k, M or G (powers of 1024)
+/// suffix.
+///
+pub fn parse_size(text: &str) -> Result<u64, String> {
+    let (digits, scale) = match text.chars().last() {
+        Some('k') | Some('K') => (&text[..text.len() - 1], 1024),
+        Some('m') | Some('M') => (&text[..text.len() - 1], 1024 * 1024),
+        Some('g') | Some('G') => (&text[..text.len() - 1], 1024 * 1024 * 1024),
+        _ => (text, 1),
+    };
+    digits
+        .parse::<u64>()
+        .ok()
+        .and_then(|n| n.checked_mul(scale))
+        .ok_or_else(|| format!("Invalid size {}: must be a number of bytes optionally followed by k, M or G", text))
+}
+///
+/// Make a logger the log crate's logger and set the level.  Err if
+/// there already is a logger.
+///
+pub fn init(logger: Logger, level: LevelFilter) -> Result<(), String> {
+    LOGGER
+        .set(logger)
+        .map_err(|_| String::from("The logger is already initialized"))?;
+    log::set_logger(LOGGER.get().unwrap()).map_err(|e| e.to_string())?;
+    log::set_max_level(level);
+    Ok(())
+}
+// Collects the structured fields of a record, keeping numbers, booleans
+// and nulls (e.g. None) as such for JSON.
+
+struct Fields(Vec<(String, JsonValue)>);
+impl<'kvs> VisitSource<'kvs> for Fields {
+    fn visit_pair(&mut self, key: Key<'kvs>, value: Value<'kvs>) -> Result<(), kv::Error> {
+        let mut json = ToJson(JsonValue::Null);
+        value.visit(&mut json)?;
+        self.0.push((key.to_string(), json.0));
+        Ok(())
+    }
+}
+struct ToJson(JsonValue);
+impl<'v> VisitValue<'v> for ToJson {
+    fn visit_any(&mut self, value: Value) -> Result<(), kv::Error> {
+        self.0 = JsonValue::from(value.to_string());
+        Ok(())
+    }
+    fn visit_null(&mut self) -> Result<(), kv::Error> {
+        self.0 = JsonValue::Null;
+        Ok(())
+    }
+    fn visit_u64(&mut self, value: u64) -> Result<(), kv::Error> {
+        self.0 = JsonValue::from(value);
+        Ok(())
+    }
+    fn visit_i64(&mut self, value: i64) -> Result<(), kv::Error> {
+        self.0 = JsonValue::from(value);
+        Ok(())
+    }
+    fn visit_f64(&mut self, value: f64) -> Result<(), kv::Error> {
+        self.0 = JsonValue::from(value);
+        Ok(())
+    }
+    fn visit_bool(&mut self, value: bool) -> Result<(), kv::Error> {
+        self.0 = JsonValue::from(value);
+        Ok(())
+    }
+    fn visit_str(&mut self, value: &str) -> Result<(), kv::Error> {
+        self.0 = JsonValue::from(value);
+        Ok(())
+    }
+}
+// Identify the current thread: its name if it has one else its id.
+
+fn thread_name() -> String {
+    let thread = std::thread::current();
+    match thread.name() {
+        Some(name) => String::from(name),
+        None => format!("{:?}", thread.id()),
+    }
+}
+#[cfg(test)]
+mod logging_tests {
+    use super::*;
+    use log::Level;
+
+    // Discards lines but wants timestamps like a file.
+
+    struct Discard;
+    impl LogSink for Discard {
+        fn write_line(&mut self, _: Level, _: &str) -> io::Result<()> {
+            Ok(())
+        }
+    }
+    fn format(format: LogFormat, fields: &[(&str, &str)]) -> String {
+        let logger = Logger::new(Box::new(Discard), format);
+        logger.format(
+            &Record::builder()
+                .level(Level::Warn)
+                .args(format_args!("PID spoof attempt"))
+                .key_values(&fields)
+                .build(),
+        )
+    }
+    #[test]
+    fn parse_1() {
+        assert_eq!(LogFormat::Json, LogFormat::parse("JSON").unwrap());
+        assert_eq!(LogFormat::Text, LogFormat::parse("text").unwrap());
+        assert!(LogFormat::parse("xml").is_err());
+        assert_eq!(LevelFilter::Debug, parse_level("debug").unwrap());
+        assert_eq!(LevelFilter::Off, parse_level("OFF").unwrap());
+        assert!(parse_level("loud").is_err());
+        assert_eq!(LogTarget::Syslog, LogTarget::parse("SysLog").unwrap());
+        assert_eq!(LogTarget::File, LogTarget::parse("file").unwrap());
+        assert!(LogTarget::parse("printer").is_err());
+    }
+    #[test]
+    fn text_1() {
+        let line = format(LogFormat::Text, &[("event", "pid_spoof"), ("peer", "a b")]);
+        assert!(line.starts_with("[00:00:00."));
+        assert!(line.ends_with("WARN   PID spoof attempt event=pid_spoof peer=\"a b\""));
+    }
+    #[test]
+    fn json_1() {
+        let line = format(LogFormat::Json, &[("event", "pid_spoof"), ("ring", "fox")]);
+        let json: JsonValue = serde_json::from_str(&line).unwrap();
+        assert_eq!("WARN", json["level"]);
+        assert_eq!("PID spoof attempt", json["message"]);
+        assert_eq!("pid_spoof", json["event"]);
+        assert_eq!("fox", json["ring"]);
+        assert!(json["time"].as_f64().unwrap() > 0.0);
+    }
+    // A fresh log file path in the temp directory.
+
+    fn log_path(name: &str) -> String {
+        let path = std::env::temp_dir().join(format!("logging_{}_{}", name, std::process::id()));
+        for n in 0..4 {
+            let _ = fs::remove_file(format!("{}.{}", path.display(), n));
+        }
+        let _ = fs::remove_file(&path);
+        String::from(path.to_str().unwrap())
+    }
+    #[test]
+    fn size_1() {
+        assert_eq!(100, parse_size("100").unwrap());
+        assert_eq!(2048, parse_size("2k").unwrap());
+        assert_eq!(3 * 1024 * 1024, parse_size("3M").unwrap());
+        assert!(parse_size("big").is_err());
+        assert!(parse_size("").is_err());
+    }
+    #[test]
+    fn rotate_1() {
+        // Rotates by size keeping 2:
+
+        let path = log_path("rotate");
+        let rotation = Rotation {
+            max_bytes: Some(10),
+            max_age: None,
+            keep: 2,
+        };
+        let mut file = LogFile::open(&path, rotation).unwrap();
+        for line in ["one\n", "two\n", "three\n", "four\n", "five\n"] {
+            file.write_all(line.as_bytes()).unwrap();
+        }
+        file.write_all(b"six\n").unwrap();
+        assert_eq!("six\n", fs::read_to_string(&path).unwrap());
+        assert_eq!("four\nfive\n", fs::read_to_string(format!("{}.1", path)).unwrap());
+        assert_eq!("three\n", fs::read_to_string(format!("{}.2", path)).unwrap());
+        assert!(fs::metadata(format!("{}.3", path)).is_err());
+        for n in 1..3 {
+            fs::remove_file(format!("{}.{}", path, n)).unwrap();
+        }
+        fs::remove_file(&path).unwrap();
+    }
+    #[test]
+    fn rotate_2() {
+        // Rotates by age; lines are not split across files:
+
+        let path = log_path("age");
+        let rotation = Rotation {
+            max_bytes: None,
+            max_age: Some(Duration::from_millis(0)),
+            keep: 1,
+        };
+        let mut file = LogFile::open(&path, rotation).unwrap();
+        file.write_all(b"one ").unwrap();
+        file.write_all(b"line\n").unwrap();
+        file.write_all(b"two\n").unwrap();
+        assert_eq!("two\n", fs::read_to_string(&path).unwrap());
+        assert_eq!("one line\n", fs::read_to_string(format!("{}.1", path)).unwrap());
+        fs::remove_file(format!("{}.1", path)).unwrap();
+        fs::remove_file(&path).unwrap();
+    }
+    #[test]
+    fn reopen_1() {
+        // As logrotate does: move the file then signal:
+
+        let path = log_path("reopen");
+        let moved = format!("{}.0", path);
+        let mut file = LogFile::open(&path, Rotation::default()).unwrap();
+        file.write_all(b"one\n").unwrap();
+        fs::rename(&path, &moved).unwrap();
+        file.write_all(b"two\n").unwrap();
+        file.reopen_flag().store(true, Ordering::SeqCst);
+        file.write_all(b"three\n").unwrap();
+        assert_eq!("one\ntwo\n", fs::read_to_string(&moved).unwrap());
+        assert_eq!("three\n", fs::read_to_string(&path).unwrap());
+        fs::remove_file(&moved).unwrap();
+        fs::remove_file(&path).unwrap();
+    }
+    #[test]
+    fn syslog_1() {
+        let path = log_path("syslog");
+        let daemon = UnixDatagram::bind(&path).unwrap();
+        let mut sink = SyslogSink::connect_to(&path, "ringmaster").unwrap();
+        assert!(!sink.timestamps());
+        sink.write_line(Level::Warn, "PID spoof attempt").unwrap();
+        let mut buffer = [0u8; 256];
+        let n = daemon.recv(&mut buffer).unwrap();
+        assert_eq!(
+            format!("<28>ringmaster[{}]: PID spoof attempt", std::process::id()),
+            String::from_utf8_lossy(&buffer[..n])
+        );
+        assert_eq!(3, severity(Level::Error));
+        assert_eq!(7, severity(Level::Trace));
+        fs::remove_file(&path).unwrap();
+    }
+    #[test]
+    fn text_2() {
+        // Sinks that timestamp for us get no timestamp:
+
+        let logger = Logger::new(Box::new(StderrSink), LogFormat::Text);
+        let line = logger.format(&Record::builder().level(Level::Info).args(format_args!("hi")).build());
+        assert!(line.starts_with('('));
+        assert!(line.ends_with("INFO   hi"));
+    }
+    #[test]
+    fn json_2() {
+        // Numbers stay numbers and None is null (left out of text):
+
+        let logger = Logger::new(Box::new(Discard), LogFormat::Json);
+        let fields: &[(&str, Option<u32>)] = &[("pid", Some(123)), ("ring", None)];
+        let line = logger.format(
+            &Record::builder()
+                .level(Level::Info)
+                .args(format_args!("connect"))
+                .key_values(&fields)
+                .build(),
+        );
+        let json: JsonValue = serde_json::from_str(&line).unwrap();
+        assert_eq!(123, json["pid"]);
+        assert!(json["ring"].is_null());
+        let text = Logger::new(Box::new(Discard), LogFormat::Text);
+        let line = text.format(&Record::builder().key_values(&fields).build());
+        assert!(line.ends_with(" pid=123"));
+    }
+}
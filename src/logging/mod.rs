@@ -0,0 +1,30 @@
+//!
+//! The logging module is the ringmaster's log backend for the log
+//! crate.  It writes one line per record in either of two formats:
+//!
+//! *   Text - the format simple_logging used:
+//!     `[hh:mm:ss.mmm] (thread) LEVEL  message`, time being the time
+//!     since the logger started.  Structured fields are appended as
+//!     key=value.
+//! *   Json - one JSON object per line with the keys time (seconds since
+//!     the epoch), level, thread, message and one key per structured field.
+//!
+//! Structured fields are the log crate's key values, e.g.
+//!
+//! ```
+//!   use log::info;
+//!
+//!   let peer = "127.0.0.1:1234";
+//!   info!(event = "request", verb = "LIST", peer = peer; "LIST request from {}", peer);
+//! ```
+//!
+//! Records that describe the same sort of happening carry the same event
+//! field so that log aggregators can select them.
+//!
+//! Lines go to a LogSink: a LogFile, the syslog daemon (SyslogSink) or
+//! stderr formatted for journald (StderrSink).
+//!
+//! LogFile is a file sink that can rotate the file by size and age and
+//! reopen it by name when asked to (e.g. on SIGHUP).
+pub mod logging;
+pub use self::logging::*;
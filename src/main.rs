@@ -1,20 +1,52 @@
 pub mod tcllist;
 use clap::*;
-use log::{error, info};
+use log::{error, info, warn};
 use nscldaq_ringbuffer::ringbuffer;
+use nscldaq_ringmaster::logging::{
+    self, LogFile, LogFormat, LogSink, LogTarget, Logger, Rotation, StderrSink, SyslogSink,
+};
+use nscldaq_ringmaster::systemd::{self, Notifier};
+use nscldaq_ringmaster::hoist::compress::compress::{Codec, Compressor, Decompressor};
+use nscldaq_ringmaster::hoist::config::config::HoistConfig;
+use nscldaq_ringmaster::hoist::fanout::fanout::{Fanout, HoistMux};
+use nscldaq_ringmaster::hoist::link::link::{set_keepalive, Backoff, Link, LinkReader, LinkWriter};
+use nscldaq_ringmaster::hoist::proxy::proxy::{find_service, Proxy, ProxyTable, ProxyTarget, RemoteReply};
+use nscldaq_ringmaster::hoist::push::push::{Push, PushTable, PushTarget};
+use nscldaq_ringmaster::hoist::queue::queue::{HoistQueue, ItemSplitter};
+use nscldaq_ringmaster::hoist::remote::remote::RemoteOptions;
+use nscldaq_ringmaster::hoist::throttle::throttle::{HoistLimits, Policy, Throttle};
+use nscldaq_ringmaster::hoist::tls::tls::{TlsClient, TlsServer};
+use nscldaq_ringmaster::rings::alarms::alarms::{
+    Alarm, AlarmConfig, AlarmMonitor, ConsumerSample, RingSample, Thresholds,
+};
+use nscldaq_ringmaster::rings::events::events::{EventBus, RingEvent};
+use nscldaq_ringmaster::rings::instance::instance::InstanceLock;
 use nscldaq_ringmaster::rings::inventory;
+use nscldaq_ringmaster::rings::pattern::pattern::RingPattern;
+use nscldaq_ringmaster::rings::positions::positions::RingPositions;
+use nscldaq_ringmaster::rings::process::process::{
+    describe_processes, may_free, ProcessDescription, ProcessIdentity,
+};
+use nscldaq_ringmaster::rings::reaper;
+use nscldaq_ringmaster::rings::ringfile;
 use nscldaq_ringmaster::rings::rings;
+use nscldaq_ringmaster::rings::stats::stats::StatsCollector;
+use nscldaq_ringmaster::rings::status::status::RingStatusReport;
 //use portman_client;
-//use simple_logging;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::fs;
-use std::io::{BufRead, BufReader, Write};
-use std::net::{Ipv4Addr, Ipv6Addr, Shutdown, SocketAddr, TcpListener, TcpStream};
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, Shutdown, SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::panic;
 use std::path::{Path, PathBuf};
 use std::process;
 use std::str;
-use std::sync::{Arc, Mutex};
+use serde_json::{json, Value};
+use std::sync::atomic::{AtomicBool, AtomicU16, Ordering};
+use std::sync::mpsc::RecvTimeoutError;
+use std::sync::{Arc, LazyLock, Mutex};
 use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 use filedescriptor::FileDescriptor;
 
 
@@ -24,12 +56,76 @@ use filedescriptor::FileDescriptor;
 type RingInventory = HashMap<String, rings::rings::RingBufferInfo>;
 type SafeInventory = Arc<Mutex<RingInventory>>;
 type SafeStream = Arc<Mutex<TcpStream>>;
+type SafeEvents = Arc<Mutex<EventBus>>;
+type SafeAlarms = Arc<Mutex<AlarmMonitor>>;
+type SafeStats = Arc<Mutex<StatsCollector>>;
+type SafePort = Arc<AtomicU16>; // The port we're advertised on.
+
+const DEFAULT_WATCH_INTERVAL: Duration = Duration::from_secs(1);
+const MIN_WATCH_INTERVAL: Duration = Duration::from_millis(100);
+const MAX_WATCH_INTERVAL: Duration = Duration::from_secs(86400);
+const WATCH_WRITE_TIMEOUT: Duration = Duration::from_secs(30); // Then the watcher is stuck.
+const SYSTEMD_STATUS_INTERVAL: Duration = Duration::from_secs(10);
+const PORTMAN_CHECK_INTERVAL: Duration = Duration::from_secs(5);
+const PORTMAN_MIN_BACKOFF: Duration = Duration::from_secs(1);
+const PORTMAN_MAX_BACKOFF: Duration = Duration::from_secs(60);
+const TLS_HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+const HOIST_CHUNK: usize = 65536; // A pipe's worth of hoisted data.
+const LINK_CONNECT_TIMEOUT: Duration = Duration::from_secs(10); // Pushes and proxies.
+const LINK_MIN_BACKOFF: Duration = Duration::from_secs(1);
+const LINK_MAX_BACKOFF: Duration = Duration::from_secs(60);
+const PROXY_PUT_WAIT: Duration = Duration::from_millis(100); // Between checks for UNPROXY.
+const LINK_KEEPALIVE_IDLE: Duration = Duration::from_secs(30); // Links: quiet this long, then probe
+const LINK_KEEPALIVE_INTERVAL: Duration = Duration::from_secs(10); // this often,
+const LINK_KEEPALIVE_PROBES: u32 = 3; // giving up after this many go unanswered.
 struct RingInfo {
     name: String,
     size: usize,
     max_consumers: usize,
     min_get: usize,
     info: ringbuffer::RingStatus,
+    slots: Vec<usize>, // Slot of each element of info.consumer_usage.
+}
+///
+/// What a server thread knows about the client on the other end of
+/// its connection:
+///
+/// *  pid - the pid the client has identified itself as, UNUSED_ENTRY
+///    until it does.
+/// *  identities - the identity (pid and start time) of each pid as of
+///    its first CONNECT.  Pids get reused so this is what we check before
+///    freeing slots on the client's behalf.
+/// *  connections - the CONNECT/DISCONNECT operations done by the client
+///    keyed by ring name.  This is used to kill off any slot reservations
+///    the client has made if it closes the connection (presumed dead).
+///    Clients making CONNECT/DISCONNECT are obligated to hold the
+///    connection until they're done with what they've connected to.
+///
+struct ClientSession {
+    pid: u32,
+    identities: HashMap<u32, ProcessIdentity>,
+    connections: HashMap<String, Vec<rings::rings::Client>>,
+}
+impl ClientSession {
+    fn new() -> ClientSession {
+        ClientSession {
+            pid: ringbuffer::UNUSED_ENTRY,
+            identities: HashMap::new(),
+            connections: HashMap::new(),
+        }
+    }
+    // Record the identity of pid unless we already have it.
+
+    fn record_identity(&mut self, pid: u32) {
+        if let std::collections::hash_map::Entry::Vacant(entry) = self.identities.entry(pid) {
+            if let Some(identity) = ProcessIdentity::of(pid) {
+                entry.insert(identity);
+            }
+        }
+    }
+    fn identity(&self, pid: u32) -> Option<ProcessIdentity> {
+        self.identities.get(&pid).copied()
+    }
 }
 ///
 /// This holds the command line options:
@@ -39,19 +135,134 @@ struct ProgramOptions {
     portman: u16,
     directory: String,
     log_filename: String,
+    log_target: LogTarget,
+    log_level: log::LevelFilter,
+    log_format: LogFormat,
+    log_rotation: Rotation,
+    reap_interval: u64,
+    reap_dry_run: bool,
+    unregister_policy: rings::rings::KillPolicy,
+    kill_grace: u64,
+    ring_mode: u32,
+    lock_dir: String,
+    listen: Vec<IpAddr>,
+    service_name: String,
+    tls: Option<TlsServer>,
+    tls_required: bool,
+    hoist_limits: HoistLimits,
+    hoist_buffer: usize,
+    hoist_fanout: bool,
+    hoist_config: HoistConfig,
+    proxy_size: u32,
+    proxy_tls: Option<TlsClient>,
+    takeover: bool,
+    alarms: AlarmConfig,
+    alarm_interval: u64,
+    alarm_command: Option<String>,
+    stats_interval: u64,
+    stats_history: usize,
+}
+impl ProgramOptions {
+    // The defaults, before the command line is looked at.
+
+    fn new() -> ProgramOptions {
+        ProgramOptions {
+            portman: 30000,
+            directory: String::from("/dev/shm"),
+            log_filename: String::from("/var/log/nscldaq/ringmaster.log"),
+            log_target: LogTarget::File,
+            log_level: log::LevelFilter::Info,
+            log_format: LogFormat::Text,
+            log_rotation: Rotation::default(),
+            reap_interval: 60,
+            reap_dry_run: false,
+            unregister_policy: rings::rings::KillPolicy::Kill,
+            kill_grace: 2,
+            ring_mode: 0o666,
+            lock_dir: String::from("/run/lock"),
+            listen: vec![IpAddr::V4(Ipv4Addr::UNSPECIFIED)],
+            service_name: String::from(SERVICE_NAME),
+            tls: None,
+            tls_required: false,
+            hoist_limits: HoistLimits::default(),
+            hoist_buffer: 4 * 1024 * 1024,
+            hoist_fanout: false,
+            hoist_config: HoistConfig::new(),
+            proxy_size: 8 * 1024 * 1024,
+            proxy_tls: None,
+            takeover: false,
+            alarms: AlarmConfig::new(Thresholds::default()),
+            alarm_interval: 5,
+            alarm_command: None,
+            stats_interval: 1,
+            stats_history: 60,
+        }
+    }
 }
-static  SERVICE_NAME : &str = "RingMaster";
+static  SERVICE_NAME : &str = "RingMaster"; // Default advertised service.
+static HOIST_MUX: LazyLock<HoistMux> = LazyLock::new(HoistMux::new); // Fan-out hoists by ring.
+static PUSHES: LazyLock<PushTable> = LazyLock::new(PushTable::new); // Outbound hoists.
+static PROXIES: LazyLock<ProxyTable> = LazyLock::new(ProxyTable::new); // Proxy rings we fill.
 fn main() {
     let options = process_options();
+    // Take this before starting anything that might inherit it:
+
+    let notifier = Notifier::from_environment();
+    let mut activated = systemd::listeners().unwrap_or_else(|e| {
+        eprintln!("Unable to use the sockets systemd passed: {}", e);
+        process::exit(-1);
+    });
+    let log_sink: Box<dyn LogSink> = match options.log_target {
+        LogTarget::File => {
+            let log_file = LogFile::open(&options.log_filename, options.log_rotation).unwrap();
+            // SIGHUP reopens the log file (e.g. after logrotate moves it):
+
+            signal_hook::flag::register(signal_hook::consts::SIGHUP, log_file.reopen_flag()).unwrap();
+            Box::new(log_file)
+        }
+        LogTarget::Syslog => match SyslogSink::connect("ringmaster") {
+            Ok(sink) => Box::new(sink),
+            Err(e) => {
+                eprintln!("Unable to connect to syslog: {}", e);
+                process::exit(-1);
+            }
+        },
+        LogTarget::Stderr => Box::new(StderrSink),
+    };
+    logging::init(Logger::new(log_sink, options.log_format), options.log_level).unwrap();
+    info!("Ringmaster Options {:?}", options);
+
+    // Only one ringmaster per ring directory.  We hold the lock until we
+    // exit:
+
+    let lock_path = InstanceLock::lock_path(&options.lock_dir, &options.directory);
+    let takeover = if options.takeover {
+        Some(Duration::from_secs(options.kill_grace))
+    } else {
+        None
+    };
+    let _instance_lock = InstanceLock::acquire(&lock_path, takeover).unwrap_or_else(|e| {
+        error!("{}", e);
+        eprintln!("{}", e);
+        process::exit(-1);
+    });
+    info!("Holding the instance lock {}", lock_path.display());
+
     // If the ringmaster is  already running refuse to continue:
 
-    if ringmaster_running(options.portman) {
-        eprintln!("The ring master is already running/advertised");
-        std::process::exit(-1);
+    match ringmaster_running(options.portman, &options.service_name) {
+        Ok(false) => {}
+        Ok(true) => {
+            error!("The ring master ({}) is already running/advertised", options.service_name);
+            eprintln!("The ring master ({}) is already running/advertised", options.service_name);
+            std::process::exit(-1);
+        }
+        Err(e) => {
+            error!("{}", e);
+            eprintln!("{}", e);
+            std::process::exit(-1);
+        }
     }
-    
-    simple_logging::log_to_file(&options.log_filename, log::LevelFilter::Info).unwrap();
-    info!("Ringmaster Options {:#?}", options);
     info!(
         "Ringmaster doing inventory of existing rings on {}",
         options.directory
@@ -62,7 +273,7 @@ fn main() {
     info!("Obtaining port from portmanager...");
     let mut port_man = portman_client::Client::new(options.portman);
     let service_port: u16;
-    match port_man.get(SERVICE_NAME) {
+    match port_man.get(&options.service_name) {
         Ok(p) => {
             service_port = p;
         }
@@ -76,11 +287,15 @@ fn main() {
         }
     }
     info!(
-        "Ringmaster will handle connections on listen port {}",
-        service_port
+        "Ringmaster will handle connections on listen port {} as {}",
+        service_port, options.service_name
     );
+    if activated.len() > 1 {
+        warn!("systemd passed {} sockets; only the first is used", activated.len());
+    }
+    let activated = if activated.is_empty() { None } else { Some(activated.remove(0)) };
 
-    server(service_port, options, ring_inventory);
+    server(service_port, options, ring_inventory, notifier, activated, port_man);
 }
 ///
 /// Main server function.  We make a listener, and process requests
@@ -90,27 +305,140 @@ fn main() {
 /// *   Our service port.
 /// *   The directory so that we know where the ringbuffers are.
 /// *   A mutable reference to the ringbufer inventory to operate on.
+/// *   The systemd notifier if systemd started us.
+/// *   The listener systemd passed us if we were socket activated.  We
+///     listen on it instead of the service port unless it's a different
+///     port, in which case we listen on both so portman's clients can
+///     still find us.
+/// *   The port manager client holding our service advertisement.  Once
+///     everything is started we supervise it (see supervise_portman).
 ///
-fn server(listen_port: u16, options: ProgramOptions, ring_inventory: RingInventory) {
-    let listener = TcpListener::bind(format!("0.0.0.0:{}", listen_port));
+fn server(
+    listen_port: u16,
+    options: ProgramOptions,
+    ring_inventory: RingInventory,
+    notifier: Option<Notifier>,
+    activated: Option<TcpListener>,
+    port_man: portman_client::Client,
+) {
+    let activated_port = activated.as_ref().and_then(|l| l.local_addr().ok()).map(|a| a.port());
+    if let Some(port) = activated_port {
+        info!("Listening on the socket systemd passed us (port {})", port);
+    }
+    let (listeners, extra_listener) = match activated {
+        Some(listener) if activated_port == Some(listen_port) => (Ok(vec![listener]), None),
+        other => (bind_listeners(&options.listen, listen_port), other),
+    };
     let sinventory = Arc::new(Mutex::new(ring_inventory));
-    if let Err(l) = listener {
-        error!("Failed to listen on {} : {}", listen_port, l.to_string());
+    let sevents = Arc::new(Mutex::new(EventBus::new()));
+    let salarms = Arc::new(Mutex::new(AlarmMonitor::new(options.alarms.clone())));
+    let sstats = Arc::new(Mutex::new(StatsCollector::new(options.stats_history)));
+    let sport = Arc::new(AtomicU16::new(listen_port));
+    let listeners = listeners.unwrap_or_else(|e| {
+        error!("{}", e);
         process::exit(-1);
+    });
+    start_reaper(&options, &sinventory, &sevents);
+    start_alarm_monitor(&options, &sinventory, &salarms);
+    start_stats_sampler(&options, &sinventory, &sstats);
+    start_configured_hoists(&options, &sinventory, &sevents);
+    if let Some(notifier) = notifier {
+        start_systemd_notifier(notifier, &sinventory, &sport);
+    }
+    if let Some(listener) = extra_listener {
+        warn!(
+            "systemd's socket is on port {} but portman gave us {}; listening on both",
+            activated_port.unwrap_or_default(),
+            listen_port
+        );
+        spawn_acceptors(vec![listener], &options, &sinventory, &sevents, &salarms, &sstats, None);
+    }
+    // Our own listener is retired if portman moves us to another port
+    // (systemd's is not ours to retire):
+
+    let retire = if activated_port == Some(listen_port) {
+        None
+    } else {
+        Some(Arc::clone(&sport))
+    };
+    spawn_acceptors(listeners, &options, &sinventory, &sevents, &salarms, &sstats, retire);
+    supervise_portman(port_man, options, sinventory, sevents, salarms, sstats, sport);
+}
+///
+/// Listen on a port at each of a set of addresses.
+///
+fn bind_listeners(addresses: &[IpAddr], port: u16) -> Result<Vec<TcpListener>, String> {
+    addresses
+        .iter()
+        .map(|address| {
+            TcpListener::bind(SocketAddr::new(*address, port))
+                .map_err(|e| format!("Failed to listen on {} : {}", SocketAddr::new(*address, port), e))
+        })
+        .collect()
+}
+///
+/// Start a thread accepting clients on each listener (see accept_clients).
+///
+fn spawn_acceptors(
+    listeners: Vec<TcpListener>,
+    options: &ProgramOptions,
+    inventory: &SafeInventory,
+    events: &SafeEvents,
+    alarms: &SafeAlarms,
+    stats: &SafeStats,
+    retire: Option<SafePort>,
+) {
+    for listener in listeners {
+        if let Ok(address) = listener.local_addr() {
+            info!("Listening for requests on {}", address);
+        }
+        let options = options.clone();
+        let inventory = Arc::clone(inventory);
+        let events = Arc::clone(events);
+        let alarms = Arc::clone(alarms);
+        let stats = Arc::clone(stats);
+        let retire = retire.clone();
+        thread::spawn(move || accept_clients(listener, options, inventory, events, alarms, stats, retire));
     }
-    for client in listener.unwrap().incoming() {
+}
+///
+/// Accept clients on a listener, handling each client's requests in its
+/// own thread.  If retire is given, the listener is on our advertised
+/// port and we stop once that's no longer retire's port (after the
+/// connection supervise_portman makes to wake us up).
+///
+fn accept_clients(
+    listener: TcpListener,
+    options: ProgramOptions,
+    sinventory: SafeInventory,
+    sevents: SafeEvents,
+    salarms: SafeAlarms,
+    sstats: SafeStats,
+    retire: Option<SafePort>,
+) {
+    let my_port = listener.local_addr().map(|a| a.port()).unwrap_or_default();
+    for client in listener.incoming() {
+        if retire.as_ref().is_some_and(|port| port.load(Ordering::SeqCst) != my_port) {
+            info!("No longer listening on port {}", my_port);
+            return;
+        }
         match client {
             Ok(stream) => {
                 let sstream = Arc::new(Mutex::new(stream));
                 let client_stream = Arc::clone(&sstream);
                 let client_inventory = Arc::clone(&sinventory);
+                let client_events = Arc::clone(&sevents);
+                let client_alarms = Arc::clone(&salarms);
+                let client_stats = Arc::clone(&sstats);
                 let thread_options = options.clone();
                 thread::spawn(move || {
                     handle_request(
                         client_stream,
-                        thread_options.directory,
-                        thread_options.portman,
+                        thread_options,
                         client_inventory,
+                        client_events,
+                        client_alarms,
+                        client_stats,
                     )
                 });
             }
@@ -131,34 +459,29 @@ fn server(listen_port: u16, options: ProgramOptions, ring_inventory: RingInvento
 /// functions specific to the request.  Those functions are expected to
 /// reply to the client and, if necessary, shutdown the stream.
 ///
-fn handle_request(client_stream: SafeStream, dir: String, portman: u16, inventory: SafeInventory) {
+fn handle_request(
+    client_stream: SafeStream,
+    options: ProgramOptions,
+    inventory: SafeInventory,
+    events: SafeEvents,
+    alarms: SafeAlarms,
+    stats: SafeStats,
+) {
     // We can hang on to the stream:
 
     let mut stream = client_stream.lock().unwrap();
+    let dir = options.directory.clone();
 
     // To read a line, make a BufReader as we've done in other.  We'll then
     // use get_request to read the line and return the busted up request
     // as a vector of strings.
 
-    let mut pid = ringbuffer::UNUSED_ENTRY; // Records register pid
-
-    // This map will record the CONNECT/DISCONNECT operations done
-    // by the client.  Note that REMOTE must be done by a remote
-    // client and hence will not every have any connections (whew):
-    //
-    // This is used to kill off any slot reservations the client
-    // has made if it closes the connection (presumed dead).
-    // clients making CONNECT/DISCONNECT are obligatd to hold the
-    // connection until they're done with what they've connected to.
-    //
-    // The key is the path to a ringbuffer and the value is
-    // a vector of Client objects which record producer/consumer
-    // information for the client.
-    //
-    // CONNECT obviously add to this and DISCONNECT obviously removes from
-    // this.
-
-    let mut connections = HashMap::<String, Vec<rings::rings::Client>>::new();
+    // The client's pid, the identities of the processes it connected
+    // and the CONNECT/DISCONNECT operations it has done (see ClientSession).
+    // Note that REMOTE must be done by a remote client and hence will not
+    // every have any connections (whew).
+
+    let mut session = ClientSession::new();
 
     let mut reader = BufReader::new(stream.try_clone().unwrap());
 
@@ -167,15 +490,86 @@ fn handle_request(client_stream: SafeStream, dir: String, portman: u16, inventor
 
     loop {
         let request = read_request(&mut reader);
-        info!("Request : {:#?}", request);
         if request.len() > 0 {
+            log_request(&stream, &request);
             match request[0].as_str() {
                 "LIST" => {
                     info!("List request from {}", stream.peer_addr().unwrap());
-                    if request.len() != 1 {
-                        fail_request(&mut stream, "LIST does not take any parameters");
+                    // LIST ?VERBOSE? ?pattern?
+
+                    let mut words = &request[1..];
+                    let verbose = !words.is_empty() && words[0].to_uppercase() == "VERBOSE";
+                    if verbose {
+                        words = &words[1..];
+                    }
+                    match words.len() {
+                        0 => list_rings(&mut stream, &dir, &inventory, &events, verbose, None),
+                        1 => match RingPattern::parse(&words[0]) {
+                            Ok(pattern) => list_rings(
+                                &mut stream,
+                                &dir,
+                                &inventory,
+                                &events,
+                                verbose,
+                                Some(&pattern),
+                            ),
+                            Err(e) => fail_request(&mut stream, &e),
+                        },
+                        _ => fail_request(
+                            &mut stream,
+                            "LIST only takes an optional VERBOSE and an optional ring name pattern",
+                        ),
+                    }
+                }
+                "STATUS" => {
+                    info!("Status request from {}", stream.peer_addr().unwrap());
+                    let json = match request.len() {
+                        2 => Some(false),
+                        3 => match request[2].to_uppercase().as_str() {
+                            "TCL" => Some(false),
+                            "JSON" => Some(true),
+                            _ => None,
+                        },
+                        _ => None,
+                    };
+                    if let Some(json) = json {
+                        ring_status(&mut stream, &dir, &request[1], json, &inventory);
                     } else {
-                        list_rings(&mut *stream, &dir, &inventory);
+                        fail_request(&mut stream, "STATUS needs a ring name optionally followed by TCL or JSON");
+                    }
+                }
+                "STATS" => {
+                    info!("Stats request from {}", stream.peer_addr().unwrap());
+                    let json = match request.len() {
+                        2 => Some(false),
+                        3 => match request[2].to_uppercase().as_str() {
+                            "TCL" => Some(false),
+                            "JSON" => Some(true),
+                            _ => None,
+                        },
+                        _ => None,
+                    };
+                    if let Some(json) = json {
+                        ring_stats(&mut stream, &request[1], json, &inventory, &stats);
+                    } else {
+                        fail_request(&mut stream, "STATS needs a ring name optionally followed by TCL or JSON");
+                    }
+                }
+                "ALARMS" => {
+                    info!("Alarms request from {}", stream.peer_addr().unwrap());
+                    match parse_alarms(&request[1..]) {
+                        Ok((pattern, json)) => list_alarms(&mut stream, &alarms, pattern, json),
+                        Err(reason) => fail_request(&mut stream, &reason),
+                    }
+                }
+                "WATCH" => {
+                    info!("Watch request from {}", stream.peer_addr().unwrap());
+                    match parse_watch(&request[1..]) {
+                        Ok((interval, pattern, json)) => {
+                            watch_rings(&mut stream, &dir, &inventory, &events, interval, pattern, json);
+                            return;
+                        }
+                        Err(reason) => fail_request(&mut stream, &reason),
                     }
                 }
                 "REGISTER" => {
@@ -186,7 +580,9 @@ fn handle_request(client_stream: SafeStream, dir: String, portman: u16, inventor
                     if request.len() != 2 {
                         fail_request(&mut stream, "REGISTER must have only a ring name parameter");
                     } else {
-                        register_ring(&mut *stream, &dir, &request[1], &inventory);
+                        if register_ring(&mut stream, &dir, &request[1], &inventory) {
+                            publish(&events, RingEvent::Registered { ring: request[1].clone() });
+                        }
                     }
                 }
                 "UNREGISTER" => {
@@ -194,13 +590,78 @@ fn handle_request(client_stream: SafeStream, dir: String, portman: u16, inventor
                         "Unregister request from {} will enforce locality",
                         stream.peer_addr().unwrap()
                     );
-                    if request.len() != 2 {
-                        fail_request(
+                    // The optional third word overrides the kill policy:
+
+                    let policy = if request.len() == 3 {
+                        rings::rings::KillPolicy::parse(&request[2])
+                    } else {
+                        Some(options.unregister_policy)
+                    };
+                    match policy {
+                        Some(policy) if request.len() == 2 || request.len() == 3 => {
+                            if unregister_ring(
+                                &mut stream,
+                                &request[1],
+                                &inventory,
+                                policy,
+                                Duration::from_secs(options.kill_grace),
+                            ) {
+                                publish(&events, RingEvent::Unregistered { ring: request[1].clone() });
+                            }
+                        }
+                        _ => {
+                            fail_request(
+                                &mut stream,
+                                "UNREGISTER must have a ring name and optionally KILL, SIGNAL or NOKILL",
+                            );
+                        }
+                    }
+                }
+                "CREATE" => {
+                    info!(
+                        "Create request from {} will enforce locality",
+                        stream.peer_addr().unwrap()
+                    );
+                    if request.len() != 4 {
+                        fail_request(&mut stream, "CREATE needs a ring name, size and max consumers");
+                    } else if let (Ok(size), Ok(max_consumers)) =
+                        (request[2].parse::<u32>(), request[3].parse::<usize>())
+                    {
+                        if create_ring(
                             &mut stream,
-                            "UNREGISTER must have only a ring name parameter",
-                        );
+                            &dir,
+                            &request[1],
+                            size,
+                            max_consumers,
+                            options.ring_mode,
+                            &inventory,
+                        ) {
+                            publish(&events, RingEvent::Registered { ring: request[1].clone() });
+                        }
+                    } else {
+                        fail_request(&mut stream, "CREATE size and max consumers must be unsigned integers");
+                    }
+                }
+                "DELETE" => {
+                    info!(
+                        "Delete request from {} will enforce locality",
+                        stream.peer_addr().unwrap()
+                    );
+                    let force = request.len() == 3 && request[2].to_uppercase() == "FORCE";
+                    if request.len() == 2 || force {
+                        if delete_ring(
+                            &mut stream,
+                            &dir,
+                            &request[1],
+                            force,
+                            &inventory,
+                            options.unregister_policy,
+                            Duration::from_secs(options.kill_grace),
+                        ) {
+                            publish(&events, RingEvent::Unregistered { ring: request[1].clone() });
+                        }
                     } else {
-                        unregister_ring(&mut *stream, &request[1], &inventory);
+                        fail_request(&mut stream, "DELETE needs a ring name optionally followed by FORCE");
                     }
                 }
                 "CONNECT" => {
@@ -226,10 +687,15 @@ fn handle_request(client_stream: SafeStream, dir: String, portman: u16, inventor
                             &request[3],
                             &comment,
                             &inventory,
-                            &mut pid,
+                            &mut session.pid,
                         );
                         if let Some(client) = result {
-                            record_connection(&request[1], &mut connections, client);
+                            session.record_identity(session.pid);
+                            record_connection(&request[1], &mut session.connections, client);
+                            publish(
+                                &events,
+                                RingEvent::Connected { ring: strip_braces(&request[1]), client },
+                            );
                         }
                     }
                 }
@@ -250,12 +716,15 @@ fn handle_request(client_stream: SafeStream, dir: String, portman: u16, inventor
                             &request[2],
                             &request[3],
                             &dir,
-                            &connections,
                             &inventory,
-                            &mut pid,
+                            &mut session,
                         );
                         if let Some(client) = removed {
-                            unrecord_connection(&request[1], &mut connections, client);
+                            unrecord_connection(&request[1], &mut session.connections, client);
+                            publish(
+                                &events,
+                                RingEvent::Disconnected { ring: strip_braces(&request[1]), client },
+                            );
                         }
                     }
                 }
@@ -263,13 +732,82 @@ fn handle_request(client_stream: SafeStream, dir: String, portman: u16, inventor
                     // Note we don't enforce locality this could be
                     // used by non NSCLDAQ programs to get a pipe from the ring.
                     info!("Remote request from {}", stream.peer_addr().unwrap());
-                    if request.len() == 2 {
-                        hoist_data(&mut stream, &request[1], &dir, portman, &inventory);
-                        return;
+                    if request.len() >= 2 {
+                        match RemoteOptions::parse(&request[2..]) {
+                            Ok(remote) => {
+                                hoist_data(&mut stream, &request[1], &remote, &options, &inventory);
+                                return;
+                            }
+                            Err(e) => fail_request(&mut stream, &e),
+                        }
                     } else {
                         fail_request(&mut stream, "Invalid request length");
                     }
                 }
+                "PUSH" => {
+                    info!("Push request from {} will enforce locality", stream.peer_addr().unwrap());
+                    match PushTarget::parse(&request[1..]) {
+                        Ok(target) => push_ring(&mut stream, target, &options, &inventory),
+                        Err(e) => fail_request(&mut stream, &e),
+                    }
+                }
+                "UNPUSH" => {
+                    info!("Unpush request from {} will enforce locality", stream.peer_addr().unwrap());
+                    if request.len() == 3 {
+                        unpush_ring(&mut stream, &request[1], &request[2]);
+                    } else {
+                        fail_request(&mut stream, "UNPUSH needs a ring name and a host:port");
+                    }
+                }
+                "PUSHES" => {
+                    info!("Pushes request from {}", stream.peer_addr().unwrap());
+                    let json = match request.len() {
+                        1 => Some(false),
+                        2 => match request[1].to_uppercase().as_str() {
+                            "TCL" => Some(false),
+                            "JSON" => Some(true),
+                            _ => None,
+                        },
+                        _ => None,
+                    };
+                    if let Some(json) = json {
+                        list_pushes(&mut stream, json);
+                    } else {
+                        fail_request(&mut stream, "PUSHES can only be followed by TCL or JSON");
+                    }
+                }
+                "PROXY" => {
+                    info!("Proxy request from {} will enforce locality", stream.peer_addr().unwrap());
+                    match ProxyTarget::parse(&request[1..]) {
+                        Ok(target) => proxy_ring(&mut stream, target, &options, &inventory, &events),
+                        Err(e) => fail_request(&mut stream, &e),
+                    }
+                }
+                "UNPROXY" => {
+                    info!("Unproxy request from {} will enforce locality", stream.peer_addr().unwrap());
+                    match ProxyTarget::parse(&request[1..]) {
+                        Ok(target) if request.len() == 3 => unproxy_ring(&mut stream, &target),
+                        Ok(_) => fail_request(&mut stream, "UNPROXY needs only a host and a ring name"),
+                        Err(e) => fail_request(&mut stream, &e),
+                    }
+                }
+                "PROXIES" => {
+                    info!("Proxies request from {}", stream.peer_addr().unwrap());
+                    let json = match request.len() {
+                        1 => Some(false),
+                        2 => match request[1].to_uppercase().as_str() {
+                            "TCL" => Some(false),
+                            "JSON" => Some(true),
+                            _ => None,
+                        },
+                        _ => None,
+                    };
+                    if let Some(json) = json {
+                        list_proxies(&mut stream, json);
+                    } else {
+                        fail_request(&mut stream, "PROXIES can only be followed by TCL or JSON");
+                    }
+                }
                 _ => {
                     fail_request(&mut stream, "Invalid Request");
                 }
@@ -279,26 +817,43 @@ fn handle_request(client_stream: SafeStream, dir: String, portman: u16, inventor
             // the other side might have already done that:
             // These if-lets are just a fancy way to ignore Err's from
             // their functions.
+            // Usually the client just hung up so this is not logged as a
+            // failed request.
             //
-            fail_request(&mut stream, "Empty request");
+            send_fail(&mut stream, "Empty request");
             break;
         }
     }
     // release any slots held by oid if it's not ringbuffer::UNUSED_ENTRY.
+    // A slot is only freed if its pid still belongs to our client (or
+    // to nobody); a reused pid may legitimately own the slot now.
 
-    for (ring_name, allocations) in connections {
+    for (ring_name, allocations) in &session.connections {
     	let ring_file = compute_ring_buffer_path(&dir, &ring_name);
+        for a in allocations {
+            publish(&events, RingEvent::Disconnected { ring: ring_name.clone(), client: *a });
+        }
         if let Ok(mut ringmap) = ringbuffer::RingBufferMap::new(&ring_file) {
-            for a in allocations {
+            for a in allocations.iter().copied() {
                 match a {
                     rings::rings::Client::Consumer { slot, pid } => {
-                        if ringmap.consumer(slot as usize).unwrap().get_pid() == pid {
-                            if let Ok(_) = ringmap.free_consumer(slot as usize, pid) {}
+                        if let Ok(consumer) = ringmap.consumer(slot as usize) {
+                            if consumer.get_pid() == pid {
+                                if may_free(pid, session.identity(pid)) {
+                                    if let Ok(_) = ringmap.free_consumer(slot as usize, pid) {}
+                                } else {
+                                    info!("Not freeing consumer slot {} of {}: pid {} was reused", slot, ring_name, pid);
+                                }
+                            }
                         }
                     }
                     rings::rings::Client::Producer { pid } => {
                         if ringmap.producer().get_pid() == pid {
-                            if let Ok(_) = ringmap.free_producer(pid) {}
+                            if may_free(pid, session.identity(pid)) {
+                                if let Ok(_) = ringmap.free_producer(pid) {}
+                            } else {
+                                info!("Not freeing producer of {}: pid {} was reused", ring_name, pid);
+                            }
                         }
                     }
                 }
@@ -308,13 +863,80 @@ fn handle_request(client_stream: SafeStream, dir: String, portman: u16, inventor
     info!("Socket service thread exiting");
 }
 ///
-/// Determine if a peer is local:
+/// Parse the parameters of WATCH ?interval? ?pattern? ?TCL|JSON?
+/// into the interval, the pattern and whether JSON was asked for.
+/// A leading number is the interval in seconds; a trailing TCL or JSON
+/// the format.  Anything in between is the pattern.
+///
+fn parse_watch(words: &[String]) -> Result<(Duration, Option<RingPattern>, bool), String> {
+    let mut words = words;
+    let mut interval = DEFAULT_WATCH_INTERVAL;
+    let mut json = false;
+    if let Some(first) = words.first() {
+        if let Ok(seconds) = first.parse::<f64>() {
+            if !(MIN_WATCH_INTERVAL.as_secs_f64()..=MAX_WATCH_INTERVAL.as_secs_f64()).contains(&seconds) {
+                return Err(format!(
+                    "WATCH interval must be from {} to {} seconds",
+                    MIN_WATCH_INTERVAL.as_secs_f64(),
+                    MAX_WATCH_INTERVAL.as_secs_f64()
+                ));
+            }
+            interval = Duration::from_secs_f64(seconds);
+            words = &words[1..];
+        }
+    }
+    if let Some(last) = words.last() {
+        match last.to_uppercase().as_str() {
+            "JSON" => {
+                json = true;
+                words = &words[..words.len() - 1];
+            }
+            "TCL" => words = &words[..words.len() - 1],
+            _ => {}
+        }
+    }
+    match words.len() {
+        0 => Ok((interval, None, json)),
+        1 => Ok((interval, Some(RingPattern::parse(&words[0])?), json)),
+        _ => Err(String::from("WATCH takes an optional interval, pattern and TCL or JSON")),
+    }
+}
+///
+/// Parse the parameters of ALARMS ?pattern? ?TCL|JSON? into the pattern
+/// and whether JSON was asked for.
+///
+fn parse_alarms(words: &[String]) -> Result<(Option<RingPattern>, bool), String> {
+    let mut words = words;
+    let mut json = false;
+    if let Some(last) = words.last() {
+        match last.to_uppercase().as_str() {
+            "JSON" => {
+                json = true;
+                words = &words[..words.len() - 1];
+            }
+            "TCL" => words = &words[..words.len() - 1],
+            _ => {}
+        }
+    }
+    match words.len() {
+        0 => Ok((None, json)),
+        1 => Ok((Some(RingPattern::parse(&words[0])?), json)),
+        _ => Err(String::from("ALARMS takes an optional pattern and TCL or JSON")),
+    }
+}
+///
+/// Determine if a peer is local: a loopback address.  IPv4 clients of a
+/// dual-stack IPv6 listener have IPv4-mapped addresses, e.g.
+/// ::ffff:127.0.0.1, which are local if the IPv4 address is.
 ///
 fn is_local_peer(stream: &TcpStream) -> bool {
     if let Ok(peer) = stream.peer_addr() {
         match peer {
-            SocketAddr::V4(p) => *p.ip() == Ipv4Addr::new(127, 0, 0, 1),
-            SocketAddr::V6(p) => *p.ip() == Ipv6Addr::new(0, 0, 0, 0, 0, 0, 0, 1),
+            SocketAddr::V4(p) => p.ip().is_loopback(),
+            SocketAddr::V6(p) => match p.ip().to_ipv4_mapped() {
+                Some(v4) => v4.is_loopback(),
+                None => p.ip().is_loopback(),
+            },
         }
     } else {
         false
@@ -327,6 +949,19 @@ fn acknowledge_client_hookup(stream: &mut TcpStream) {
     }
 }
 ///
+/// Reply OK followed by a list of pids, e.g. the clients
+/// a request signalled.
+///
+fn acknowledge_with_pids(stream: &mut TcpStream, pids: &[u32]) {
+    let mut reply = String::from("OK");
+    for pid in pids {
+        reply += &format!(" {}", pid);
+    }
+    if stream.write_all(format!("{}\r\n", reply).as_bytes()).is_ok() {
+        let _ = stream.flush();
+    }
+}
+///
 /// produce the Arc::Mutex::ClientMonitorInfo for a producer.
 /// When we return, the monitor is running and has a stream to listen to
 /// as well as the way to unregister itself.
@@ -387,6 +1022,7 @@ fn connect_client(
                 // unless the client pid is UNUSED_ENTRY:
 
                 if (pid_value != *client_pid) && (*client_pid != ringbuffer::UNUSED_ENTRY) {
+                    log_pid_spoof(stream, &ring_name, pid_value, *client_pid);
                     fail_request(stream, "PID spoof attempt");
                     return None;
                 } else {
@@ -403,6 +1039,11 @@ fn connect_client(
                     return Some(client_info);
                 } else if connection.len() == 2 && connection[0] == "consumer" {
                     if let Ok(slot) = connection[1].parse::<u32>() {
+                        let slots = ringbuffer::RingBufferMap::new(&info.ring_file).map(|map| map.max_consumers());
+                        if !slots.is_ok_and(|slots| (slot as usize) < slots) {
+                            fail_request(stream, &format!("{} has no consumer slot {}", ring_name, slot));
+                            return None;
+                        }
                         let client_info = connect_consumer(stream, slot, pid_value);
                         
                         info.add_client(&Arc::new(Mutex::new(
@@ -454,15 +1095,17 @@ fn connection_exists(
 /// *  There is an approprioately typed consumer with the PID identified
 /// in the ring's monitorlist.
 ///
+/// The slot itself is only freed if the process identity recorded when
+/// the pid CONNECTed still holds (see may_free).
+///
 fn disconnect_client(
     stream: &mut TcpStream,
     ring: &str,
     connection_type: &str,
     pid: &str,
     dir: &str,
-    connections: &HashMap<String, Vec<rings::rings::Client>>,
     inventory: &SafeInventory,
-    client_pid: &mut u32,
+    session: &mut ClientSession,
 ) -> Option<rings::rings::Client> {
     // Trim the {} off the ring name:
     let mut ring_name = String::from(ring);
@@ -473,15 +1116,17 @@ fn disconnect_client(
     info!("Ring buffer file {}", filename);
     if is_local_peer(&stream) {
         if let Some(info) = inventory.lock().unwrap().get_mut(&ring_name) {
-            if let Some(registrations) = connections.get(&ring_name) {
+            if let Some(registrations) = session.connections.get(&ring_name) {
                 if let Ok(pid_num) = pid.parse::<u32>() {
                     // Must match the client pid if there is one:
 
-                    if (pid_num != *client_pid) && (*client_pid != ringbuffer::UNUSED_ENTRY) {
+                    if (pid_num != session.pid) && (session.pid != ringbuffer::UNUSED_ENTRY) {
+                        log_pid_spoof(stream, &ring_name, pid_num, session.pid);
                         fail_request(stream, "attemped PID spoof");
                     } else {
-                        *client_pid = pid_num;
+                        session.pid = pid_num;
                     }
+                    let identity = session.identity(pid_num);
 
                     // Producer or consumer:
 
@@ -499,8 +1144,10 @@ fn disconnect_client(
                             if connection_exists(&client_info, &registrations) {
                                 if let Ok(_) = stream.write_all(b"OK\r\n") {}
                                 if let Ok(_) = stream.flush() {}
-                                if let Ok(mut map) = ringbuffer::RingBufferMap::new(&filename) {
-                                    if let Ok(_) = map.free_producer(pid_num) {}
+                                if may_free(pid_num, identity) {
+                                    if let Ok(mut map) = ringbuffer::RingBufferMap::new(&filename) {
+                                        if let Ok(_) = map.free_producer(pid_num) {}
+                                    }
                                 }
                                 return Some(client_info);
                             } else {
@@ -529,8 +1176,10 @@ fn disconnect_client(
                                 if connection_exists(&client_info, &registrations) {
                                     if let Ok(_) = stream.write_all(b"OK\r\n") {}
                                     if let Ok(_) = stream.flush() {}
-                                    if let Ok(mut map) = ringbuffer::RingBufferMap::new(&filename) {
-                                        if let Ok(_) = map.free_consumer(slot_num as usize, pid_num) {}
+                                    if may_free(pid_num, identity) {
+                                        if let Ok(mut map) = ringbuffer::RingBufferMap::new(&filename) {
+                                            if let Ok(_) = map.free_consumer(slot_num as usize, pid_num) {}
+                                        }
                                     }
                                     return Some(client_info);
                                 } else {
@@ -584,10 +1233,16 @@ fn disconnect_client(
 ///  *  The file representing the ring must be in the inventory.
 ///  *  If the file exists (has not been deleted by the invoker),
 ///     it will be deleted by us.
+///  *  The ring's clients are dealt with according to the kill policy.
+///     This is done after the ring is out of the inventory so that
+///     waiting out the grace period does not hold up other requests.
 ///
-/// On success "Ok\r\n" is emitted.  Regardess, the connectio is
+/// On success "Ok\r\n" is emitted.  If any client processes were
+/// signalled their pids follow the OK.  Regardess, the connectio is
 /// closed after the request...if possible.
 ///
+/// Returns true if the ring was in the inventory and has been removed.
+///
 /// #### Note
 ///
 /// If this program runs at escalated privilege, there's a bit of
@@ -595,18 +1250,27 @@ fn disconnect_client(
 /// requestor to delete a ring-buffer file the requestor could not otherwise
 /// delete.
 ///
-fn unregister_ring(stream: &mut TcpStream, ring_name: &str, inventory: &SafeInventory) {
-    let mut inventory = inventory.lock().unwrap();
+fn unregister_ring(
+    stream: &mut TcpStream,
+    ring_name: &str,
+    inventory: &SafeInventory,
+    policy: rings::rings::KillPolicy,
+    grace: Duration,
+) -> bool {
     if is_local_peer(&stream) {
         // The inventory must contain the ring.  The file need not be present
         // as in theory there was once a ring buffer file named that if
         // it was in our inventory.
 
-        if inventory.contains_key(ring_name) {
-            if let Some(info) = inventory.get_mut(ring_name) {
-                info.remove_all();
-                inventory.remove(ring_name).unwrap();
-            }
+        let removed = inventory.lock().unwrap().remove(ring_name);
+        let was_registered = removed.is_some();
+        let mut signalled = Vec::<u32>::new();
+        if let Some(mut info) = removed {
+            signalled = info.remove_all_with_policy(policy, grace);
+            info!(
+                "Unregistered {} with policy {:?}, signalled {:?}",
+                ring_name, policy, signalled
+            );
         }
         // It's the client's responsibility to remove the ringbuffer
         // file itself, otherwise we could be a securit hole
@@ -619,10 +1283,11 @@ fn unregister_ring(stream: &mut TcpStream, ring_name: &str, inventory: &SafeInve
 
         // THe ring buffer does not need to be in our inventory so:
 
-        if let Ok(_) = stream.write_all(b"OK\r\n") {}
-        if let Ok(_) = stream.flush() {}
+        acknowledge_with_pids(stream, &signalled);
+        was_registered
     } else {
         fail_request(stream, "UNREGISTER request only legal from local peers");
+        false
     }
 }
 
@@ -634,8 +1299,9 @@ fn unregister_ring(stream: &mut TcpStream, ring_name: &str, inventory: &SafeInve
 ///
 /// If all of that holds the ring is added to the inventory and
 /// an "OK\r\n" response is emitted.  Regardless, the connection is closed.
+/// Returns true if the ring was added to the inventory.
 ///
-fn register_ring(stream: &mut TcpStream, dir: &str, name: &str, inventory: &SafeInventory) {
+fn register_ring(stream: &mut TcpStream, dir: &str, name: &str, inventory: &SafeInventory) -> bool {
     let mut inventory = inventory.lock().unwrap();
     if is_local_peer(&stream) {
         if inventory.contains_key(name) {
@@ -650,6 +1316,7 @@ fn register_ring(stream: &mut TcpStream, dir: &str, name: &str, inventory: &Safe
                 add_ring(name, &mut inventory);
                 if let Ok(_) = stream.write_all(b"OK\r\n") {}
                 if let Ok(_) = stream.flush() {}
+                return true;
             } else {
                 fail_request(stream, format!("{} is not a ringbuffer", name).as_str());
             }
@@ -657,6 +1324,117 @@ fn register_ring(stream: &mut TcpStream, dir: &str, name: &str, inventory: &Safe
     } else {
         fail_request(stream, "REGISTER Must come from a local host");
     }
+    false
+}
+/// create a new ring buffer:
+///
+/// *   The request must be local.
+/// *   The ring name must be a plain filename and not already exist.
+/// *   The ring file is created in the ring directory with the
+///     --ring-mode permissions and added to the inventory.
+///
+/// The inventory is locked throughout so no other request can see the
+/// ring until it's completely made.  On success "OK\r\n" is emitted
+/// and true is returned.
+///
+fn create_ring(
+    stream: &mut TcpStream,
+    dir: &str,
+    name: &str,
+    size: u32,
+    max_consumers: usize,
+    mode: u32,
+    inventory: &SafeInventory,
+) -> bool {
+    if !is_local_peer(stream) {
+        fail_request(stream, "CREATE must come from a local host");
+        return false;
+    }
+    if !ringfile::ringfile::valid_ring_name(name) {
+        fail_request(stream, &format!("{} is not a valid ring name", name));
+        return false;
+    }
+    let mut inventory = inventory.lock().unwrap();
+    if inventory.contains_key(name) {
+        fail_request(stream, &format!("{} already exists", name));
+        return false;
+    }
+    let full_path = compute_ring_buffer_path(dir, name);
+    match ringfile::ringfile::create_ring(&full_path, size, max_consumers, mode) {
+        Ok(_) => {
+            add_ring(&full_path, &mut inventory);
+            acknowledge_client_hookup(stream);
+            true
+        }
+        Err(reason) => {
+            error!("Failed to create ring {}: {}", name, reason);
+            fail_request(stream, &reason);
+            false
+        }
+    }
+}
+/// delete a ring buffer:
+///
+/// *   The request must be local.
+/// *   The ring must be in the inventory.
+/// *   The ring must not be a proxy ring being filled (else the proxy
+///     would just make it again).
+/// *   If the ring has a producer or consumers, the request is refused
+///     unless force is true.  In that case, the clients are dealt with
+///     according to the kill policy as for UNREGISTER.
+///
+/// The ring leaves the inventory and its file is removed under the
+/// inventory lock.  Clients are signalled after the lock is released.
+/// On success "OK\r\n" is emitted, followed by the pids of any
+/// signalled clients, and true is returned.
+///
+fn delete_ring(
+    stream: &mut TcpStream,
+    dir: &str,
+    name: &str,
+    force: bool,
+    inventory: &SafeInventory,
+    policy: rings::rings::KillPolicy,
+    grace: Duration,
+) -> bool {
+    if !is_local_peer(stream) {
+        fail_request(stream, "DELETE must come from a local host");
+        return false;
+    }
+    let full_path = compute_ring_buffer_path(dir, name);
+    let removed = {
+        let mut inventory = inventory.lock().unwrap();
+        if !inventory.contains_key(name) {
+            fail_request(stream, &format!("{} is not in the ring master's inventory", name));
+            return false;
+        }
+        if PROXIES.contains(name) {
+            fail_request(stream, &format!("{} is being filled by a proxy, UNPROXY it first", name));
+            return false;
+        }
+        if !force {
+            if let Ok(mut map) = ringbuffer::RingBufferMap::new(&full_path) {
+                let usage = map.get_usage();
+                if usage.producer_pid != ringbuffer::UNUSED_ENTRY || !usage.consumer_usage.is_empty() {
+                    fail_request(stream, &format!("{} has clients attached, use FORCE", name));
+                    return false;
+                }
+            }
+        }
+        if let Err(reason) = ringfile::ringfile::delete_ring(&full_path) {
+            error!("Failed to delete ring {}: {}", name, reason);
+            fail_request(stream, &reason);
+            return false;
+        }
+        inventory.remove(name)
+    };
+    let mut signalled = Vec::<u32>::new();
+    if let Some(mut info) = removed {
+        signalled = info.remove_all_with_policy(policy, grace);
+        info!("Deleted {}, signalled {:?}", name, signalled);
+    }
+    acknowledge_with_pids(stream, &signalled);
+    true
 }
 ///
 /// Return a vector of ring list information.
@@ -680,133 +1458,1616 @@ fn register_ring(stream: &mut TcpStream, dir: &str, name: &str, inventory: &Safe
 ///     *  The number of bytes of backlog the consumer has.
 /// The stream will be closed
 ///
+/// If verbose is true, the process name and user of the producer and
+/// consumers are added (see format_ring_info).  If a pattern is given,
+/// only the rings whose names match it are mapped and listed.
+///
 /// ##### Note
 ///    If the ring has disappeared, we clean, and any watches up.
-fn list_rings(stream: &mut TcpStream, directory: &str, inventory: &SafeInventory) {
-    let mut gone_rings = Vec::<String>::new();
+fn list_rings(
+    stream: &mut TcpStream,
+    directory: &str,
+    inventory: &SafeInventory,
+    events: &SafeEvents,
+    verbose: bool,
+    pattern: Option<&RingPattern>,
+) {
+    let ring_infos = collect_ring_infos(directory, inventory, events, pattern);
+    if let Ok(_) = stream.write_all(b"OK\r\n") {
+        // Look up all the processes in one go:
 
-    let mut inventory = inventory.lock().unwrap();
+        let processes = if verbose {
+            let mut pids = Vec::<u32>::new();
+            for info in &ring_infos {
+                if info.info.producer_pid != ringbuffer::UNUSED_ENTRY {
+                    pids.push(info.info.producer_pid);
+                }
+                pids.extend(info.info.consumer_usage.iter().map(|c| c.pid));
+            }
+            pids.sort_unstable();
+            pids.dedup();
+            Some(describe_processes(&pids))
+        } else {
+            None
+        };
+        let listing_string = format_listing(ring_infos, processes.as_ref());
+        if let Ok(_) = stream.write_all(format!("{}\r\n", listing_string).as_bytes()) {}
+    }
+}
+///
+/// Get the LIST information for the rings in the inventory whose names
+/// match the pattern (all if there's no pattern).
+///
+/// ##### Note
+///    If the ring has disappeared, we clean, and any watches up.
+fn collect_ring_infos(
+    directory: &str,
+    inventory: &SafeInventory,
+    events: &SafeEvents,
+    pattern: Option<&RingPattern>,
+) -> Vec<RingInfo> {
+    let mut gone_rings = Vec::<String>::new();
+    let mut ring_infos = Vec::<RingInfo>::new();
 
-    if let Ok(_) = stream.write_all(b"OK\r\n") {
-        let mut listing = tcllist::TclList::new();
+    let removed = {
+        let mut inventory = inventory.lock().unwrap();
         for name in inventory.keys() {
+            if pattern.is_some_and(|p| !p.matches(name)) {
+                continue;
+            }
             if let Ok(ring_info) = get_ring_list_info(directory, name) {
-                listing.add_element(&format_ring_info(ring_info));
+                ring_infos.push(ring_info);
             } else {
                 gone_rings.push(name.to_string()); // Destroying here invalidates iterator.
             }
         }
-        // our rendering of sublists means that we really need to take off the first and last characters.
+        // Take the rings that failed to list (they died) out of the
+        // inventory:
 
-        let mut listing_string = format!("{}", listing);
-        if listing_string.len() >= 2 {
-            listing_string = listing_string[1..listing_string.len() - 1].to_string();
-        }
-        if let Ok(_) = stream.write_all(format!("{}\r\n", listing_string).as_bytes()) {}
-    }
+        gone_rings
+            .into_iter()
+            .filter_map(|name| inventory.remove(&name).map(|info| (name, info)))
+            .collect::<Vec<_>>()
+    };
+    // Their clients are dealt with once we no longer hold the inventory.
 
-    // Kill off all the rings that failed to list (they died).
+    for (bad_ring, mut ring_info) in removed {
+        ring_info.remove_all();
+        publish(events, RingEvent::Unregistered { ring: bad_ring });
+    }
+    ring_infos
+}
+///
+/// Format ring information as the Tcl list LIST replies with.
+///
+fn format_listing(
+    ring_infos: Vec<RingInfo>,
+    processes: Option<&HashMap<u32, ProcessDescription>>,
+) -> String {
+    let mut listing = tcllist::TclList::new();
+    for ring_info in ring_infos {
+        listing.add_element(&format_ring_info(ring_info, processes));
+    }
+    // our rendering of sublists means that we really need to take off the first and last characters.
 
-    for bad_ring in gone_rings {
-        if let Some(ring_info) = inventory.get_mut(&bad_ring) {
-            ring_info.remove_all();
-            if let Some(_) = inventory.remove(&bad_ring) {}
-        }
+    let mut listing_string = format!("{}", listing);
+    if listing_string.len() >= 2 {
+        listing_string = listing_string[1..listing_string.len() - 1].to_string();
     }
+    listing_string
 }
-/// hoist data from the ring to the client.
-//  - We require the RUST ring2stdout to be in the path.
-//  - We run it with stdout pointed at the stream and
-//    stderr, stdin off.
-//  - The program options are set as follows:
-//      *  --directory - is set to the directory in which we know the rings live.
-//      *  --ring      - is the name of the ring passed in to the request.
-//      *  --port      - is the port manager port we're using.
-//      *  --comment   - Is "Hoisting to {}" where {} is replaced by the
-//                       address of the request's peer.
-//
-fn hoist_data(
-    stream: &mut TcpStream,
-    ring: &str,
-    dir: &str,
-    portman: u16,
+///
+/// Format ring information as a JSON object.  The keys correspond to
+/// the LIST elements; the producer is null if there isn't one.
+///
+fn ring_info_json(info: &RingInfo) -> Value {
+    let producer = if info.info.producer_pid == ringbuffer::UNUSED_ENTRY {
+        None
+    } else {
+        Some(info.info.producer_pid)
+    };
+    let consumers = info
+        .info
+        .consumer_usage
+        .iter()
+        .enumerate()
+        .map(|(i, c)| json!({ "pid": c.pid, "backlog": c.available, "slot": info.slots.get(i) }))
+        .collect::<Vec<Value>>();
+    json!({
+        "name": info.name,
+        "size": info.size,
+        "free_space": info.info.free_space,
+        "max_consumers": info.max_consumers,
+        "producer": producer,
+        "max_queued": info.info.max_queued,
+        "min_get": info.min_get,
+        "consumers": consumers,
+    })
+}
+///
+/// Stream ring usage to a client until it goes away.  The reply is OK
+/// followed by lines of two sorts:
+///
+/// *   Snapshots of the rings matching the pattern every interval.  These
+///     are SNAPSHOT followed by the LIST listing or, if json is true, a
+///     JSON object with type snapshot and a rings array.
+/// *   Events (see RingEvent) about the matching rings as they happen.
+///     These are EVENT followed by the event as a Tcl list or a JSON
+///     object with type event.
+///
+fn watch_rings(
+    stream: &mut TcpStream,
+    directory: &str,
     inventory: &SafeInventory,
+    events: &SafeEvents,
+    interval: Duration,
+    pattern: Option<RingPattern>,
+    json: bool,
 ) {
-    // Validate that the ring is in our ring inventory:
-    // Gettin gthe bool holds the lock minmally.
+    let receiver = events.lock().unwrap().subscribe();
+    acknowledge_client_hookup(stream);
 
-    let ring_exists = inventory.lock().unwrap().contains_key(ring);
-    if ring_exists {
-        let process_stdout = socket_to_stdio(stream);
-        let dir_arg = String::from(dir);
-        let ring_arg = String::from(ring);
-        let port_arg = portman.to_string();
-        let comment_arg = format!("Hoisting to {}", stream.peer_addr().unwrap());
+    // A watcher that stops reading must not hold us (or its event
+    // queue) forever:
 
-        // Output our success string and start the client program:
+    let _ = stream.set_write_timeout(Some(WATCH_WRITE_TIMEOUT));
 
-        match stream.write_all(b"OK BINARY FOLLOWS\r\n") {
-            Ok(_) => {
-                if let Err(e) = stream.flush() {
-                    error!("Failed to flush BINARY FOLLOWS string {}", e);
+    let mut next_snapshot = Instant::now();
+    loop {
+        if Instant::now() >= next_snapshot {
+            let ring_infos = collect_ring_infos(directory, inventory, events, pattern.as_ref());
+            let line = if json {
+                let rings = ring_infos.iter().map(ring_info_json).collect::<Vec<Value>>();
+                json!({ "type": "snapshot", "rings": rings }).to_string()
+            } else {
+                format!("SNAPSHOT {}", format_listing(ring_infos, None))
+            };
+            if !send_line(stream, &line) {
+                break;
+            }
+            next_snapshot = Instant::now() + interval;
+        }
+        match receiver.recv_timeout(next_snapshot.saturating_duration_since(Instant::now())) {
+            Ok(event) => {
+                if pattern.as_ref().is_some_and(|p| !p.matches(event.ring())) {
+                    continue;
+                }
+                let line = if json {
+                    event.to_json().to_string()
                 } else {
-                    // can start the child.
-
-                    start_hoister(process_stdout, &dir_arg, &ring_arg, &port_arg, &comment_arg);
+                    let event = event.to_tcl().to_string();
+                    format!("EVENT {}", &event[1..event.len() - 1])
+                };
+                if !send_line(stream, &line) {
+                    break;
                 }
             }
-            Err(e) => {
-                // We just give up on error logging that.
-
-                error!("Failed to send OK BINARY FOLLOWS  string {}", e);
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => {
+                warn!("Watcher {:?} fell too far behind the events", stream.peer_addr());
+                break;
             }
         }
+    }
+    info!("Watch ended for {:?}", stream.peer_addr());
+}
+///
+/// Reply with the active alarms of the rings matching the pattern.  The
+/// reply is OK followed by a line with a Tcl list of alarms or, if json is
+/// true, a JSON array of them.
+///
+fn list_alarms(stream: &mut TcpStream, alarms: &SafeAlarms, pattern: Option<RingPattern>, json: bool) {
+    let active = alarms
+        .lock()
+        .unwrap()
+        .active()
+        .into_iter()
+        .filter(|alarm| pattern.as_ref().is_none_or(|p| p.matches(&alarm.ring)))
+        .collect::<Vec<Alarm>>();
+    let body = if json {
+        Value::from(active.iter().map(|alarm| alarm.to_json()).collect::<Vec<Value>>()).to_string()
     } else {
+        let mut listing = tcllist::TclList::new();
+        for alarm in &active {
+            listing.add_element(&alarm.to_tcl().to_string());
+        }
+        let listing = listing.to_string();
+        listing[1..listing.len() - 1].to_string()
+    };
+    acknowledge_client_hookup(stream);
+    send_line(stream, &body);
+}
+///
+/// Write a line to a client.  False if that failed (e.g. the client
+/// went away).
+///
+fn send_line(stream: &mut TcpStream, line: &str) -> bool {
+    stream.write_all(format!("{}\r\n", line).as_bytes()).is_ok() && stream.flush().is_ok()
+}
+///
+/// Publish an event to all WATCHers.
+///
+fn publish(events: &SafeEvents, event: RingEvent) {
+    events.lock().unwrap().publish(event);
+}
+///
+/// CONNECT and DISCONNECT ring names come in braces.  Strip them off
+/// the way connect_client does.
+///
+fn strip_braces(ring: &str) -> String {
+    let mut ring_name = String::from(ring);
+    if ring_name.len() > 2 {
+        ring_name = ring_name[1..ring_name.len() - 1].to_string();
+    }
+    ring_name
+}
+///
+/// Report the detailed status of a single ring.  On success the
+/// reply is OK followed by a line containing the status either as a Tcl
+/// dict or, if json is true, a JSON object.
+///
+fn ring_status(stream: &mut TcpStream, directory: &str, name: &str, json: bool, inventory: &SafeInventory) {
+    // Only hold the lock long enough to get our records of the clients:
+
+    let clients = inventory.lock().unwrap().get(name).map(|info| info.client_records());
+    let clients = match clients {
+        Some(clients) => clients,
+        None => {
+            fail_request(
+                stream,
+                format!("{} is not in the ring master's inventory", name).as_ref(),
+            );
+            return;
+        }
+    };
+    let path = compute_ring_buffer_path(directory, name);
+    match RingStatusReport::collect(name, &path, clients) {
+        Ok(report) => {
+            let body = if json {
+                report.to_json().to_string()
+            } else {
+                // Take the outer braces off as list_rings does.
+
+                let listing = report.to_tcl().to_string();
+                listing[1..listing.len() - 1].to_string()
+            };
+            if stream.write_all(format!("OK\r\n{}\r\n", body).as_bytes()).is_ok() {
+                let _ = stream.flush();
+            }
+        }
+        Err(e) => fail_request(stream, &format!("Unable to get the status of {}: {}", name, e)),
+    }
+}
+///
+/// Report the data rates of a ring's clients.  On success the reply is
+/// OK followed by a line containing the statistics either as a Tcl dict
+/// or, if json is true, a JSON object.
+///
+fn ring_stats(stream: &mut TcpStream, name: &str, json: bool, inventory: &SafeInventory, stats: &SafeStats) {
+    if !inventory.lock().unwrap().contains_key(name) {
+        fail_request(
+            stream,
+            format!("{} is not in the ring master's inventory", name).as_ref(),
+        );
+        return;
+    }
+    match stats.lock().unwrap().stats(name, Instant::now()) {
+        Some(report) => {
+            let body = if json {
+                report.to_json().to_string()
+            } else {
+                let listing = report.to_tcl().to_string();
+                listing[1..listing.len() - 1].to_string()
+            };
+            if stream.write_all(format!("OK\r\n{}\r\n", body).as_bytes()).is_ok() {
+                let _ = stream.flush();
+            }
+        }
+        None => fail_request(stream, &format!("No statistics have been gathered for {} yet", name)),
+    }
+}
+/// hoist data from the ring to the client.
+//  - We require the RUST ring2stdout to be in the path.
+//  - Without TLS we run it with stdout pointed at the stream and
+//    stderr, stdin off.
+//  - With TLS we reply OK TLS, do the server side of the handshake and
+//    then pump ring2stdout's output through the encrypted stream.
+//    The OK BINARY FOLLOWS reply is encrypted too.
+//  - With COMPRESS we pump ring2stdout's output through a compressor.
+//    The OK BINARY FOLLOWS reply names the codec and is not compressed.
+//  - With a rate limit or the sample policy we pump ring2stdout's output
+//    too, metering it out (see pump_blocking and pump_sampled).
+//  - With --hoist-fanout we share a ring2stdout with the ring's other
+//    hoists (see pump_fanout).
+//  - See spawn_hoister for the ring2stdout arguments.
+//
+fn hoist_data(
+    stream: &mut TcpStream,
+    ring: &str,
+    remote: &RemoteOptions,
+    options: &ProgramOptions,
+    inventory: &SafeInventory,
+) {
+    // Validate that the ring is in our ring inventory:
+    // Gettin gthe bool holds the lock minmally.
+
+    let ring_exists = inventory.lock().unwrap().contains_key(ring);
+    if !ring_exists {
         fail_request(
             stream,
             format!("{} is not in the ring master's inventory", ring).as_ref(),
         );
+        return;
+    }
+    let comment = format!("Hoisting to {}", peer_name(stream));
+    if remote.tls {
+        match &options.tls {
+            Some(tls) => hoist_tls(stream, ring, tls, remote, options, &comment),
+            None => fail_request(stream, "This ring master is not configured for TLS"),
+        }
+        return;
+    }
+    if options.tls_required {
+        fail_request(stream, "This ring master requires REMOTE ringname TLS");
+        return;
+    }
+    if remote.compress.is_some() || options.hoist_fanout || !remote.limits(&options.hoist_limits).unlimited() {
+        hoist_stream(stream, ring, remote, options, &comment);
+        return;
+    }
+    let process_stdout = socket_to_stdio(stream);
+
+    // Output our success string and start the client program:
+
+    match stream.write_all(b"OK BINARY FOLLOWS\r\n") {
+        Ok(_) => {
+            if let Err(e) = stream.flush() {
+                error!("Failed to flush BINARY FOLLOWS string {}", e);
+            } else {
+                // can start the child and wait for it to finish.
+
+                match spawn_hoister(process_stdout, ring, options, &comment) {
+                    Ok(mut child) => match child.wait() {
+                        Ok(status) if !status.success() => warn!("ring2stdout ({}) failed: {}", comment, status),
+                        _ => {}
+                    },
+                    Err(reason) => error!("Failed to start ring2stdout: {}", reason),
+                }
+            }
+        }
+        Err(e) => {
+            // We just give up on error logging that.
+
+            error!("Failed to send OK BINARY FOLLOWS  string {}", e);
+        }
+    }
+}
+// Hoist over TLS.  A client that never finishes the handshake gets
+// TLS_HANDSHAKE_TIMEOUT to do so.
+
+fn hoist_tls(
+    stream: &mut TcpStream,
+    ring: &str,
+    tls: &TlsServer,
+    remote: &RemoteOptions,
+    options: &ProgramOptions,
+    comment: &str,
+) {
+    if let Err(e) = stream.write_all(b"OK TLS\r\n").and_then(|_| stream.flush()) {
+        error!("Failed to send OK TLS string {}", e);
+        return;
+    }
+    let peer = peer_name(stream);
+    let _ = stream.set_read_timeout(Some(TLS_HANDSHAKE_TIMEOUT));
+    let mut secure = match tls.accept(&mut *stream) {
+        Ok(secure) => secure,
+        Err(e) => {
+            warn!(event = "tls_failed", peer = peer.as_str(); "TLS with {} failed: {}", peer, e);
+            return;
+        }
+    };
+    let _ = secure.sock.set_read_timeout(None);
+    hoist_stream(&mut secure, ring, remote, options, comment);
+    secure.conn.send_close_notify();
+    let _ = secure.flush();
+}
+// Reply OK BINARY FOLLOWS and hoist through output, compressing if asked
+// and within the hoist's limits.
+
+fn hoist_stream<W: Write>(output: &mut W, ring: &str, remote: &RemoteOptions, options: &ProgramOptions, comment: &str) {
+    let reply = match remote.compress {
+        Some(codec) => format!("OK BINARY FOLLOWS COMPRESS {}\r\n", codec.name()),
+        None => String::from("OK BINARY FOLLOWS\r\n"),
+    };
+    if let Err(e) = output.write_all(reply.as_bytes()).and_then(|_| output.flush()) {
+        error!("Failed to send OK BINARY FOLLOWS  string {}", e);
+        return;
+    }
+    let limits = remote.limits(&options.hoist_limits);
+    match remote.compress {
+        Some(codec) => match Compressor::new(codec, &mut *output) {
+            Ok(mut compressor) => {
+                let _ = pump_hoister(&mut compressor, ring, limits, options, comment);
+                let _ = compressor.finish();
+            }
+            Err(e) => error!("Unable to start {} compression: {}", codec.name(), e),
+        },
+        None => {
+            let _ = pump_hoister(output, ring, limits, options, comment);
+        }
+    }
+}
+// Run ring2stdout and copy what it writes to output until it exits or
+// output fails (the client went away), within the hoist's limits.  Err
+// if output failed or ring2stdout could not be started.
+
+fn pump_hoister<W: Write>(
+    output: &mut W,
+    ring: &str,
+    limits: HoistLimits,
+    options: &ProgramOptions,
+    comment: &str,
+) -> io::Result<()> {
+    if options.hoist_fanout {
+        return pump_fanout(output, ring, limits, options, comment);
+    }
+    match spawn_hoister(process::Stdio::piped(), ring, options, comment) {
+        Ok(mut child) => {
+            let result = match limits.policy {
+                Policy::Block => pump_blocking(&mut child, output, limits.rate),
+                Policy::Sample => pump_sampled(&mut child, output, limits.rate, options.hoist_buffer, ring),
+            };
+            if let Err(e) = &result {
+                info!("Hoisting {} ended: {}", ring, e);
+            }
+            let _ = child.kill();
+            let _ = child.wait();
+            result
+        }
+        Err(reason) => {
+            error!("Failed to start ring2stdout: {}", reason);
+            Err(reason)
+        }
+    }
+}
+// Block policy: each chunk is sent (waiting for the rate if there is one)
+// before the next is read.  Each chunk is flushed as it comes so that
+// buffering (e.g. compression) doesn't hold data back.
+
+fn pump_blocking<W: Write>(child: &mut process::Child, output: &mut W, rate: Option<u64>) -> io::Result<()> {
+    let mut data = child.stdout.take().unwrap();
+    let mut throttle = rate.map(|rate| Throttle::new(rate, Instant::now()));
+    let mut chunk = vec![0u8; HOIST_CHUNK];
+    loop {
+        let n = match data.read(&mut chunk) {
+            Ok(0) => return Ok(()),
+            Ok(n) => n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+        if let Some(throttle) = throttle.as_mut() {
+            thread::sleep(throttle.delay(n, Instant::now()));
+        }
+        output.write_all(&chunk[..n])?;
+        output.flush()?;
+    }
+}
+// Sample policy: a thread drains ring2stdout as fast as it writes,
+// queueing the ring items that fit within the rate and the queue and
+// skipping the rest.  We send what's queued.
+
+fn pump_sampled<W: Write>(
+    child: &mut process::Child,
+    output: &mut W,
+    rate: Option<u64>,
+    capacity: usize,
+    ring: &str,
+) -> io::Result<()> {
+    let mut data = child.stdout.take().unwrap();
+    let queue = Arc::new(HoistQueue::new(capacity));
+    let reader = {
+        let queue = Arc::clone(&queue);
+        let ring = String::from(ring);
+        thread::spawn(move || {
+            let mut splitter = ItemSplitter::new(capacity);
+            let mut throttle = rate.map(|rate| Throttle::new(rate, Instant::now()));
+            let mut chunk = vec![0u8; HOIST_CHUNK];
+            let mut skipped = 0u64;
+            'hoist: loop {
+                match data.read(&mut chunk) {
+                    Ok(0) => break,
+                    Ok(n) => splitter.push(&chunk[..n]),
+                    Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                    Err(_) => break,
+                }
+                loop {
+                    let item = match splitter.next_item() {
+                        Ok(Some(item)) => item,
+                        Ok(None) => break,
+                        Err(e) => {
+                            warn!("Hoisting {} ended: {}", ring, e);
+                            break 'hoist;
+                        }
+                    };
+                    let allowed = match throttle.as_mut() {
+                        Some(throttle) => throttle.allow(item.len(), Instant::now()),
+                        None => true,
+                    };
+                    if !(allowed && queue.offer(item)) {
+                        skipped += 1;
+                    }
+                }
+            }
+            queue.close();
+            skipped
+        })
+    };
+    let result = send_queued(&queue, output, None);
+
+    // Stop the reader if we stopped first:
+
+    queue.close();
+    let _ = child.kill();
+    let skipped = reader.join().unwrap_or(0);
+    if skipped > 0 {
+        info!(
+            event = "hoist_sampled", ring = ring, skipped = skipped;
+            "Skipped {} ring items hoisting {} to keep up", skipped, ring
+        );
     }
+    result
 }
-// Actually start the hoister:
+// Send items from a queue until it's closed and empty, flushing whenever
+// we catch up and keeping to the throttle's rate if there is one.
 
-fn start_hoister(
+fn send_queued<W: Write>(queue: &HoistQueue, output: &mut W, mut throttle: Option<Throttle>) -> io::Result<()> {
+    loop {
+        let item = match queue.try_take() {
+            Some(item) => item,
+            None => {
+                output.flush()?;
+                match queue.take() {
+                    Some(item) => item,
+                    None => return Ok(()),
+                }
+            }
+        };
+        if let Some(throttle) = throttle.as_mut() {
+            thread::sleep(throttle.delay(item.len(), Instant::now()));
+        }
+        output.write_all(&item)?;
+    }
+}
+// Fan-out: subscribe to the ring's shared hoister, starting it if we're
+// first, and send what it publishes to us.  Block subscribers meter their
+// rate here; those that fall behind are cut off by the fan-out.
+
+fn pump_fanout<W: Write>(
+    output: &mut W,
+    ring: &str,
+    limits: HoistLimits,
+    options: &ProgramOptions,
+    comment: &str,
+) -> io::Result<()> {
+    let (fanout, subscription, new) = HOIST_MUX.subscribe(ring, limits, options.hoist_buffer);
+    if new {
+        start_fanout(ring, &fanout, options);
+    }
+    let throttle = match limits.policy {
+        Policy::Block => limits.rate.map(|rate| Throttle::new(rate, Instant::now())),
+        Policy::Sample => None,
+    };
+    let mut result = send_queued(&subscription.queue, output, throttle);
+    HOIST_MUX.unsubscribe(ring, &fanout, subscription.id);
+
+    if let Err(e) = &result {
+        info!("Hoisting {} ended: {}", ring, e);
+    } else if subscription.cut.load(Ordering::SeqCst) {
+        let reason = format!("fell more than {} bytes behind", options.hoist_buffer);
+        warn!(event = "hoist_cut", ring = ring; "{}: {}, disconnected", comment, reason);
+        result = Err(io::Error::other(reason));
+    }
+    let skipped = subscription.skipped.load(Ordering::SeqCst);
+    if skipped > 0 {
+        info!(
+            event = "hoist_sampled", ring = ring, skipped = skipped;
+            "Skipped {} ring items hoisting {} to keep up", skipped, ring
+        );
+    }
+    result
+}
+// Start the hoister a ring's fan-out shares.  A thread publishes its ring
+// items until it exits, which it's made to do when the last subscriber
+// leaves.
+
+fn start_fanout(ring: &str, fanout: &Arc<Fanout>, options: &ProgramOptions) {
+    let comment = format!("Fan-out hoisting of {}", ring);
+    let mut child = match spawn_hoister(process::Stdio::piped(), ring, options, &comment) {
+        Ok(child) => child,
+        Err(reason) => {
+            error!("Failed to start ring2stdout: {}", reason);
+            HOIST_MUX.retire(ring, fanout);
+            return;
+        }
+    };
+    let mut data = child.stdout.take().unwrap();
+    let child = Arc::new(Mutex::new(child));
+    {
+        let child = Arc::clone(&child);
+        fanout.set_stopper(Box::new(move || {
+            let _ = child.lock().unwrap().kill();
+        }));
+    }
+    let fanout = Arc::clone(fanout);
+    let ring = String::from(ring);
+    let max_item = options.hoist_buffer;
+    thread::spawn(move || {
+        info!("Started {}", comment);
+        let mut splitter = ItemSplitter::new(max_item);
+        let mut chunk = vec![0u8; HOIST_CHUNK];
+        'hoist: loop {
+            match data.read(&mut chunk) {
+                Ok(0) => break,
+                Ok(n) => splitter.push(&chunk[..n]),
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(_) => break,
+            }
+            loop {
+                match splitter.next_item() {
+                    Ok(Some(item)) => fanout.publish(&item, Instant::now()),
+                    Ok(None) => break,
+                    Err(e) => {
+                        warn!("{} ended: {}", comment, e);
+                        break 'hoist;
+                    }
+                }
+            }
+        }
+        HOIST_MUX.retire(&ring, &fanout);
+        let mut child = child.lock().unwrap();
+        let _ = child.kill();
+        let _ = child.wait();
+        info!("Ended {}", comment);
+    });
+}
+// Actually start the hoister.  The program options are set as follows:
+//      *  --directory - is set to the directory in which we know the rings live.
+//      *  --ring      - is the name of the ring passed in to the request.
+//      *  --port      - is the port manager port we're using.
+//      *  --service   - is our service name if it's not the default.
+//      *  --comment   - Is "Hoisting to {}" where {} is replaced by the
+//                       address of the request's peer.
+// What the hoister says on stderr (e.g. an older one's complaint about
+// --service) is logged.
+
+fn spawn_hoister(
     proc_stdout: process::Stdio,
-    rings_dir: &str,
     ring_name: &str,
-    portman: &str,
+    options: &ProgramOptions,
+    comment: &str,
+) -> io::Result<process::Child> {
+    let portman = options.portman.to_string();
+    let mut hoister = process::Command::new("ring2stdout");
+    hoister.args(&[
+        "--directory",
+        &options.directory,
+        "--ring",
+        ring_name,
+        "--port",
+        &portman,
+        "--comment",
+        comment,
+    ]);
+    // Older hoisters don't know --service so only pass it if it matters:
+
+    if options.service_name != SERVICE_NAME {
+        hoister.args(&["--service", &options.service_name]);
+    }
+    let mut child = hoister
+        .stdout(proc_stdout)
+        .stderr(process::Stdio::piped())
+        .stdin(process::Stdio::null())
+        .spawn()?;
+    if let Some(errors) = child.stderr.take() {
+        let comment = String::from(comment);
+        thread::spawn(move || {
+            for line in BufReader::new(errors).lines().map_while(Result::ok) {
+                warn!("ring2stdout ({}): {}", comment, line);
+            }
+        });
+    }
+    Ok(child)
+}
+
+///
+/// Start pushing a ring to a remote host (PUSH).  Only local clients
+/// may do this and the ring must be in our inventory.  The reply is OK
+/// once the push is started; PUSHES says how it's going from then on.
+///
+fn push_ring(stream: &mut TcpStream, target: PushTarget, options: &ProgramOptions, inventory: &SafeInventory) {
+    if !is_local_peer(stream) {
+        fail_request(stream, "PUSH must come from a local host");
+        return;
+    }
+    let ring_exists = inventory.lock().unwrap().contains_key(&target.ring);
+    if !ring_exists {
+        fail_request(
+            stream,
+            format!("{} is not in the ring master's inventory", target.ring).as_ref(),
+        );
+        return;
+    }
+    match add_push(target, options, inventory) {
+        Ok(()) => acknowledge_client_hookup(stream),
+        Err(reason) => fail_request(stream, &reason),
+    }
+}
+///
+/// Stop pushing a ring to a remote host (UNPUSH).  Only local clients
+/// may do this.
+///
+fn unpush_ring(stream: &mut TcpStream, ring: &str, address: &str) {
+    if !is_local_peer(stream) {
+        fail_request(stream, "UNPUSH must come from a local host");
+        return;
+    }
+    match PUSHES.remove(ring, address) {
+        Ok(()) => {
+            info!("Stopping the push of {} to {}", ring, address);
+            acknowledge_client_hookup(stream);
+        }
+        Err(reason) => fail_request(stream, &reason),
+    }
+}
+///
+/// Reply to PUSHES: OK followed by a line with a Tcl list of the pushes'
+/// statuses or, if json is true, a JSON array of them.
+///
+fn list_pushes(stream: &mut TcpStream, json: bool) {
+    let pushes = PUSHES.list();
+    let body = if json {
+        Value::from(pushes.iter().map(|push| push.to_json()).collect::<Vec<Value>>()).to_string()
+    } else {
+        let mut listing = tcllist::TclList::new();
+        for push in &pushes {
+            listing.add_element(&push.to_tcl().to_string());
+        }
+        let listing = listing.to_string();
+        listing[1..listing.len() - 1].to_string()
+    };
+    acknowledge_client_hookup(stream);
+    send_line(stream, &body);
+}
+///
+/// Start the hoists the hoist configuration file asks for.  The rings
+/// pushed need not exist yet; the pushes wait for them.  Proxy rings are
+/// made if need be.
+///
+fn start_configured_hoists(options: &ProgramOptions, inventory: &SafeInventory, events: &SafeEvents) {
+    for target in &options.hoist_config.pushes {
+        if let Err(reason) = add_push(target.clone(), options, inventory) {
+            warn!("Ignoring configured push: {}", reason);
+        }
+    }
+    for target in &options.hoist_config.proxies {
+        if let Err(reason) = add_proxy(target.clone(), options, inventory, events) {
+            warn!("Ignoring configured proxy: {}", reason);
+        }
+    }
+}
+// Add a push to the table of pushes and start it.
+
+fn add_push(target: PushTarget, options: &ProgramOptions, inventory: &SafeInventory) -> Result<(), String> {
+    let push = PUSHES.add(target, unix_time())?;
+    start_push(push, options, inventory);
+    Ok(())
+}
+// Start the thread that does a push: it connects to the remote and
+// hoists the ring into the connection until that fails, then, backing
+// off, connects again.  This goes on until the push is stopped.
+
+fn start_push(push: Arc<Push>, options: &ProgramOptions, inventory: &SafeInventory) {
+    let options = options.clone();
+    let inventory = Arc::clone(inventory);
+    thread::spawn(move || {
+        let ring = push.target.ring.clone();
+        let address = push.target.address();
+        let mut backoff = Backoff::new(LINK_MIN_BACKOFF, LINK_MAX_BACKOFF);
+        info!("Pushing {} to {}", ring, address);
+        while !push.link.is_stopped() {
+            push.link.connecting(unix_time());
+            let result = connect_push(&push.target, &inventory).and_then(|connection| {
+                push.link.connected(connection.try_clone().ok(), unix_time());
+                backoff.reset();
+                info!(
+                    event = "push_connected", ring = ring.as_str(), address = address.as_str();
+                    "Pushing {} to {}: connected", ring, address
+                );
+                push_data(&push, connection, &options)
+            });
+            if push.link.is_stopped() {
+                break;
+            }
+            let reason = result.err().unwrap_or_else(|| String::from("ring2stdout exited"));
+            warn!(
+                event = "push_failed", ring = ring.as_str(), address = address.as_str(), reason = reason.as_str();
+                "Pushing {} to {}: {}", ring, address, reason
+            );
+            push.link.waiting(&reason, unix_time());
+            if push.link.pause(backoff.next_delay()) {
+                break;
+            }
+        }
+        info!("Stopped pushing {} to {}", ring, address);
+    });
+}
+// Connect to a push's remote.  The ring must (still) be in the inventory.
+
+fn connect_push(target: &PushTarget, inventory: &SafeInventory) -> Result<TcpStream, String> {
+    let ring_exists = inventory.lock().unwrap().contains_key(&target.ring);
+    if !ring_exists {
+        return Err(format!("{} is not in the ring master's inventory", target.ring));
+    }
+    let connection = connect_link(&target.host, target.port)?;
+    keep_alive(&connection);
+    Ok(connection)
+}
+// Turn on TCP keepalive for a link connection so that a remote that's
+// gone is noticed even when the link is quiet.
+
+fn keep_alive(connection: &TcpStream) {
+    if let Err(e) = set_keepalive(connection, LINK_KEEPALIVE_IDLE, LINK_KEEPALIVE_INTERVAL, LINK_KEEPALIVE_PROBES) {
+        warn!("Unable to turn on keepalive for {:?}: {}", connection.peer_addr(), e);
+    }
+}
+// Connect to a port on a host, trying each of its addresses.
+
+fn connect_link(host: &str, port: u16) -> Result<TcpStream, String> {
+    let addresses = (host, port)
+        .to_socket_addrs()
+        .map_err(|e| format!("Unable to look up {}: {}", host, e))?;
+    let mut reason = format!("{} has no addresses", host);
+    for address in addresses {
+        match TcpStream::connect_timeout(&address, LINK_CONNECT_TIMEOUT) {
+            Ok(connection) => return Ok(connection),
+            Err(e) => reason = format!("Unable to connect to {}: {}", address, e),
+        }
+    }
+    Err(reason)
+}
+// Hoist a push's ring into its connection: what follows OK BINARY
+// FOLLOWS in a REMOTE reply, compressed if the push asks for that.  Err
+// if the connection failed.  While this goes on, watch_push notices a
+// remote that's gone when there's nothing to send.
+
+fn push_data(push: &Arc<Push>, connection: TcpStream, options: &ProgramOptions) -> Result<(), String> {
+    let target = &push.target;
+    let comment = format!("Pushing to {}", target.address());
+    let limits = target.options.limits(&options.hoist_limits);
+    let done = Arc::new(AtomicBool::new(false));
+    if let Ok(watched) = connection.try_clone() {
+        watch_push(Arc::clone(push), watched, Arc::clone(&done));
+    }
+    let result = push_connection(push, &connection, limits, options, &comment);
+    done.store(true, Ordering::SeqCst);
+    let _ = connection.shutdown(Shutdown::Both); // Ends watch_push.
+    result
+}
+// Watch a push connection for the remote going away.  The remote has
+// nothing to say, so the read ends on EOF or, with keepalive, when the
+// remote stops answering probes.  The push is then marked waiting and the
+// connection shut down so that the next write fails and the push
+// reconnects.  done says push_data has finished with the connection.
+
+fn watch_push(push: Arc<Push>, mut connection: TcpStream, done: Arc<AtomicBool>) {
+    thread::spawn(move || {
+        let mut discard = [0u8; 512];
+        let reason = loop {
+            match connection.read(&mut discard) {
+                Ok(0) => break String::from("The remote closed the connection"),
+                Ok(_) => continue,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => break e.to_string(),
+            }
+        };
+        if !done.load(Ordering::SeqCst) && !push.link.is_stopped() {
+            push.link.waiting(&reason, unix_time());
+            let _ = connection.shutdown(Shutdown::Both);
+        }
+    });
+}
+// Pump the ring into a push's connection.
+
+fn push_connection(
+    push: &Arc<Push>,
+    connection: &TcpStream,
+    limits: HoistLimits,
+    options: &ProgramOptions,
     comment: &str,
+) -> Result<(), String> {
+    let target = &push.target;
+    let mut output = LinkWriter::new(connection, &push.link);
+    let result = match target.options.compress {
+        Some(codec) => {
+            let mut compressor = Compressor::new(codec, &mut output).map_err(|e| e.to_string())?;
+            let result = pump_hoister(&mut compressor, &target.ring, limits, options, comment);
+            result.and(compressor.finish().map(|_| ()))
+        }
+        None => pump_hoister(&mut output, &target.ring, limits, options, comment),
+    };
+    result.map_err(|e| e.to_string())
+}
+///
+/// Fill a proxy ring from a ring on another host (PROXY).  Only local
+/// clients may do this.  The proxy ring is made if need be.  The reply
+/// is OK once the proxy is started; PROXIES says how it's going from then
+/// on.
+///
+fn proxy_ring(
+    stream: &mut TcpStream,
+    target: ProxyTarget,
+    options: &ProgramOptions,
+    inventory: &SafeInventory,
+    events: &SafeEvents,
+) {
+    if !is_local_peer(stream) {
+        fail_request(stream, "PROXY must come from a local host");
+        return;
+    }
+    match add_proxy(target, options, inventory, events) {
+        Ok(()) => acknowledge_client_hookup(stream),
+        Err(reason) => fail_request(stream, &reason),
+    }
+}
+///
+/// Stop filling a proxy ring (UNPROXY).  Only local clients may do this.
+/// The proxy ring is left alone.
+///
+fn unproxy_ring(stream: &mut TcpStream, target: &ProxyTarget) {
+    if !is_local_peer(stream) {
+        fail_request(stream, "UNPROXY must come from a local host");
+        return;
+    }
+    let ring = target.proxy_ring();
+    match PROXIES.remove(&ring) {
+        Ok(()) => {
+            info!("Stopping the proxy {}", ring);
+            acknowledge_client_hookup(stream);
+        }
+        Err(reason) => fail_request(stream, &reason),
+    }
+}
+///
+/// Reply to PROXIES: OK followed by a line with a Tcl list of the
+/// proxies' statuses or, if json is true, a JSON array of them.
+///
+fn list_proxies(stream: &mut TcpStream, json: bool) {
+    let proxies = PROXIES.list();
+    let body = if json {
+        Value::from(proxies.iter().map(|proxy| proxy.to_json()).collect::<Vec<Value>>()).to_string()
+    } else {
+        let mut listing = tcllist::TclList::new();
+        for proxy in &proxies {
+            listing.add_element(&proxy.to_tcl().to_string());
+        }
+        let listing = listing.to_string();
+        listing[1..listing.len() - 1].to_string()
+    };
+    acknowledge_client_hookup(stream);
+    send_line(stream, &body);
+}
+// Make a proxy's ring, add it to the table of proxies and start it.
+
+fn add_proxy(
+    target: ProxyTarget,
+    options: &ProgramOptions,
+    inventory: &SafeInventory,
+    events: &SafeEvents,
+) -> Result<(), String> {
+    if target.options.tls && options.proxy_tls.is_none() {
+        return Err(String::from("TLS proxies need --proxy-tls-ca"));
+    }
+    make_proxy_ring(&target.proxy_ring(), false, options, inventory, events)?;
+    let proxy = PROXIES.add(target, unix_time())?;
+    start_proxy(proxy, options, inventory, events);
+    Ok(())
+}
+// Make a proxy ring (--proxy-size bytes with all the consumer slots) and
+// add it to the inventory unless it's already there.  A proxy remaking
+// its ring only does so while it's in PROXIES: the check is under the
+// inventory lock, as DELETE's is, so a ring DELETE removes after UNPROXY
+// stays removed.
+
+fn make_proxy_ring(
+    ring: &str,
+    remake: bool,
+    options: &ProgramOptions,
+    inventory: &SafeInventory,
+    events: &SafeEvents,
+) -> Result<(), String> {
+    if !ringfile::ringfile::valid_ring_name(ring) {
+        return Err(format!("{} is not a valid ring name", ring));
+    }
+    {
+        let mut inventory = inventory.lock().unwrap();
+        if inventory.contains_key(ring) {
+            return Ok(());
+        }
+        if remake && !PROXIES.contains(ring) {
+            return Err(format!("The proxy filling {} was stopped", ring));
+        }
+        let full_path = compute_ring_buffer_path(&options.directory, ring);
+        ringfile::ringfile::create_ring(
+            &full_path,
+            options.proxy_size,
+            ringfile::ringfile::MAX_CONSUMER_SLOTS,
+            options.ring_mode,
+        )
+        .map_err(|e| format!("Unable to make proxy ring {}: {}", ring, e))?;
+        add_ring(&full_path, &mut inventory);
+    }
+    info!("Made proxy ring {}", ring);
+    publish(events, RingEvent::Registered { ring: String::from(ring) });
+    Ok(())
+}
+// Start the thread that fills a proxy ring: it asks the remote ring
+// master for the ring and puts what it gets into the proxy ring until the
+// connection fails, then, backing off, asks again.  This goes on until
+// the proxy is stopped.
+
+fn start_proxy(proxy: Arc<Proxy>, options: &ProgramOptions, inventory: &SafeInventory, events: &SafeEvents) {
+    let options = options.clone();
+    let inventory = Arc::clone(inventory);
+    let events = Arc::clone(events);
+    thread::spawn(move || {
+        let ring = proxy.target.proxy_ring();
+        let host = proxy.target.host.clone();
+        let mut backoff = Backoff::new(LINK_MIN_BACKOFF, LINK_MAX_BACKOFF);
+        info!("Proxying {} from {} into {}", proxy.target.ring, host, ring);
+        while !proxy.link.is_stopped() {
+            proxy.link.connecting(unix_time());
+            let result = fill_proxy(&proxy, &options, &inventory, &events, &mut backoff);
+            if proxy.link.is_stopped() {
+                break;
+            }
+            let reason = result.err().unwrap_or_else(|| String::from("The remote ring master stopped hoisting"));
+            warn!(
+                event = "proxy_failed", ring = ring.as_str(), host = host.as_str(), reason = reason.as_str();
+                "Proxy {}: {}", ring, reason
+            );
+            proxy.link.waiting(&reason, unix_time());
+            if proxy.link.pause(backoff.next_delay()) {
+                break;
+            }
+        }
+        info!("Stopped the proxy {}", ring);
+    });
+}
+// One connection's worth of filling a proxy ring.  The proxy ring is
+// remade if it was deleted.  We're its producer while connected.
+
+fn fill_proxy(
+    proxy: &Proxy,
+    options: &ProgramOptions,
+    inventory: &SafeInventory,
+    events: &SafeEvents,
+    backoff: &mut Backoff,
+) -> Result<(), String> {
+    let target = &proxy.target;
+    let ring = target.proxy_ring();
+    make_proxy_ring(&ring, true, options, inventory, events)?;
+    let map = ringbuffer::RingBufferMap::new(&compute_ring_buffer_path(&options.directory, &ring))?;
+    let max_item = map.data_bytes();
+    let mut producer = ringbuffer::producer::Producer::attach(&Arc::new(Mutex::new(map)))
+        .map_err(|_| format!("{} already has a producer", ring))?;
+
+    let portman = target.portman.unwrap_or(options.portman);
+    let service = target.service.as_deref().unwrap_or(SERVICE_NAME);
+    let port = find_service(&target.host, portman, service, LINK_CONNECT_TIMEOUT)?;
+    let connection = connect_link(&target.host, port)?;
+    keep_alive(&connection);
+    let handle = connection.try_clone().ok();
+    let (data, codec) = request_remote(target, connection, options)?;
+
+    proxy.link.connected(handle, unix_time());
+    backoff.reset();
+    info!(
+        event = "proxy_connected", ring = ring.as_str(), host = target.host.as_str();
+        "Proxy {}: connected", ring
+    );
+    let data = LinkReader::new(data, &proxy.link);
+    match codec {
+        Some(codec) => {
+            let data = Decompressor::new(codec, data).map_err(|e| e.to_string())?;
+            fill_ring(&mut producer, data, max_item, &proxy.link)
+        }
+        None => fill_ring(&mut producer, data, max_item, &proxy.link),
+    }
+}
+// Ask a remote ring master for a proxy's ring with REMOTE, doing TLS if
+// it's asked for.  What's returned reads the data and says how they're
+// compressed.
+
+fn request_remote(
+    target: &ProxyTarget,
+    connection: TcpStream,
+    options: &ProgramOptions,
+) -> Result<(Box<dyn Read + Send>, Option<Codec>), String> {
+    let _ = connection.set_read_timeout(Some(LINK_CONNECT_TIMEOUT));
+    (&connection)
+        .write_all(format!("{}\n", target.remote_request()).as_bytes())
+        .map_err(|e| format!("Unable to send REMOTE: {}", e))?;
+    let mut reader = BufReader::new(connection);
+    match read_remote_reply(&mut reader)? {
+        RemoteReply::BinaryFollows(codec) => {
+            let _ = reader.get_ref().set_read_timeout(None);
+            Ok((Box::new(reader), codec))
+        }
+        RemoteReply::Tls => {
+            let tls = options
+                .proxy_tls
+                .as_ref()
+                .ok_or_else(|| String::from("The remote ring master wants TLS and we have no --proxy-tls-ca"))?;
+            let secure = tls.connect(&target.host, reader.into_inner())?;
+            let mut reader = BufReader::new(secure);
+            match read_remote_reply(&mut reader)? {
+                RemoteReply::BinaryFollows(codec) => {
+                    let _ = reader.get_ref().sock.set_read_timeout(None);
+                    Ok((Box::new(reader), codec))
+                }
+                RemoteReply::Tls => Err(String::from("The remote ring master asked for TLS twice")),
+            }
+        }
+    }
+}
+// Read a reply line to REMOTE.
+
+fn read_remote_reply<R: BufRead>(reader: &mut R) -> Result<RemoteReply, String> {
+    let mut line = String::new();
+    match reader.read_line(&mut line) {
+        Ok(0) => Err(String::from("The remote ring master closed the connection")),
+        Ok(_) => RemoteReply::parse(&line),
+        Err(e) => Err(format!("No reply to REMOTE: {}", e)),
+    }
+}
+// Put the ring items read from data into a proxy ring until data end.
+// Only whole items go in, and none bigger than max_item (the ring's data
+// size).  While the ring is full we check for the proxy being stopped
+// every PROXY_PUT_WAIT.
+
+fn fill_ring<R: Read>(
+    producer: &mut ringbuffer::producer::Producer,
+    mut data: R,
+    max_item: usize,
+    link: &Link,
+) -> Result<(), String> {
+    let mut splitter = ItemSplitter::new(max_item);
+    let mut chunk = vec![0u8; HOIST_CHUNK];
+    loop {
+        match data.read(&mut chunk) {
+            Ok(0) => return Ok(()),
+            Ok(n) => splitter.push(&chunk[..n]),
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e.to_string()),
+        }
+        while let Some(item) = splitter.next_item().map_err(|e| e.to_string())? {
+            loop {
+                match producer.timed_put(&item, PROXY_PUT_WAIT) {
+                    Ok(_) => break,
+                    Err(ringbuffer::producer::Error::Timeout) if !link.is_stopped() => continue,
+                    Err(ringbuffer::producer::Error::Timeout) => return Ok(()),
+                    Err(e) => return Err(ringbuffer::producer::error_string(&e)),
+                }
+            }
+        }
+    }
+}
+// Now in seconds since the epoch.
+
+fn unix_time() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |t| t.as_secs())
+}
+///
+/// Start the thread that periodically sweeps the rings in the inventory
+/// for slots held by processes that no longer exist.  A zero
+/// reap interval disables the sweep.
+///
+fn start_reaper(options: &ProgramOptions, inventory: &SafeInventory, events: &SafeEvents) {
+    if options.reap_interval == 0 {
+        info!("Stale slot reaper is disabled");
+        return;
+    }
+    let interval = Duration::from_secs(options.reap_interval);
+    let dir = options.directory.clone();
+    let dry_run = options.reap_dry_run;
+    let inventory = Arc::clone(inventory);
+    let events = Arc::clone(events);
+    info!(
+        "Stale slot reaper will sweep every {} seconds (dry run: {})",
+        options.reap_interval, dry_run
+    );
+    thread::spawn(move || {
+        let mut total: usize = 0;
+        let mut reported = HashSet::<(String, rings::rings::Client)>::new();
+        loop {
+            thread::sleep(interval);
+            let freed = reap_rings(&dir, &inventory, &events, dry_run);
+
+            // A dry run leaves the slots alone so they turn up on every
+            // sweep.  Only count (and log) the ones we've not reported yet.
+
+            let reclaimed = if dry_run {
+                let current: HashSet<_> = freed.into_iter().collect();
+                for (ring, client) in current.difference(&reported) {
+                    info!("Reaper would free {:?} on ring {}", client, ring);
+                }
+                let fresh = current.difference(&reported).count();
+                reported = current;
+                fresh
+            } else {
+                freed.len()
+            };
+            total += reclaimed;
+            if reclaimed > 0 {
+                info!(
+                    "Reaper reclaimed {} slots this sweep, {} since startup",
+                    reclaimed, total
+                );
+            }
+        }
+    });
+}
+///
+/// Start the thread that periodically checks the rings against the
+/// alarm thresholds.  Nothing is started if no thresholds are set or the
+/// interval is zero.
+///
+fn start_alarm_monitor(options: &ProgramOptions, inventory: &SafeInventory, alarms: &SafeAlarms) {
+    if options.alarm_interval == 0 || options.alarms.is_empty() {
+        info!("Ring alarms are disabled");
+        return;
+    }
+    let interval = Duration::from_secs(options.alarm_interval);
+    let dir = options.directory.clone();
+    let command = options.alarm_command.clone();
+    let inventory = Arc::clone(inventory);
+    let alarms = Arc::clone(alarms);
+    info!("Ring alarms will be checked every {} seconds", options.alarm_interval);
+    thread::spawn(move || loop {
+        thread::sleep(interval);
+        check_alarms(&dir, &inventory, &alarms, command.as_deref());
+    });
+}
+///
+/// Watch our advertisement in the port manager.  If the port manager
+/// goes away (e.g. it's restarted) or forgets us, get a service port from
+/// it again, retrying with backoff until we do.  If the new port is not
+/// the old one, listen on it and retire the old listener.  Never returns.
+///
+fn supervise_portman(
+    mut port_man: portman_client::Client,
+    options: ProgramOptions,
+    inventory: SafeInventory,
+    events: SafeEvents,
+    alarms: SafeAlarms,
+    stats: SafeStats,
+    port: SafePort,
 ) {
-    let hoister = process::Command::new("ring2stdout")
-        .args(&[
-            "--directory",
-            rings_dir,
-            "--ring",
-            ring_name,
-            "--port",
-            portman,
-            "--comment",
-            comment,
+    loop {
+        thread::sleep(PORTMAN_CHECK_INTERVAL);
+        let current = port.load(Ordering::SeqCst);
+        match portman_request(&mut port_man, |p| p.find_my_service(&options.service_name)) {
+            Ok(allocations) if allocations.iter().any(|a| a.port == current) => continue,
+            Ok(_) => warn!(event = "portman_lost"; "The port manager no longer advertises us on port {}", current),
+            Err(e) => warn!(event = "portman_lost"; "Lost the port manager: {}", e),
+        }
+        // Get advertised again:
+
+        let mut backoff = PORTMAN_MIN_BACKOFF;
+        let (client, new_port, listeners) = loop {
+            let mut client = portman_client::Client::new(options.portman);
+            let attempt = portman_request(&mut client, |c| c.get(&options.service_name)).and_then(|p| {
+                if p == current {
+                    Ok((p, None))
+                } else {
+                    bind_listeners(&options.listen, p).map(|l| (p, Some(l)))
+                }
+            });
+            match attempt {
+                Ok((p, listeners)) => break (client, p, listeners),
+                Err(e) => {
+                    warn!("Unable to advertise with the port manager (retry in {:?}): {}", backoff, e);
+                    thread::sleep(backoff);
+                    backoff = (backoff * 2).min(PORTMAN_MAX_BACKOFF);
+                }
+            }
+        };
+        port_man = client;
+        info!(event = "portman_advertised", port = new_port; "Advertised with the port manager on port {}", new_port);
+        if let Some(listeners) = listeners {
+            port.store(new_port, Ordering::SeqCst);
+            let retire = Some(Arc::clone(&port));
+            spawn_acceptors(listeners, &options, &inventory, &events, &alarms, &stats, retire);
+            info!("Moved from port {} to port {}", current, new_port);
+
+            // Wake up the old listeners so they see they're retired.  We
+            // reach wildcard listeners through loopback:
+
+            for address in &options.listen {
+                let address = match address {
+                    IpAddr::V4(a) if a.is_unspecified() => IpAddr::V4(Ipv4Addr::LOCALHOST),
+                    IpAddr::V6(a) if a.is_unspecified() => IpAddr::V6(Ipv6Addr::LOCALHOST),
+                    a => *a,
+                };
+                let _ = TcpStream::connect_timeout(&SocketAddr::new(address, current), Duration::from_secs(1));
+            }
+        }
+    }
+}
+///
+/// Make a port manager request for supervise_portman.  The port manager
+/// client unwraps read errors (e.g. when the port manager dies) and that
+/// must not take the ring master down, so a panic is an Err like any other
+/// failure.  The client is not used again after one.
+///
+fn portman_request<T>(
+    client: &mut portman_client::Client,
+    request: impl FnOnce(&mut portman_client::Client) -> Result<T, portman_client::Error>,
+) -> Result<T, String> {
+    match panic::catch_unwind(panic::AssertUnwindSafe(|| request(client))) {
+        Ok(result) => result.map_err(|e| e.to_string()),
+        Err(_) => Err(String::from("The port manager connection failed")),
+    }
+}
+///
+/// Tell systemd we're ready and start the thread that keeps our status
+/// up to date and, if systemd wants it, pings the watchdog.  Pings are
+/// only sent after the inventory lock has been gotten so a deadlocked
+/// ringmaster gets restarted.
+///
+fn start_systemd_notifier(notifier: Notifier, inventory: &SafeInventory, port: &SafePort) {
+    let watchdog = systemd::watchdog_interval();
+    let interval = watchdog.map_or(SYSTEMD_STATUS_INTERVAL, |w| w.min(SYSTEMD_STATUS_INTERVAL));
+    let inventory = Arc::clone(inventory);
+    let port = Arc::clone(port);
+    if let Err(e) = notifier.ready(&systemd_status(&inventory, &port)) {
+        warn!("Unable to notify systemd that we're ready: {}", e);
+    }
+    info!("Notified systemd we're ready; watchdog interval {:?}", watchdog);
+    thread::spawn(move || loop {
+        thread::sleep(interval);
+        let mut state = format!("STATUS={}", systemd_status(&inventory, &port));
+        if watchdog.is_some() {
+            state.push_str("\nWATCHDOG=1");
+        }
+        if let Err(e) = notifier.notify(&state) {
+            warn!("Unable to notify systemd: {}", e);
+        }
+    });
+}
+///
+/// The status line we give systemd: rings and clients we know of.
+///
+fn systemd_status(inventory: &SafeInventory, port: &SafePort) -> String {
+    let inventory = inventory.lock().unwrap();
+    let clients: usize = inventory.values().map(|info| info.client_records().len()).sum();
+    format!(
+        "Serving {} rings with {} clients on port {}",
+        inventory.len(),
+        clients,
+        port.load(Ordering::SeqCst)
+    )
+}
+///
+/// Start the thread that periodically samples the positions of each
+/// ring's clients for STATS.  Nothing is started if the interval is zero.
+///
+fn start_stats_sampler(options: &ProgramOptions, inventory: &SafeInventory, stats: &SafeStats) {
+    if options.stats_interval == 0 {
+        info!("Ring statistics are disabled");
+        return;
+    }
+    let interval = Duration::from_secs(options.stats_interval);
+    let dir = options.directory.clone();
+    let inventory = Arc::clone(inventory);
+    let stats = Arc::clone(stats);
+    info!("Ring statistics will be sampled every {} seconds", options.stats_interval);
+    thread::spawn(move || loop {
+        sample_stats(&dir, &inventory, &stats);
+        thread::sleep(interval);
+    });
+}
+///
+/// Record the client positions of every ring in the inventory.
+///
+fn sample_stats(dir: &str, inventory: &SafeInventory, stats: &SafeStats) {
+    let names = inventory.lock().unwrap().keys().cloned().collect::<Vec<String>>();
+    let samples = names
+        .iter()
+        .filter_map(|name| {
+            RingPositions::read(&compute_ring_buffer_path(dir, name))
+                .ok()
+                .map(|positions| (name.clone(), positions, Instant::now()))
+        })
+        .collect::<Vec<_>>();
+    let mut collector = stats.lock().unwrap();
+    collector.retain_rings(&names);
+    for (name, positions, when) in samples {
+        collector.record(&name, positions, when);
+    }
+}
+///
+/// Sample every ring in the inventory and evaluate the alarm thresholds.
+/// Raised alarms are logged as warnings and passed to the alarm command,
+/// if there is one.  Cleared alarms are logged.
+///
+fn check_alarms(dir: &str, inventory: &SafeInventory, alarms: &SafeAlarms, command: Option<&str>) {
+    let names = inventory.lock().unwrap().keys().cloned().collect::<Vec<String>>();
+
+    // Sample the rings before locking the monitor.  Rings that can't be
+    // read are skipped; LIST cleans these up.
+
+    let samples = names
+        .iter()
+        .filter_map(|name| {
+            ring_sample(&compute_ring_buffer_path(dir, name)).map(|s| (name.clone(), s))
+        })
+        .collect::<Vec<(String, RingSample)>>();
+    let mut monitor = alarms.lock().unwrap();
+    for alarm in monitor.retain_rings(&names) {
+        info!("Alarm cleared, ring gone: {}", describe_alarm(&alarm));
+    }
+    for (name, sample) in samples {
+        let changes = monitor.evaluate(&name, &sample, Instant::now());
+        for alarm in changes.raised {
+            warn!(
+                event = "alarm_raised",
+                ring = alarm.ring.as_str(),
+                condition = alarm.kind.name(),
+                value = alarm.value;
+                "Alarm raised: {}", describe_alarm(&alarm)
+            );
+            if let Some(command) = command {
+                run_alarm_command(command, &alarm);
+            }
+        }
+        for alarm in changes.cleared {
+            info!(
+                event = "alarm_cleared",
+                ring = alarm.ring.as_str(),
+                condition = alarm.kind.name();
+                "Alarm cleared: {}", describe_alarm(&alarm)
+            );
+        }
+    }
+}
+///
+/// Sample a ring for the alarm monitor from one snapshot of its
+/// client positions.  None if the ring can't be read.
+///
+fn ring_sample(path: &str) -> Option<RingSample> {
+    let positions = RingPositions::read(path).ok()?;
+    let usage = positions.usage();
+    let consumers = positions
+        .consumers
+        .iter()
+        .zip(usage.consumer_usage.iter())
+        .map(|(c, u)| ConsumerSample {
+            slot: c.slot,
+            pid: c.pid,
+            backlog: u.available,
+            offset: Some(c.offset),
+        })
+        .collect::<Vec<ConsumerSample>>();
+    Some(RingSample {
+        data_bytes: positions.data_bytes,
+        free_space: usage.free_space,
+        producer_pid: positions.producer.map(|p| p.pid),
+        consumers,
+    })
+}
+///
+/// Describe an alarm for the log.
+///
+fn describe_alarm(alarm: &Alarm) -> String {
+    format!(
+        "{} on ring {} slot {:?} pid {:?} value {:.3}",
+        alarm.kind.name(),
+        alarm.ring,
+        alarm.slot,
+        alarm.pid,
+        alarm.value
+    )
+}
+///
+/// Run the alarm command with the ring, pid (-1 if none), condition
+/// and value as parameters.  We don't wait for it but a thread reaps it
+/// so it does not linger as a zombie.
+///
+fn run_alarm_command(command: &str, alarm: &Alarm) {
+    let pid = alarm.pid.map_or(String::from("-1"), |p| p.to_string());
+    let child = process::Command::new(command)
+        .args([
+            alarm.ring.as_str(),
+            pid.as_str(),
+            alarm.kind.name(),
+            &format!("{:.3}", alarm.value),
         ])
-        .stdout(proc_stdout)
-        .stderr(process::Stdio::null())
         .stdin(process::Stdio::null())
+        .stdout(process::Stdio::null())
+        .stderr(process::Stdio::null())
         .spawn();
-    
-    match hoister {
+    match child {
         Ok(mut child) => {
-            let _ = child.wait();
-        },
-        Err(reason) => error!("Failed to start ring2stdout: {}", reason),
-    };
-        
-    // Waits until the command completes/reaping the status etc.
+            thread::spawn(move || {
+                let _ = child.wait();
+            });
+        }
+        Err(e) => error!("Unable to run alarm command {}: {}", command, e),
+    }
+}
+///
+/// Make one reaper sweep over all rings in the inventory.
+/// Each slot that is reclaimed is logged and its client is
+/// removed from the ring's client list.   In a dry run, nothing is freed
+/// or logged here; the caller decides which of the slots are news.
+/// Rings that can't be mapped are skipped; LIST cleans those up.
+///
+/// The sweep works from a snapshot of the ring names so the inventory
+/// is only locked briefly, to look up client identities and to
+/// unregister the clients of freed slots.
+///
+/// Returns the ring and client of each slot that was (or would have been)
+/// reclaimed.
+///
+fn reap_rings(
+    dir: &str,
+    inventory: &SafeInventory,
+    events: &SafeEvents,
+    dry_run: bool,
+) -> Vec<(String, rings::rings::Client)> {
+    let names: Vec<String> = inventory.lock().unwrap().keys().cloned().collect();
+    let mut result = Vec::new();
+    for name in names {
+        let ring_file = compute_ring_buffer_path(dir, &name);
+        let known = |pid| {
+            inventory
+                .lock()
+                .unwrap()
+                .get(&name)
+                .and_then(|info| info.client_identity(pid))
+        };
+        let reclaimed = match reaper::reaper::reap_ring(&ring_file, &known, dry_run) {
+            Ok(r) => r,
+            Err(_) => continue,
+        };
+        for client in reclaimed {
+            if !dry_run {
+                let pid = match client {
+                    rings::rings::Client::Producer { pid } => pid,
+                    rings::rings::Client::Consumer { pid, slot: _slot } => pid,
+                };
+                info!("Reaper freed {:?} on ring {}", client, name);
+                if let Some(info) = inventory.lock().unwrap().get_mut(&name) {
+                    info.unregister_client(pid);
+                }
+                publish(events, RingEvent::Disconnected { ring: name.clone(), client });
+            }
+            result.push((name.clone(), client));
+        }
+    }
+    result
 }
 
 /// Given a ring info struct, and it's name turns it into a Tcl list that
 /// describes that ring.
 ///
-fn format_ring_info(info: RingInfo) -> String {
+/// If processes is supplied (LIST VERBOSE), the consumer sublists get the
+/// slot index, executable name and user appended and the ring information
+/// gets a final {name user} element describing the producer ({} if none).
+/// Appending keeps the positions existing parsers rely on.
+///
+fn format_ring_info(info: RingInfo, processes: Option<&HashMap<u32, ProcessDescription>>) -> String {
     let mut result = tcllist::TclList::new();
     result.add_element(&info.name);
     let mut ring_info = tcllist::TclList::new();
@@ -827,17 +3088,45 @@ fn format_ring_info(info: RingInfo) -> String {
     // Now a sublist for each consumer:
 
     let mut consumer_list = tcllist::TclList::new();
-    for consumer in info.info.consumer_usage {
+    for (i, consumer) in info.info.consumer_usage.iter().enumerate() {
         let mut consumer_info = tcllist::TclList::new();
         consumer_info
             .add_element(&consumer.pid.to_string())
             .add_element(&consumer.available.to_string());
+        if let Some(processes) = processes {
+            // A consumer could attach between get_usage and the slot scan:
+
+            match info.slots.get(i) {
+                Some(slot) => consumer_info.add_element(&slot.to_string()),
+                None => consumer_info.add_element("-1"),
+            };
+            add_process_description(&mut consumer_info, processes.get(&consumer.pid));
+        }
         consumer_list.add_sublist(Box::new(consumer_info));
     }
     ring_info.add_sublist(Box::new(consumer_list));
+    if let Some(processes) = processes {
+        let mut producer_info = tcllist::TclList::new();
+        if info.info.producer_pid != ringbuffer::UNUSED_ENTRY {
+            add_process_description(&mut producer_info, processes.get(&info.info.producer_pid));
+        }
+        ring_info.add_sublist(Box::new(producer_info));
+    }
     result.add_sublist(Box::new(ring_info));
     result.to_string()
 }
+///
+/// Add the executable name and user of a process to a list.  Unknown
+/// values (e.g. the process exited) are empty elements.
+///
+fn add_process_description(list: &mut tcllist::TclList, process: Option<&ProcessDescription>) {
+    match process {
+        Some(p) => list
+            .add_quoted_element(&p.name)
+            .add_quoted_element(p.user.as_deref().unwrap_or("")),
+        None => list.add_quoted_element("").add_quoted_element(""),
+    };
+}
 /// get_ring_list_info
 ///   Given a ringbuffer - get the ring's information for the LIST - we're given the name
 /// and directory string:
@@ -848,19 +3137,19 @@ fn format_ring_info(info: RingInfo) -> String {
 fn get_ring_list_info(dir: &str, name: &str) -> Result<RingInfo, String> {
     let path = compute_ring_buffer_path(dir, name);
 
-    match ringbuffer::RingBufferMap::new(&path) {
-        Ok(mut map) => {
-            let usage = map.get_usage();
-            Ok(RingInfo {
-                name: String::from(name),
-                size: map.data_bytes(),
-                max_consumers: map.max_consumers(),
-                min_get: min_gettable(&usage),
-                info: usage,
-            })
-        }
-        Err(e) => Err(e),
-    }
+    // One snapshot of the positions gives both the slots and the usage
+    // so consumers coming and going can't shift one against the other.
+
+    let positions = RingPositions::read(&path)?;
+    let usage = positions.usage();
+    Ok(RingInfo {
+        name: String::from(name),
+        size: positions.data_bytes,
+        max_consumers: positions.max_consumers,
+        min_get: min_gettable(&usage),
+        info: usage,
+        slots: positions.consumers.iter().map(|c| c.slot).collect(),
+    })
 }
 ///
 /// Return the minimum gettable bytes in a ring:
@@ -909,11 +3198,70 @@ fn read_request(reader: &mut BufReader<TcpStream>) -> Vec<String> {
     }
     result
 }
+/// Log a request.  The fields are the verb, the ring (if the request
+/// has one) and, for CONNECT and DISCONNECT, the pid the client claims.
+///
+fn log_request(stream: &TcpStream, request: &[String]) {
+    let verb = request.first().map_or("", |v| v.as_str());
+    let ring = match verb {
+        "STATUS" | "STATS" | "REGISTER" | "UNREGISTER" | "CREATE" | "DELETE" | "REMOTE" | "PUSH"
+        | "UNPUSH" => {
+            request.get(1).cloned()
+        }
+        "CONNECT" | "DISCONNECT" => request.get(1).map(|r| strip_braces(r)),
+        "PROXY" | "UNPROXY" => request.get(2).cloned(),
+        _ => None,
+    };
+    let pid = match verb {
+        "CONNECT" | "DISCONNECT" => request.get(3).and_then(|p| p.parse::<u32>().ok()),
+        _ => None,
+    };
+    info!(
+        event = "request",
+        verb = verb,
+        ring = ring.as_deref(),
+        pid = pid,
+        peer = peer_name(stream).as_str();
+        "Request : {}", request.join(" ")
+    );
+}
+///
+/// Log a client claiming a pid other than the one it used before.
+///
+fn log_pid_spoof(stream: &TcpStream, ring: &str, claimed: u32, actual: u32) {
+    warn!(
+        event = "pid_spoof",
+        ring = ring,
+        pid = claimed,
+        previous_pid = actual,
+        peer = peer_name(stream).as_str();
+        "PID spoof attempt on {}: claimed {} but was {}", ring, claimed, actual
+    );
+}
+///
+/// The peer of a stream as text (empty if that's not known).
+///
+fn peer_name(stream: &TcpStream) -> String {
+    stream.peer_addr().map_or(String::new(), |p| p.to_string())
+}
 /// Fail a request by, if possible writing a failure
 /// string to the peer and shutting down the socket.
 ///
 ///
 fn fail_request(stream: &mut TcpStream, reason: &str) {
+    warn!(
+        event = "request_failed",
+        outcome = "FAIL",
+        peer = peer_name(stream).as_str(),
+        reason = reason;
+        "Request failed: {}", reason
+    );
+    send_fail(stream, reason);
+}
+///
+/// Write FAIL and the reason to the peer and shut down the socket.
+///
+fn send_fail(stream: &mut TcpStream, reason: &str) {
     if let Ok(_) = stream.write_all(format!("FAIL {}\r\n", reason).as_bytes()) {}
     if let Ok(_) = stream.flush() {}
     if let Ok(_) = stream.shutdown(Shutdown::Both) {}
@@ -926,6 +3274,32 @@ fn fail_request(stream: &mut TcpStream, reason: &str) {
 /// *   --directory   - The directory in which we look for ringbuffer
 /// backing files.
 /// *   --log-file the file we'll use to log what we're doing
+/// *   --log-target file, syslog or stderr - where logs go.
+/// *   --log-level the most detailed level of message logged.
+/// *   --log-format text or json log lines.
+/// *   --log-max-size, --log-max-age rotate the log file when it gets this big/old.
+/// *   --log-keep number of rotated log files kept.
+/// *   --reap-interval seconds between sweeps for slots held by dead processes.
+/// *   --reap-dry-run   only log what the reaper would free.
+/// *   --unregister-policy what UNREGISTER does to a ring's clients by default.
+/// *   --kill-grace     seconds between SIGTERM and SIGKILL when killing clients.
+/// *   --ring-mode      octal permissions of rings made by CREATE.
+/// *   --lock-dir       where the instance lock files live.
+/// *   --listen         addresses we accept requests on.
+/// *   --service-name   the service we advertise with the port manager.
+/// *   --per-user       qualify the service name with our username.
+/// *   --tls-cert       certificate for REMOTE ... TLS.
+/// *   --tls-key        private key for the certificate.
+/// *   --tls-client-ca  CA that REMOTE ... TLS clients' certificates need.
+/// *   --tls-required   refuse REMOTE requests without TLS.
+/// *   --hoist-rate     most bytes/second a REMOTE hoist may send.
+/// *   --hoist-policy   block or sample when a REMOTE hoist falls behind.
+/// *   --hoist-buffer   bytes a REMOTE hoist can fall behind.
+/// *   --hoist-fanout   REMOTE hoists of a ring share one hoister.
+/// *   --hoist-config   file of hoists (pushes and proxies) to start with.
+/// *   --proxy-size     bytes of data in the proxy rings PROXY makes.
+/// *   --proxy-tls-ca   CA that signs remote ring masters' certificates for PROXY ... TLS.
+/// *   --takeover       kill the ringmaster holding our instance lock.
 ///
 fn process_options() -> ProgramOptions {
     // Define the program options to Clap and process parameters with it:
@@ -962,15 +3336,283 @@ fn process_options() -> ProgramOptions {
                 .action(ArgAction::Set)
                 .default_value("/var/log/nscldaq/ringmaster.log"),
         )
+        .arg(
+            Arg::new("log-target")
+                .long("log-target")
+                .value_name("TARGET")
+                .help("Where logs go: file (--log-file), syslog (/dev/log) or stderr (for journald)")
+                .action(ArgAction::Set)
+                .default_value("file"),
+        )
+        .arg(
+            Arg::new("log-level")
+                .long("log-level")
+                .value_name("LEVEL")
+                .help("Most detailed messages logged: off, error, warn, info, debug or trace")
+                .action(ArgAction::Set)
+                .default_value("info"),
+        )
+        .arg(
+            Arg::new("log-format")
+                .long("log-format")
+                .value_name("FORMAT")
+                .help("Format of log lines: text or json (one object per line)")
+                .action(ArgAction::Set)
+                .default_value("text"),
+        )
+        .arg(
+            Arg::new("log-max-size")
+                .long("log-max-size")
+                .value_name("BYTES")
+                .help("Rotate the log file before it grows past this size (k, M, G suffixes allowed)")
+                .action(ArgAction::Set),
+        )
+        .arg(
+            Arg::new("log-max-age")
+                .long("log-max-age")
+                .value_name("SECONDS")
+                .help("Rotate the log file after it has been written for this long")
+                .action(ArgAction::Set)
+                .value_parser(value_parser!(u64)),
+        )
+        .arg(
+            Arg::new("log-keep")
+                .long("log-keep")
+                .value_name("COUNT")
+                .help("Number of rotated log files kept")
+                .action(ArgAction::Set)
+                .default_value("5")
+                .value_parser(value_parser!(usize)),
+        )
+        .arg(
+            Arg::new("reap-interval")
+                .long("reap-interval")
+                .value_name("SECONDS")
+                .help("Seconds between sweeps for slots held by dead processes (0 disables)")
+                .action(ArgAction::Set)
+                .default_value("60")
+                .value_parser(value_parser!(u64))
+        )
+        .arg(
+            Arg::new("reap-dry-run")
+                .long("reap-dry-run")
+                .help("Only log the slots the reaper would free")
+                .action(ArgAction::SetTrue)
+        )
+        .arg(
+            Arg::new("unregister-policy")
+                .long("unregister-policy")
+                .value_name("POLICY")
+                .help("What UNREGISTER does to the ring's clients: kill, signal or detach")
+                .action(ArgAction::Set)
+                .default_value("kill")
+                .value_parser(["kill", "signal", "detach"])
+        )
+        .arg(
+            Arg::new("kill-grace")
+                .long("kill-grace")
+                .value_name("SECONDS")
+                .help("Seconds a client has to exit after SIGTERM before it is sent SIGKILL")
+                .action(ArgAction::Set)
+                .default_value("2")
+                .value_parser(value_parser!(u64))
+        )
+        .arg(
+            Arg::new("ring-mode")
+                .long("ring-mode")
+                .value_name("OCTAL")
+                .help("Permissions given to rings made by CREATE")
+                .action(ArgAction::Set)
+                .default_value("666")
+                .value_parser(|mode: &str| u32::from_str_radix(mode, 8))
+        )
+        .arg(
+            Arg::new("listen")
+                .long("listen")
+                .value_name("ADDRESS")
+                .help("Address to accept requests on, e.g. 10.0.0.1, ::1 or [::] (may be repeated)")
+                .action(ArgAction::Append)
+                .default_value("0.0.0.0")
+                .value_parser(|address: &str| {
+                    address
+                        .trim_start_matches('[')
+                        .trim_end_matches(']')
+                        .parse::<IpAddr>()
+                })
+        )
+        .arg(
+            Arg::new("service-name")
+                .long("service-name")
+                .value_name("NAME")
+                .help("Service name advertised with the port manager")
+                .action(ArgAction::Set)
+                .default_value(SERVICE_NAME)
+        )
+        .arg(
+            Arg::new("per-user")
+                .long("per-user")
+                .help("Qualify the service name with the username (e.g. RingMaster_fox)")
+                .action(ArgAction::SetTrue)
+        )
+        .arg(
+            Arg::new("tls-cert")
+                .long("tls-cert")
+                .value_name("PATH")
+                .help("PEM certificate (chain) for TLS encrypted REMOTE hoisting")
+                .action(ArgAction::Set)
+        )
+        .arg(
+            Arg::new("tls-key")
+                .long("tls-key")
+                .value_name("PATH")
+                .help("PEM private key of the --tls-cert certificate")
+                .action(ArgAction::Set)
+        )
+        .arg(
+            Arg::new("tls-client-ca")
+                .long("tls-client-ca")
+                .value_name("PATH")
+                .help("PEM CA certificate(s) TLS clients must have certificates from")
+                .action(ArgAction::Set)
+        )
+        .arg(
+            Arg::new("tls-required")
+                .long("tls-required")
+                .help("Refuse REMOTE requests that don't ask for TLS")
+                .action(ArgAction::SetTrue)
+        )
+        .arg(
+            Arg::new("hoist-rate")
+                .long("hoist-rate")
+                .value_name("BYTES")
+                .help("Most bytes/second a REMOTE hoist may send (k, M, G suffixes allowed)")
+                .action(ArgAction::Set)
+        )
+        .arg(
+            Arg::new("hoist-policy")
+                .long("hoist-policy")
+                .value_name("POLICY")
+                .help("When a REMOTE hoist falls behind: block (hold back the ring) or sample (skip data)")
+                .action(ArgAction::Set)
+                .default_value("block")
+        )
+        .arg(
+            Arg::new("hoist-buffer")
+                .long("hoist-buffer")
+                .value_name("BYTES")
+                .help("Data a sampled or fan-out REMOTE hoist can fall behind by (k, M, G suffixes allowed)")
+                .action(ArgAction::Set)
+                .default_value("4M")
+        )
+        .arg(
+            Arg::new("hoist-fanout")
+                .long("hoist-fanout")
+                .help("Share one hoister (and consumer slot) among the REMOTE hoists of a ring")
+                .action(ArgAction::SetTrue)
+        )
+        .arg(
+            Arg::new("hoist-config")
+                .long("hoist-config")
+                .value_name("PATH")
+                .help("File of hoists (PUSH and PROXY requests) to start with")
+                .action(ArgAction::Set)
+        )
+        .arg(
+            Arg::new("proxy-size")
+                .long("proxy-size")
+                .value_name("BYTES")
+                .help("Data size of the proxy rings PROXY makes (k, M, G suffixes allowed)")
+                .action(ArgAction::Set)
+                .default_value("8M")
+        )
+        .arg(
+            Arg::new("proxy-tls-ca")
+                .long("proxy-tls-ca")
+                .value_name("PATH")
+                .help("PEM CA certificate(s) remote ring masters' certificates are checked against for PROXY ... TLS")
+                .action(ArgAction::Set)
+        )
+        .arg(
+            Arg::new("lock-dir")
+                .long("lock-dir")
+                .value_name("PATH")
+                .help("Directory of the lock files that keep two ring masters off one ring directory")
+                .action(ArgAction::Set)
+                .default_value("/run/lock")
+        )
+        .arg(
+            Arg::new("takeover")
+                .long("takeover")
+                .help("Kill a (hung) ring master serving our ring directory and take over")
+                .action(ArgAction::SetTrue)
+        )
+        .arg(
+            Arg::new("alarm-free")
+                .long("alarm-free")
+                .value_name("FRACTION")
+                .help("Alarm when a ring's free space falls below this fraction of its size")
+                .action(ArgAction::Set)
+        )
+        .arg(
+            Arg::new("alarm-backlog")
+                .long("alarm-backlog")
+                .value_name("FRACTION")
+                .help("Alarm when a consumer's backlog exceeds this fraction of the ring size")
+                .action(ArgAction::Set)
+        )
+        .arg(
+            Arg::new("alarm-stall")
+                .long("alarm-stall")
+                .value_name("SECONDS")
+                .help("Alarm when a consumer with a backlog gets nothing for this long")
+                .action(ArgAction::Set)
+        )
+        .arg(
+            Arg::new("alarm-config")
+                .long("alarm-config")
+                .value_name("PATH")
+                .help("File of per ring alarm thresholds")
+                .action(ArgAction::Set)
+        )
+        .arg(
+            Arg::new("alarm-interval")
+                .long("alarm-interval")
+                .value_name("SECONDS")
+                .help("Seconds between alarm checks (0 disables)")
+                .action(ArgAction::Set)
+                .default_value("5")
+                .value_parser(value_parser!(u64))
+        )
+        .arg(
+            Arg::new("alarm-command")
+                .long("alarm-command")
+                .value_name("PATH")
+                .help("Command run with ring, pid, condition and value when an alarm is raised")
+                .action(ArgAction::Set)
+        )
+        .arg(
+            Arg::new("stats-interval")
+                .long("stats-interval")
+                .value_name("SECONDS")
+                .help("Seconds between samples of ring data rates (0 disables)")
+                .action(ArgAction::Set)
+                .default_value("1")
+                .value_parser(value_parser!(u64))
+        )
+        .arg(
+            Arg::new("stats-history")
+                .long("stats-history")
+                .value_name("SAMPLES")
+                .help("Number of data rate samples kept for each ring client")
+                .action(ArgAction::Set)
+                .default_value("60")
+                .value_parser(value_parser!(usize))
+        )
         .get_matches();
 
     // Initialize the result with the default values:
 
-    let mut result = ProgramOptions {
-        portman: 30000,
-        directory: String::from("/dev/shm"),
-        log_filename: String::from("/var/log/nscldaq/ringmaster.log"),
-    };
+    let mut result = ProgramOptions::new();
     // Override the struct values with what we got from clap:
 
     // listen port
@@ -995,7 +3637,18 @@ fn process_options() -> ProgramOptions {
 
     // Log File:
 
-    if let Some(file) = parser.get_one::<String>("log") {
+    if let Some(target) = parser.get_one::<String>("log-target") {
+        result.log_target = LogTarget::parse(target).unwrap_or_else(|e| {
+            eprintln!("{}", e);
+            process::exit(-1);
+        });
+    }
+    // Only file logging needs the log file.
+
+    let log_file = parser
+        .get_one::<String>("log")
+        .filter(|_| result.log_target == LogTarget::File);
+    if let Some(file) = log_file {
         // We need to be able to write to the file.  the
         // only way I know how to do that is test open the file:
 
@@ -1009,6 +3662,187 @@ fn process_options() -> ProgramOptions {
             result.log_filename = String::from(file);
         }
     }
+    if let Some(level) = parser.get_one::<String>("log-level") {
+        result.log_level = logging::parse_level(level).unwrap_or_else(|e| {
+            eprintln!("{}", e);
+            process::exit(-1);
+        });
+    }
+    if let Some(format) = parser.get_one::<String>("log-format") {
+        result.log_format = LogFormat::parse(format).unwrap_or_else(|e| {
+            eprintln!("{}", e);
+            process::exit(-1);
+        });
+    }
+    if let Some(size) = parser.get_one::<String>("log-max-size") {
+        result.log_rotation.max_bytes = Some(logging::parse_size(size).unwrap_or_else(|e| {
+            eprintln!("{}", e);
+            process::exit(-1);
+        }));
+    }
+    if let Some(age) = parser.get_one::<u64>("log-max-age") {
+        result.log_rotation.max_age = Some(Duration::from_secs(*age));
+    }
+    if let Some(keep) = parser.get_one::<usize>("log-keep") {
+        result.log_rotation.keep = *keep;
+    }
+
+    // Stale slot reaper:
+
+    if let Some(interval) = parser.get_one::<u64>("reap-interval") {
+        result.reap_interval = *interval;
+    }
+    result.reap_dry_run = parser.get_flag("reap-dry-run");
+
+    // What UNREGISTER does to clients:
+
+    if let Some(policy) = parser.get_one::<String>("unregister-policy") {
+        result.unregister_policy =
+            rings::rings::KillPolicy::parse(policy).expect("clap validated the policy");
+    }
+    if let Some(grace) = parser.get_one::<u64>("kill-grace") {
+        result.kill_grace = *grace;
+    }
+
+    if let Some(mode) = parser.get_one::<u32>("ring-mode") {
+        result.ring_mode = *mode;
+    }
+    if let Some(addresses) = parser.get_many::<IpAddr>("listen") {
+        result.listen = addresses.copied().collect();
+    }
+    if let Some(name) = parser.get_one::<String>("service-name") {
+        result.service_name = name.clone();
+    }
+    if parser.get_flag("per-user") {
+        match whoami::username() {
+            Ok(user) => result.service_name = qualified_service_name(&result.service_name, &user),
+            Err(e) => {
+                eprintln!("Unable to determine the username for --per-user: {}", e);
+                process::exit(-1);
+            }
+        }
+    }
+    if result.service_name.is_empty() || result.service_name.contains(char::is_whitespace) {
+        eprintln!("--service-name must be a non-empty name without whitespace");
+        process::exit(-1);
+    }
+    match (parser.get_one::<String>("tls-cert"), parser.get_one::<String>("tls-key")) {
+        (Some(cert), Some(key)) => {
+            let client_ca = parser.get_one::<String>("tls-client-ca").map(|s| s.as_str());
+            result.tls = Some(TlsServer::load(cert, key, client_ca).unwrap_or_else(|e| {
+                eprintln!("Unable to set up TLS: {}", e);
+                process::exit(-1);
+            }));
+        }
+        (None, None) => {
+            if parser.get_one::<String>("tls-client-ca").is_some() {
+                eprintln!("--tls-client-ca needs --tls-cert and --tls-key");
+                process::exit(-1);
+            }
+        }
+        _ => {
+            eprintln!("--tls-cert and --tls-key must be given together");
+            process::exit(-1);
+        }
+    }
+    result.tls_required = parser.get_flag("tls-required");
+    if result.tls_required && result.tls.is_none() {
+        eprintln!("--tls-required needs --tls-cert and --tls-key");
+        process::exit(-1);
+    }
+    if let Some(rate) = parser.get_one::<String>("hoist-rate") {
+        result.hoist_limits.rate = match logging::parse_size(rate) {
+            Ok(0) => {
+                eprintln!("--hoist-rate must be more than 0");
+                process::exit(-1);
+            }
+            Ok(rate) => Some(rate),
+            Err(e) => {
+                eprintln!("{}", e);
+                process::exit(-1);
+            }
+        };
+    }
+    if let Some(policy) = parser.get_one::<String>("hoist-policy") {
+        result.hoist_limits.policy = Policy::parse(policy).unwrap_or_else(|e| {
+            eprintln!("{}", e);
+            process::exit(-1);
+        });
+    }
+    if let Some(size) = parser.get_one::<String>("hoist-buffer") {
+        result.hoist_buffer = logging::parse_size(size).unwrap_or_else(|e| {
+            eprintln!("{}", e);
+            process::exit(-1);
+        }) as usize;
+    }
+    result.hoist_fanout = parser.get_flag("hoist-fanout");
+    if let Some(file) = parser.get_one::<String>("hoist-config") {
+        result.hoist_config = fs::read_to_string(file)
+            .map_err(|e| e.to_string())
+            .and_then(|text| HoistConfig::parse(&text))
+            .unwrap_or_else(|e| {
+                eprintln!("Unable to use hoist configuration {}: {}", file, e);
+                process::exit(-1);
+            });
+    }
+    if let Some(size) = parser.get_one::<String>("proxy-size") {
+        result.proxy_size = match logging::parse_size(size) {
+            Ok(size) if size > 0 && size <= u32::MAX as u64 => size as u32,
+            Ok(_) => {
+                eprintln!("--proxy-size must be more than 0 and less than 4G");
+                process::exit(-1);
+            }
+            Err(e) => {
+                eprintln!("{}", e);
+                process::exit(-1);
+            }
+        };
+    }
+    if let Some(ca) = parser.get_one::<String>("proxy-tls-ca") {
+        result.proxy_tls = Some(TlsClient::load(ca, None).unwrap_or_else(|e| {
+            eprintln!("{}", e);
+            process::exit(-1);
+        }));
+    }
+    if let Some(dir) = parser.get_one::<String>("lock-dir") {
+        result.lock_dir = dir.clone();
+    }
+    result.takeover = parser.get_flag("takeover");
+
+    // Alarms - the command line thresholds are the defaults for the
+    // rings the configuration file says nothing about:
+
+    let mut settings = Vec::<String>::new();
+    for (arg, key) in [("alarm-free", "free"), ("alarm-backlog", "backlog"), ("alarm-stall", "stall")] {
+        if let Some(value) = parser.get_one::<String>(arg) {
+            settings.push(format!("{}={}", key, value));
+        }
+    }
+    let settings = settings.iter().map(|s| s.as_str()).collect::<Vec<&str>>();
+    let defaults = Thresholds::default().with_settings(&settings).unwrap_or_else(|e| {
+        eprintln!("Invalid alarm threshold: {}", e);
+        process::exit(-1);
+    });
+    result.alarms = match parser.get_one::<String>("alarm-config") {
+        Some(file) => fs::read_to_string(file)
+            .map_err(|e| e.to_string())
+            .and_then(|text| AlarmConfig::parse(&text, defaults))
+            .unwrap_or_else(|e| {
+                eprintln!("Unable to use alarm configuration {}: {}", file, e);
+                process::exit(-1);
+            }),
+        None => AlarmConfig::new(defaults),
+    };
+    if let Some(interval) = parser.get_one::<u64>("alarm-interval") {
+        result.alarm_interval = *interval;
+    }
+    result.alarm_command = parser.get_one::<String>("alarm-command").cloned();
+    if let Some(interval) = parser.get_one::<u64>("stats-interval") {
+        result.stats_interval = *interval;
+    }
+    if let Some(history) = parser.get_one::<usize>("stats-history") {
+        result.stats_history = *history;
+    }
 
     // Returnt he final value:
 
@@ -1212,17 +4046,25 @@ fn unrecord_connection(
     }
 }
 
+///
+/// Qualify a service name with a username as the port manager's
+/// per-user services are named: e.g. RingMaster_fox.
+///
+fn qualified_service_name(service: &str, user: &str) -> String {
+    format!("{}_{}", service, user)
+}
 ///
 /// Check to see if the ring master is already running.:
 /// 
-/// Returns bool true - if RingMaster is advertised on the
-/// portman.
+/// Returns bool true - if our service name is advertised on the
+/// portman (by anyone).  Err if the port manager can't be asked.
 ///
-fn ringmaster_running(portman : u16) -> bool {
+fn ringmaster_running(portman : u16, service_name: &str) -> Result<bool, String> {
     let mut portman_client  = portman_client::Client::new(portman);
-    let services = portman_client.find_by_service(SERVICE_NAME).expect("Port manager isn't running!");
-    return services.len() > 0;
-    
+    let services = portman_client.find_by_service(service_name).map_err(|e| {
+        format!("Unable to contact the port manager on port {}: {}", portman, e.to_string())
+    })?;
+    Ok(services.len() > 0)
 }
 /// This function takes a TcpStream and turns it into
 /// an process::Stdio object.  How this is done is
@@ -1237,3 +4079,274 @@ fn socket_to_stdio(socket: &TcpStream) -> process::Stdio {
     sock.as_stdio().expect("Unable to convert fd -> stdio")
     
 }
+#[cfg(test)]
+mod main_tests {
+    // End to end tests: a ring master serving on a loopback port with
+    // a stand-in for ring2stdout that hoists <directory>/<ring>.items.
+
+    use super::*;
+    use nscldaq_ringmaster::hoist::queue::queue::ItemSplitter;
+    use rcgen::generate_simple_self_signed;
+    use std::os::unix::fs::PermissionsExt;
+    use std::sync::Once;
+
+    const RING: &str = "hoisted";
+    const FAKE_HOISTER: &str = r#"#!/bin/sh
+while [ $# -gt 0 ]; do
+    case "$1" in
+        --directory) directory=$2; shift;;
+        --ring) ring=$2; shift;;
+    esac
+    shift
+done
+exec cat "$directory/$ring.items"
+"#;
+
+    // Put the stand-in ring2stdout first in the PATH.
+
+    fn install_hoister() {
+        static INSTALL: Once = Once::new();
+        INSTALL.call_once(|| {
+            let bin = std::env::temp_dir().join(format!("ringmaster_test_bin_{}", process::id()));
+            fs::create_dir_all(&bin).unwrap();
+            let hoister = bin.join("ring2stdout");
+            fs::write(&hoister, FAKE_HOISTER).unwrap();
+            fs::set_permissions(&hoister, fs::Permissions::from_mode(0o755)).unwrap();
+            let path = std::env::var("PATH").unwrap_or_default();
+            std::env::set_var("PATH", format!("{}:{}", bin.display(), path));
+        });
+    }
+    // count ring items of size bytes, each filled with its index.
+
+    fn ring_items(count: usize, size: usize) -> Vec<Vec<u8>> {
+        (0..count)
+            .map(|i| {
+                let mut item = (size as u32).to_ne_bytes().to_vec();
+                item.resize(size, i as u8);
+                item
+            })
+            .collect()
+    }
+    // A ring master whose directory has RING, which hoists items.  The
+    // options are as tweak leaves them.  Returns the ring master's
+    // address and directory.
+
+    fn ringmaster(
+        name: &str,
+        items: &[Vec<u8>],
+        tweak: impl FnOnce(&mut ProgramOptions),
+    ) -> (SocketAddr, PathBuf) {
+        install_hoister();
+        let dir = std::env::temp_dir().join(format!("ringmaster_test_{}_{}", name, process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let ring = dir.join(RING);
+        ringfile::ringfile::create_ring(ring.to_str().unwrap(), 4096, 4, 0o666).unwrap();
+        fs::write(dir.join(format!("{}.items", RING)), items.concat()).unwrap();
+
+        let mut options = ProgramOptions::new();
+        options.directory = String::from(dir.to_str().unwrap());
+        tweak(&mut options);
+        let inventory = Arc::new(Mutex::new(inventory_rings(&options.directory)));
+        let events = Arc::new(Mutex::new(EventBus::new()));
+        let alarms = Arc::new(Mutex::new(AlarmMonitor::new(options.alarms.clone())));
+        let stats = Arc::new(Mutex::new(StatsCollector::new(options.stats_history)));
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        thread::spawn(move || accept_clients(listener, options, inventory, events, alarms, stats, None));
+        (address, dir)
+    }
+    // Send a request, returning the connection, ready to read the reply.
+
+    fn request(address: SocketAddr, request: &str) -> BufReader<TcpStream> {
+        let mut connection = TcpStream::connect(address).unwrap();
+        let _ = connection.set_read_timeout(Some(Duration::from_secs(10)));
+        connection.write_all(format!("{}\n", request).as_bytes()).unwrap();
+        BufReader::new(connection)
+    }
+    fn reply_line<R: BufRead>(reader: &mut R) -> String {
+        let mut line = String::new();
+        reader.read_line(&mut line).unwrap();
+        line
+    }
+    fn split_items(data: &[u8]) -> Vec<Vec<u8>> {
+        let mut splitter = ItemSplitter::new(usize::MAX);
+        splitter.push(data);
+        std::iter::from_fn(|| splitter.next_item().unwrap()).collect()
+    }
+
+    #[test]
+    fn remote_tls_1() {
+        let certified = generate_simple_self_signed(vec![String::from("localhost")]).unwrap();
+        let (cert, key) = (certified.cert.pem(), certified.signing_key.serialize_pem());
+        let items = ring_items(50, 100);
+        let (address, dir) = ringmaster("tls", &items, |options| {
+            options.tls = Some(TlsServer::from_pem(cert.as_bytes(), key.as_bytes(), None).unwrap());
+            options.tls_required = true;
+        });
+
+        // Without TLS we're refused:
+
+        let mut reply = request(address, &format!("REMOTE {}", RING));
+        assert!(reply_line(&mut reply).starts_with("FAIL"));
+
+        // OK TLS, the handshake and the rest is encrypted:
+
+        let mut reply = request(address, &format!("REMOTE {} TLS", RING));
+        assert_eq!("OK TLS\r\n", reply_line(&mut reply));
+        let client = TlsClient::from_pem(cert.as_bytes(), None).unwrap();
+        let secure = client.connect("localhost", reply.into_inner()).unwrap();
+        let mut secure = BufReader::new(secure);
+        assert_eq!("OK BINARY FOLLOWS\r\n", reply_line(&mut secure));
+        let mut data = Vec::new();
+        secure.read_to_end(&mut data).unwrap();
+        assert_eq!(items.concat(), data);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+    #[test]
+    fn remote_compress_1() {
+        let items = ring_items(200, 1000);
+        let (address, dir) = ringmaster("compress", &items, |_| {});
+
+        // The reply names the codec we get; the first we asked for that
+        // the ring master has:
+
+        for (asked, codec) in [("zstd", Codec::Zstd), ("brotli,lz4", Codec::Lz4)] {
+            let mut reply = request(address, &format!("REMOTE {} COMPRESS {}", RING, asked));
+            assert_eq!(
+                format!("OK BINARY FOLLOWS COMPRESS {}\r\n", codec.name()),
+                reply_line(&mut reply)
+            );
+            let mut compressed = Vec::new();
+            reply.read_to_end(&mut compressed).unwrap();
+            assert!(compressed.len() < items.concat().len());
+            let mut data = Vec::new();
+            Decompressor::new(codec, &compressed[..]).unwrap().read_to_end(&mut data).unwrap();
+            assert_eq!(items.concat(), data);
+        }
+        fs::remove_dir_all(dir).unwrap();
+    }
+    #[test]
+    fn remote_sample_1() {
+        // The hoister dumps 100k at once.  At 10k/second sampling only
+        // about a burst's worth of whole items gets through, in order:
+
+        let items = ring_items(100, 1000);
+        let (address, dir) = ringmaster("sample", &items, |_| {});
+        let mut reply = request(address, &format!("REMOTE {} RATE 10k POLICY sample", RING));
+        assert_eq!("OK BINARY FOLLOWS\r\n", reply_line(&mut reply));
+        let mut data = Vec::new();
+        reply.read_to_end(&mut data).unwrap();
+        let received = split_items(&data);
+        assert!(!received.is_empty());
+        assert!(received.len() < items.len() / 2);
+        let mut originals = items.iter();
+        for item in &received {
+            assert!(originals.any(|original| original == item));
+        }
+        fs::remove_dir_all(dir).unwrap();
+    }
+    #[test]
+    fn push_1() {
+        let items = ring_items(100, 500);
+        let (address, dir) = ringmaster("push", &items, |_| {});
+        let remote = TcpListener::bind("127.0.0.1:0").unwrap();
+        let target = format!("{} {}", RING, remote.local_addr().unwrap());
+
+        // The ring master connects to us and sends the ring's items with
+        // no reply line:
+
+        let mut reply = request(address, &format!("PUSH {}", target));
+        assert_eq!("OK\r\n", reply_line(&mut reply));
+        let (mut connection, _) = remote.accept().unwrap();
+        let _ = connection.set_read_timeout(Some(Duration::from_secs(10)));
+        let mut data = Vec::new();
+        connection.read_to_end(&mut data).unwrap();
+        assert_eq!(items.concat(), data);
+
+        let mut reply = request(address, &format!("UNPUSH {}", target));
+        assert_eq!("OK\r\n", reply_line(&mut reply));
+        let mut reply = request(address, &format!("UNPUSH {}", target));
+        assert!(reply_line(&mut reply).starts_with("FAIL"));
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+    #[test]
+    fn delete_proxy_1() {
+        let (address, dir) = ringmaster("delete_proxy", &[], |options| options.proxy_size = 4096);
+
+        // Nothing answers on port 1 so the proxy just keeps trying, but
+        // while it's there its ring can't be deleted, even by force:
+
+        let mut reply = request(address, "PROXY 127.0.0.1:1 fox");
+        assert_eq!("OK\r\n", reply_line(&mut reply));
+        assert!(dir.join("fox@127.0.0.1").exists());
+        let mut reply = request(address, "DELETE fox@127.0.0.1 FORCE");
+        let line = reply_line(&mut reply);
+        assert!(line.starts_with("FAIL") && line.contains("UNPROXY"), "{}", line);
+
+        let mut reply = request(address, "UNPROXY 127.0.0.1:1 fox");
+        assert_eq!("OK\r\n", reply_line(&mut reply));
+        let mut reply = request(address, "DELETE fox@127.0.0.1 FORCE");
+        assert!(reply_line(&mut reply).starts_with("OK"));
+        assert!(!dir.join("fox@127.0.0.1").exists());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+    #[test]
+    fn parse_watch_1() {
+        let words = |text: &str| text.split_whitespace().map(String::from).collect::<Vec<String>>();
+        let (interval, pattern, json) = parse_watch(&words("")).unwrap();
+        assert_eq!((DEFAULT_WATCH_INTERVAL, false), (interval, json));
+        assert!(pattern.is_none());
+        let (interval, pattern, json) = parse_watch(&words("2.5 fox* JSON")).unwrap();
+        assert_eq!((Duration::from_millis(2500), true), (interval, json));
+        assert!(pattern.unwrap().matches("foxes"));
+        assert_eq!(MAX_WATCH_INTERVAL, parse_watch(&words("86400")).unwrap().0);
+
+        // Out of range intervals fail rather than panic:
+
+        for interval in ["0.05", "-1", "86401", "1e300", "inf", "NaN"] {
+            assert!(parse_watch(&words(interval)).is_err(), "{}", interval);
+        }
+    }
+    #[test]
+    fn portman_request_1() {
+        // A port manager whose reply isn't text makes the client panic:
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let portman = thread::spawn(move || {
+            let (mut client, _) = listener.accept().unwrap();
+            let mut line = String::new();
+            BufReader::new(client.try_clone().unwrap()).read_line(&mut line).unwrap();
+            client.write_all(b"OK \xff\xfe\n").unwrap();
+        });
+        let mut client = portman_client::Client::new(port);
+        assert!(portman_request(&mut client, |c| c.find_my_service("RingMaster")).is_err());
+        portman.join().unwrap();
+    }
+    #[test]
+    fn connect_slot_1() {
+        // RING has 4 consumer slots:
+
+        let (address, dir) = ringmaster("connect_slot", &[], |_| {});
+        let pid = process::id();
+        let mut reply = request(address, &format!("CONNECT {{{}}} consumer.20 {}", RING, pid));
+        assert!(reply_line(&mut reply).starts_with("FAIL"));
+        let mut reply = request(address, &format!("CONNECT {{{}}} consumer.4 {}", RING, pid));
+        assert!(reply_line(&mut reply).starts_with("FAIL"));
+
+        // A slot that exists is fine and so is dropping the connection:
+
+        let mut reply = request(address, &format!("CONNECT {{{}}} consumer.3 {}", RING, pid));
+        assert_eq!("OK\r\n", reply_line(&mut reply));
+        drop(reply);
+        let mut reply = request(address, "LIST");
+        assert_eq!("OK\r\n", reply_line(&mut reply));
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
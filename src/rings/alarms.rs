@@ -0,0 +1,546 @@
+///
+/// The alarms module watches ring usage for conditions that mean data
+/// is about to be (or is being) lost:
+///
+/// *  The free space in the ring has fallen below a fraction of its size,
+///    so the producer is close to blocking.
+/// *  A consumer's backlog has grown past a fraction of the ring size.
+/// *  A consumer with a backlog has not gotten anything for too long.
+///
+/// Thresholds have defaults and can be set per ring by pattern.  The
+/// AlarmMonitor is given periodic samples of each ring and keeps track
+/// of which alarms are active, reporting the ones that are raised and
+/// cleared.
+///
+pub mod alarms {
+    use crate::rings::pattern::pattern::RingPattern;
+    use crate::tcllist::TclList;
+    use serde_json::{json, Value};
+    use std::collections::HashMap;
+    use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+    ///
+    /// Alarm thresholds for a ring.  None disables that alarm.
+    ///
+    /// *  min_free - free space as a fraction of the ring size below which
+    ///    the FREE alarm is raised.
+    /// *  max_backlog - consumer backlog as a fraction of the ring size
+    ///    above which the BACKLOG alarm is raised.
+    /// *  max_stall - how long a consumer with a backlog may go without
+    ///    getting data before the STALL alarm is raised.
+    ///
+    #[derive(Copy, Clone, Debug, Default, PartialEq)]
+    pub struct Thresholds {
+        pub min_free: Option<f64>,
+        pub max_backlog: Option<f64>,
+        pub max_stall: Option<Duration>,
+    }
+    impl Thresholds {
+        ///
+        /// True if no alarms are enabled.
+        ///
+        pub fn is_empty(&self) -> bool {
+            self.min_free.is_none() && self.max_backlog.is_none() && self.max_stall.is_none()
+        }
+        ///
+        /// Apply settings of the form free=fraction, backlog=fraction
+        /// and stall=seconds to a copy of these thresholds.  A value of
+        /// off disables the alarm.
+        ///
+        pub fn with_settings(&self, settings: &[&str]) -> Result<Thresholds, String> {
+            let mut result = *self;
+            for setting in settings {
+                let (key, value) = match setting.split_once('=') {
+                    Some(kv) => kv,
+                    None => return Err(format!("{} is not of the form key=value", setting)),
+                };
+                let off = value.eq_ignore_ascii_case("off");
+                match key.to_lowercase().as_str() {
+                    "free" => result.min_free = if off { None } else { Some(parse_fraction(value)?) },
+                    "backlog" => {
+                        result.max_backlog = if off { None } else { Some(parse_fraction(value)?) }
+                    }
+                    "stall" => {
+                        result.max_stall = if off { None } else { Some(parse_seconds(value)?) }
+                    }
+                    _ => return Err(format!("Unknown alarm threshold {}", key)),
+                }
+            }
+            Ok(result)
+        }
+    }
+    ///
+    /// Parse a fraction in [0, 1].
+    ///
+    pub fn parse_fraction(value: &str) -> Result<f64, String> {
+        match value.parse::<f64>() {
+            Ok(f) if (0.0..=1.0).contains(&f) => Ok(f),
+            _ => Err(format!("{} is not a fraction between 0 and 1", value)),
+        }
+    }
+    ///
+    /// Parse a positive number of seconds.  Err for more than a Duration
+    /// can hold.
+    ///
+    pub fn parse_seconds(value: &str) -> Result<Duration, String> {
+        match value.parse::<f64>() {
+            Ok(s) if s > 0.0 => Duration::try_from_secs_f64(s)
+                .map_err(|_| format!("{} is too many seconds", value)),
+            _ => Err(format!("{} is not a positive number of seconds", value)),
+        }
+    }
+    ///
+    /// Which thresholds apply to which rings.  Rings that match none of
+    /// the rules get the defaults.
+    ///
+    #[derive(Clone, Debug)]
+    pub struct AlarmConfig {
+        defaults: Thresholds,
+        rules: Vec<(RingPattern, Thresholds)>,
+    }
+    impl AlarmConfig {
+        pub fn new(defaults: Thresholds) -> AlarmConfig {
+            AlarmConfig {
+                defaults,
+                rules: Vec::new(),
+            }
+        }
+        ///
+        /// Parse the per ring rules of an alarm configuration file.
+        /// Each non blank line that does not start with # is a ring name
+        /// pattern (see the pattern module) followed by settings as for
+        /// Thresholds::with_settings.  Settings not given come from the
+        /// defaults.  The first rule whose pattern matches a ring applies.
+        ///
+        pub fn parse(text: &str, defaults: Thresholds) -> Result<AlarmConfig, String> {
+            let mut result = AlarmConfig::new(defaults);
+            for (number, line) in text.lines().enumerate() {
+                let words = line.split_whitespace().collect::<Vec<&str>>();
+                if words.is_empty() || words[0].starts_with('#') {
+                    continue;
+                }
+                let rule = RingPattern::parse(words[0])
+                    .and_then(|pattern| Ok((pattern, defaults.with_settings(&words[1..])?)))
+                    .map_err(|e| format!("Line {}: {}", number + 1, e))?;
+                result.rules.push(rule);
+            }
+            Ok(result)
+        }
+        ///
+        /// The thresholds that apply to a ring.
+        ///
+        pub fn thresholds(&self, ring: &str) -> Thresholds {
+            self.rules
+                .iter()
+                .find(|(pattern, _)| pattern.matches(ring))
+                .map(|(_, thresholds)| *thresholds)
+                .unwrap_or(self.defaults)
+        }
+        ///
+        /// True if no ring can ever raise an alarm.
+        ///
+        pub fn is_empty(&self) -> bool {
+            self.defaults.is_empty() && self.rules.iter().all(|(_, t)| t.is_empty())
+        }
+    }
+    ///
+    /// The conditions that raise alarms.
+    ///
+    #[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+    pub enum AlarmKind {
+        FreeSpace,
+        Backlog,
+        Stall,
+    }
+    impl AlarmKind {
+        ///
+        /// The name used for the condition in replies and hooks.
+        ///
+        pub fn name(&self) -> &'static str {
+            match self {
+                AlarmKind::FreeSpace => "FREE",
+                AlarmKind::Backlog => "BACKLOG",
+                AlarmKind::Stall => "STALL",
+            }
+        }
+    }
+    ///
+    /// An alarm:
+    ///
+    /// *  ring - the ring it's about.
+    /// *  kind - the condition.
+    /// *  slot, pid - the consumer for BACKLOG and STALL alarms; the
+    ///    producer pid (if any) for FREE alarms.
+    /// *  value - the free or backlog fraction or the stall seconds as of
+    ///    the most recent sample.
+    /// *  since - when the alarm was raised in seconds since the epoch.
+    ///
+    #[derive(Clone, Debug, PartialEq)]
+    pub struct Alarm {
+        pub ring: String,
+        pub kind: AlarmKind,
+        pub slot: Option<usize>,
+        pub pid: Option<u32>,
+        pub value: f64,
+        pub since: u64,
+    }
+    impl Alarm {
+        ///
+        /// Render as a Tcl list of key value pairs.  Missing slots and
+        /// pids are -1.
+        ///
+        pub fn to_tcl(&self) -> TclList {
+            let mut result = TclList::new();
+            result
+                .add_element("ring")
+                .add_quoted_element(&self.ring)
+                .add_element("condition")
+                .add_element(self.kind.name())
+                .add_element("slot")
+                .add_element(&self.slot.map_or(String::from("-1"), |s| s.to_string()))
+                .add_element("pid")
+                .add_element(&self.pid.map_or(String::from("-1"), |p| p.to_string()))
+                .add_element("value")
+                .add_element(&format!("{:.3}", self.value))
+                .add_element("since")
+                .add_element(&self.since.to_string());
+            result
+        }
+        ///
+        /// Render as a JSON object with the same keys as to_tcl.  Missing
+        /// slots and pids are null.
+        ///
+        pub fn to_json(&self) -> Value {
+            json!({
+                "ring": self.ring,
+                "condition": self.kind.name(),
+                "slot": self.slot,
+                "pid": self.pid,
+                "value": self.value,
+                "since": self.since,
+            })
+        }
+    }
+    ///
+    /// A consumer as sampled: its slot, pid, backlog in bytes and get
+    /// offset (None if it couldn't be read).
+    ///
+    pub struct ConsumerSample {
+        pub slot: usize,
+        pub pid: u32,
+        pub backlog: usize,
+        pub offset: Option<usize>,
+    }
+    ///
+    /// A sample of a ring's usage.
+    ///
+    pub struct RingSample {
+        pub data_bytes: usize,
+        pub free_space: usize,
+        pub producer_pid: Option<u32>,
+        pub consumers: Vec<ConsumerSample>,
+    }
+    ///
+    /// Alarms that were raised and cleared by a sample.
+    ///
+    #[derive(Default)]
+    pub struct AlarmChanges {
+        pub raised: Vec<Alarm>,
+        pub cleared: Vec<Alarm>,
+    }
+
+    type AlarmKey = (String, AlarmKind, Option<usize>, Option<u32>);
+    type ConsumerKey = (String, usize, u32);
+
+    ///
+    /// Keeps track of the active alarms.
+    ///
+    pub struct AlarmMonitor {
+        config: AlarmConfig,
+        progress: HashMap<ConsumerKey, (Option<usize>, usize, Instant)>, // offset, backlog, last progress.
+        active: HashMap<AlarmKey, Alarm>,
+    }
+    impl AlarmMonitor {
+        pub fn new(config: AlarmConfig) -> AlarmMonitor {
+            AlarmMonitor {
+                config,
+                progress: HashMap::new(),
+                active: HashMap::new(),
+            }
+        }
+        ///
+        /// The configuration the monitor evaluates against.
+        ///
+        pub fn config(&self) -> &AlarmConfig {
+            &self.config
+        }
+        ///
+        /// Evaluate a sample of ring taken at now.
+        ///
+        pub fn evaluate(&mut self, ring: &str, sample: &RingSample, now: Instant) -> AlarmChanges {
+            let thresholds = self.config.thresholds(ring);
+            let mut present = Vec::<(AlarmKey, f64)>::new();
+            let size = sample.data_bytes.max(1) as f64;
+
+            if let Some(min_free) = thresholds.min_free {
+                let free = sample.free_space as f64 / size;
+                if free < min_free {
+                    present.push((
+                        (String::from(ring), AlarmKind::FreeSpace, None, sample.producer_pid),
+                        free,
+                    ));
+                }
+            }
+            let mut seen = Vec::<ConsumerKey>::new();
+            for consumer in &sample.consumers {
+                let key = (String::from(ring), consumer.slot, consumer.pid);
+                let backlog = consumer.backlog as f64 / size;
+                if thresholds.max_backlog.is_some_and(|max| backlog > max) {
+                    present.push((
+                        (String::from(ring), AlarmKind::Backlog, Some(consumer.slot), Some(consumer.pid)),
+                        backlog,
+                    ));
+                }
+                // A consumer is making progress unless it had and still has
+                // something to get and has not moved since the last sample:
+
+                let last_progress = match self.progress.get(&key) {
+                    Some((offset, backlog, when))
+                        if *backlog > 0 && consumer.backlog > 0 && *offset == consumer.offset =>
+                    {
+                        *when
+                    }
+                    _ => now,
+                };
+                self.progress
+                    .insert(key.clone(), (consumer.offset, consumer.backlog, last_progress));
+                seen.push(key);
+
+                let stalled = now.duration_since(last_progress);
+                if consumer.offset.is_some() && thresholds.max_stall.is_some_and(|max| stalled >= max) {
+                    present.push((
+                        (String::from(ring), AlarmKind::Stall, Some(consumer.slot), Some(consumer.pid)),
+                        stalled.as_secs_f64(),
+                    ));
+                }
+            }
+            self.progress
+                .retain(|key, _| key.0 != ring || seen.contains(key));
+            self.update(ring, present)
+        }
+        ///
+        /// Forget a ring that has gone away, clearing its alarms.
+        ///
+        pub fn forget_ring(&mut self, ring: &str) -> Vec<Alarm> {
+            self.progress.retain(|key, _| key.0 != ring);
+            self.update(ring, Vec::new()).cleared
+        }
+        ///
+        /// Forget all rings other than the ones listed (e.g. the
+        /// ones still in the inventory), clearing their alarms.
+        ///
+        pub fn retain_rings(&mut self, rings: &[String]) -> Vec<Alarm> {
+            let mut gone = self
+                .active
+                .keys()
+                .map(|key| key.0.clone())
+                .chain(self.progress.keys().map(|key| key.0.clone()))
+                .filter(|ring| !rings.contains(ring))
+                .collect::<Vec<String>>();
+            gone.sort();
+            gone.dedup();
+            let mut cleared = Vec::<Alarm>::new();
+            for ring in gone {
+                cleared.append(&mut self.forget_ring(&ring));
+            }
+            cleared
+        }
+        ///
+        /// The active alarms ordered by ring, condition and slot.
+        ///
+        pub fn active(&self) -> Vec<Alarm> {
+            let mut result = self.active.values().cloned().collect::<Vec<Alarm>>();
+            result.sort_by(|a, b| (&a.ring, a.kind, a.slot).cmp(&(&b.ring, b.kind, b.slot)));
+            result
+        }
+        // Make the active alarms of a ring the ones that are present.
+
+        fn update(&mut self, ring: &str, present: Vec<(AlarmKey, f64)>) -> AlarmChanges {
+            let mut changes = AlarmChanges::default();
+            let keys = present.iter().map(|(key, _)| key.clone()).collect::<Vec<AlarmKey>>();
+            let gone = self
+                .active
+                .keys()
+                .filter(|key| key.0 == ring && !keys.contains(key))
+                .cloned()
+                .collect::<Vec<AlarmKey>>();
+            for key in gone {
+                if let Some(alarm) = self.active.remove(&key) {
+                    changes.cleared.push(alarm);
+                }
+            }
+            for (key, value) in present {
+                match self.active.get_mut(&key) {
+                    Some(alarm) => alarm.value = value,
+                    None => {
+                        let alarm = Alarm {
+                            ring: key.0.clone(),
+                            kind: key.1,
+                            slot: key.2,
+                            pid: key.3,
+                            value,
+                            since: SystemTime::now()
+                                .duration_since(UNIX_EPOCH)
+                                .map(|d| d.as_secs())
+                                .unwrap_or(0),
+                        };
+                        changes.raised.push(alarm.clone());
+                        self.active.insert(key, alarm);
+                    }
+                }
+            }
+            changes
+        }
+    }
+    #[cfg(test)]
+    mod alarms_tests {
+        use super::*;
+
+        fn sample(free_space: usize, consumers: Vec<(usize, usize)>) -> RingSample {
+            RingSample {
+                data_bytes: 1000,
+                free_space,
+                producer_pid: Some(1),
+                consumers: consumers
+                    .into_iter()
+                    .map(|(backlog, offset)| ConsumerSample {
+                        slot: 2,
+                        pid: 100,
+                        backlog,
+                        offset: Some(offset),
+                    })
+                    .collect(),
+            }
+        }
+        #[test]
+        fn settings_1() {
+            let t = Thresholds::default()
+                .with_settings(&["free=0.1", "backlog=0.9", "stall=2.5"])
+                .unwrap();
+            assert_eq!(Some(0.1), t.min_free);
+            assert_eq!(Some(0.9), t.max_backlog);
+            assert_eq!(Some(Duration::from_millis(2500)), t.max_stall);
+            assert!(t.with_settings(&["free=off"]).unwrap().min_free.is_none());
+
+            assert!(t.with_settings(&["free=2"]).is_err());
+            assert!(t.with_settings(&["stall=-1"]).is_err());
+            assert!(t.with_settings(&["stall=1e20"]).is_err());
+            assert!(t.with_settings(&["stall=inf"]).is_err());
+            assert!(t.with_settings(&["stall=NaN"]).is_err());
+            assert!(t.with_settings(&["junk=1"]).is_err());
+            assert!(t.with_settings(&["free"]).is_err());
+        }
+        #[test]
+        fn config_1() {
+            let defaults = Thresholds::default().with_settings(&["free=0.1"]).unwrap();
+            let config = AlarmConfig::parse(
+                "# comment\n\ne17* backlog=0.5\n/^quiet/ free=off\n",
+                defaults,
+            )
+            .unwrap();
+            let t = config.thresholds("e17raw");
+            assert_eq!(Some(0.1), t.min_free);
+            assert_eq!(Some(0.5), t.max_backlog);
+            assert!(config.thresholds("quiet1").is_empty());
+            assert_eq!(defaults, config.thresholds("other"));
+            assert!(!config.is_empty());
+
+            let e = AlarmConfig::parse("ok free=0.1\nbad free=x\n", defaults).err().unwrap();
+            assert!(e.starts_with("Line 2"));
+            assert!(AlarmConfig::new(Thresholds::default()).is_empty());
+        }
+        #[test]
+        fn free_1() {
+            let t = Thresholds::default().with_settings(&["free=0.1"]).unwrap();
+            let mut monitor = AlarmMonitor::new(AlarmConfig::new(t));
+            let now = Instant::now();
+            let changes = monitor.evaluate("fox", &sample(50, vec![]), now);
+            assert_eq!(1, changes.raised.len());
+            assert_eq!(AlarmKind::FreeSpace, changes.raised[0].kind);
+            assert_eq!(Some(1), changes.raised[0].pid);
+
+            // Still there - not raised again:
+
+            let changes = monitor.evaluate("fox", &sample(40, vec![]), now);
+            assert!(changes.raised.is_empty());
+            assert_eq!(0.04, monitor.active()[0].value);
+
+            // Cleared:
+
+            let changes = monitor.evaluate("fox", &sample(500, vec![]), now);
+            assert_eq!(1, changes.cleared.len());
+            assert!(monitor.active().is_empty());
+        }
+        #[test]
+        fn backlog_1() {
+            let t = Thresholds::default().with_settings(&["backlog=0.5"]).unwrap();
+            let mut monitor = AlarmMonitor::new(AlarmConfig::new(t));
+            let now = Instant::now();
+            assert!(monitor.evaluate("fox", &sample(1000, vec![(400, 0)]), now).raised.is_empty());
+            let changes = monitor.evaluate("fox", &sample(1000, vec![(600, 0)]), now);
+            assert_eq!(AlarmKind::Backlog, changes.raised[0].kind);
+            assert_eq!(Some(2), changes.raised[0].slot);
+
+            // The ring going away clears its alarms:
+
+            monitor.evaluate("cat", &sample(1000, vec![(600, 0)]), now);
+            assert_eq!(1, monitor.forget_ring("fox").len());
+            assert_eq!(1, monitor.active().len());
+            assert!(monitor.retain_rings(&[String::from("cat")]).is_empty());
+            assert_eq!(1, monitor.retain_rings(&[]).len());
+            assert!(monitor.active().is_empty());
+        }
+        #[test]
+        fn stall_1() {
+            let t = Thresholds::default().with_settings(&["stall=10"]).unwrap();
+            let mut monitor = AlarmMonitor::new(AlarmConfig::new(t));
+            let start = Instant::now();
+            let later = |s| start + Duration::from_secs(s);
+
+            // No backlog, not stalled however long it sits:
+
+            monitor.evaluate("fox", &sample(1000, vec![(0, 10)]), start);
+            assert!(monitor.evaluate("fox", &sample(1000, vec![(0, 10)]), later(20)).raised.is_empty());
+
+            // Backlog and no movement for 10 seconds:
+
+            monitor.evaluate("fox", &sample(900, vec![(100, 10)]), later(21));
+            assert!(monitor.evaluate("fox", &sample(900, vec![(100, 10)]), later(30)).raised.is_empty());
+            let changes = monitor.evaluate("fox", &sample(900, vec![(100, 10)]), later(31));
+            assert_eq!(AlarmKind::Stall, changes.raised[0].kind);
+
+            // Moving clears it:
+
+            let changes = monitor.evaluate("fox", &sample(900, vec![(100, 20)]), later(32));
+            assert_eq!(1, changes.cleared.len());
+        }
+        #[test]
+        fn render_1() {
+            let alarm = Alarm {
+                ring: String::from("fox"),
+                kind: AlarmKind::FreeSpace,
+                slot: None,
+                pid: Some(12),
+                value: 0.05,
+                since: 100,
+            };
+            assert_eq!(
+                "{ring fox condition FREE slot -1 pid 12 value 0.050 since 100 }",
+                alarm.to_tcl().to_string()
+            );
+            let json = alarm.to_json();
+            assert!(json["slot"].is_null());
+            assert_eq!("FREE", json["condition"]);
+        }
+    }
+}
@@ -0,0 +1,252 @@
+///
+/// The events module lets interested parties (e.g. WATCH requests)
+/// hear about changes to the ring inventory and its clients as they
+/// happen rather than by polling.  Each subscriber gets its own bounded
+/// channel on which every published event is sent.  Subscribers go away by
+/// dropping their receiver or are dropped if they fall so far behind
+/// that their channel fills.
+///
+pub mod events {
+    use crate::rings::rings::rings::Client;
+    use crate::tcllist::TclList;
+    use serde_json::{json, Value};
+    use log::warn;
+    use std::sync::mpsc::{sync_channel, Receiver, SyncSender, TrySendError};
+
+    /// Events that can be queued for a subscriber before it is dropped.
+    ///
+    pub const SUBSCRIBER_QUEUE: usize = 1024;
+
+    ///
+    /// Things that happen to rings:
+    ///
+    /// *  Registered - a ring was added to the inventory.
+    /// *  Unregistered - a ring left the inventory.
+    /// *  Connected - a client attached to a ring.
+    /// *  Disconnected - a client detached from a ring (or died).
+    ///
+    #[derive(Clone, Debug, PartialEq)]
+    pub enum RingEvent {
+        Registered { ring: String },
+        Unregistered { ring: String },
+        Connected { ring: String, client: Client },
+        Disconnected { ring: String, client: Client },
+    }
+    impl RingEvent {
+        ///
+        /// The name of the ring the event is about.
+        ///
+        pub fn ring(&self) -> &str {
+            match self {
+                RingEvent::Registered { ring }
+                | RingEvent::Unregistered { ring }
+                | RingEvent::Connected { ring, client: _ }
+                | RingEvent::Disconnected { ring, client: _ } => ring,
+            }
+        }
+        ///
+        /// Render the event as a Tcl list.  The first element is the
+        /// event type (REGISTER, UNREGISTER, CONNECT or DISCONNECT) and the
+        /// second the ring name.  Client events add the connection type as
+        /// in CONNECT requests (producer or consumer.slot) and the pid.
+        ///
+        pub fn to_tcl(&self) -> TclList {
+            let mut result = TclList::new();
+            let (kind, client) = match self {
+                RingEvent::Registered { ring: _ } => ("REGISTER", None),
+                RingEvent::Unregistered { ring: _ } => ("UNREGISTER", None),
+                RingEvent::Connected { ring: _, client } => ("CONNECT", Some(client)),
+                RingEvent::Disconnected { ring: _, client } => ("DISCONNECT", Some(client)),
+            };
+            result.add_element(kind).add_quoted_element(self.ring());
+            match client {
+                Some(Client::Producer { pid }) => {
+                    result.add_element("producer").add_element(&pid.to_string());
+                }
+                Some(Client::Consumer { pid, slot }) => {
+                    result
+                        .add_element(&format!("consumer.{}", slot))
+                        .add_element(&pid.to_string());
+                }
+                None => {}
+            }
+            result
+        }
+        ///
+        /// Render the event as a JSON object with keys event, ring and,
+        /// for client events, client (producer or consumer), slot for
+        /// consumers and pid.
+        ///
+        pub fn to_json(&self) -> Value {
+            let (kind, client) = match self {
+                RingEvent::Registered { ring: _ } => ("register", None),
+                RingEvent::Unregistered { ring: _ } => ("unregister", None),
+                RingEvent::Connected { ring: _, client } => ("connect", Some(client)),
+                RingEvent::Disconnected { ring: _, client } => ("disconnect", Some(client)),
+            };
+            let mut result = json!({ "type": "event", "event": kind, "ring": self.ring() });
+            match client {
+                Some(Client::Producer { pid }) => {
+                    result["client"] = json!("producer");
+                    result["pid"] = json!(pid);
+                }
+                Some(Client::Consumer { pid, slot }) => {
+                    result["client"] = json!("consumer");
+                    result["slot"] = json!(slot);
+                    result["pid"] = json!(pid);
+                }
+                None => {}
+            }
+            result
+        }
+    }
+    ///
+    /// Distributes events to subscribers.
+    ///
+    pub struct EventBus {
+        subscribers: Vec<SyncSender<RingEvent>>,
+        queue: usize,
+    }
+    impl EventBus {
+        pub fn new() -> EventBus {
+            Self::with_queue(SUBSCRIBER_QUEUE)
+        }
+        ///
+        /// An event bus whose subscribers can have at most queue events
+        /// waiting for them.
+        ///
+        pub fn with_queue(queue: usize) -> EventBus {
+            EventBus {
+                subscribers: Vec::new(),
+                queue,
+            }
+        }
+        ///
+        /// Subscribe to events.  Every event published from now on
+        /// is sent to the returned receiver.  The receiver is disconnected
+        /// if it lets too many events pile up.
+        ///
+        pub fn subscribe(&mut self) -> Receiver<RingEvent> {
+            let (sender, receiver) = sync_channel(self.queue);
+            self.subscribers.push(sender);
+            receiver
+        }
+        ///
+        /// Send an event to all subscribers.  Subscribers that have
+        /// dropped their receivers are forgotten, as are those whose
+        /// channels are full; publishing never waits on a subscriber.
+        ///
+        pub fn publish(&mut self, event: RingEvent) {
+            let queue = self.queue;
+            self.subscribers.retain(|subscriber| match subscriber.try_send(event.clone()) {
+                Ok(()) => true,
+                Err(TrySendError::Full(_)) => {
+                    warn!("Dropping an event subscriber with {} events unread", queue);
+                    false
+                }
+                Err(TrySendError::Disconnected(_)) => false,
+            });
+        }
+        ///
+        /// Number of subscribers as of the last publish.
+        ///
+        pub fn subscriber_count(&self) -> usize {
+            self.subscribers.len()
+        }
+    }
+    impl Default for EventBus {
+        fn default() -> Self {
+            Self::new()
+        }
+    }
+    #[cfg(test)]
+    mod events_tests {
+        use super::*;
+
+        #[test]
+        fn publish_1() {
+            let mut bus = EventBus::new();
+            let r1 = bus.subscribe();
+            let r2 = bus.subscribe();
+            let event = RingEvent::Registered {
+                ring: String::from("fox"),
+            };
+            bus.publish(event.clone());
+            assert_eq!(event, r1.try_recv().unwrap());
+            assert_eq!(event, r2.try_recv().unwrap());
+            assert!(r1.try_recv().is_err());
+        }
+        #[test]
+        fn publish_2() {
+            // Dropped subscribers are pruned:
+
+            let mut bus = EventBus::new();
+            let r1 = bus.subscribe();
+            {
+                let _r2 = bus.subscribe();
+            }
+            assert_eq!(2, bus.subscriber_count());
+            bus.publish(RingEvent::Unregistered {
+                ring: String::from("fox"),
+            });
+            assert_eq!(1, bus.subscriber_count());
+            assert!(r1.try_recv().is_ok());
+        }
+        #[test]
+        fn publish_3() {
+            // Subscribers that fall behind are dropped and disconnected
+            // once they've read what was queued:
+
+            let mut bus = EventBus::with_queue(2);
+            let r1 = bus.subscribe();
+            let event = RingEvent::Registered {
+                ring: String::from("fox"),
+            };
+            bus.publish(event.clone());
+            bus.publish(event.clone());
+            assert_eq!(1, bus.subscriber_count());
+            bus.publish(event.clone());
+            assert_eq!(0, bus.subscriber_count());
+            assert_eq!(event, r1.recv().unwrap());
+            assert_eq!(event, r1.recv().unwrap());
+            assert!(r1.recv().is_err());
+        }
+        #[test]
+        fn tcl_1() {
+            let event = RingEvent::Connected {
+                ring: String::from("fox"),
+                client: Client::Consumer { pid: 123, slot: 4 },
+            };
+            assert_eq!("fox", event.ring());
+            assert_eq!("{CONNECT fox consumer.4 123 }", event.to_tcl().to_string());
+            let event = RingEvent::Disconnected {
+                ring: String::from("fox"),
+                client: Client::Producer { pid: 123 },
+            };
+            assert_eq!("{DISCONNECT fox producer 123 }", event.to_tcl().to_string());
+            let event = RingEvent::Registered {
+                ring: String::from("fox"),
+            };
+            assert_eq!("{REGISTER fox }", event.to_tcl().to_string());
+        }
+        #[test]
+        fn json_1() {
+            let event = RingEvent::Connected {
+                ring: String::from("fox"),
+                client: Client::Consumer { pid: 123, slot: 4 },
+            };
+            let json = event.to_json();
+            assert_eq!("connect", json["event"]);
+            assert_eq!("consumer", json["client"]);
+            assert_eq!(4, json["slot"]);
+            assert_eq!(123, json["pid"]);
+
+            let json = RingEvent::Unregistered {
+                ring: String::from("fox"),
+            }
+            .to_json();
+            assert_eq!("unregister", json["event"]);
+            assert!(json.get("pid").is_none());
+        }
+    }
+}
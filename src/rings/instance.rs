@@ -0,0 +1,184 @@
+///
+/// The instance module keeps two ringmasters from serving the same ring
+/// directory.  Each ringmaster holds an exclusive flock(2) on a pidfile
+/// whose name is derived from its ring directory.  The kernel drops the
+/// lock when the holder exits however it exits, so a pidfile that is not
+/// locked was left by a dead ringmaster and is simply reused.
+///
+pub mod instance {
+    use crate::rings::process::process::ProcessIdentity;
+    use crate::rings::rings::rings::{KillPolicy, RingBufferInfo};
+    use log::{info, warn};
+    use std::fs::{self, File, OpenOptions, TryLockError};
+    use std::io::{Read, Seek, SeekFrom, Write};
+    use std::path::{Path, PathBuf};
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    ///
+    /// The lock on a ring directory.  The lock is held until this is
+    /// dropped.
+    ///
+    #[derive(Debug)]
+    pub struct InstanceLock {
+        path: PathBuf,
+        _file: File,
+    }
+    impl InstanceLock {
+        ///
+        /// The pidfile in lock_dir for a ring directory, e.g.
+        /// lock_dir/ringmaster_dev_shm.pid for /dev/shm.  The ring directory
+        /// is canonicalized if possible so different spellings of it get
+        /// the same pidfile.
+        ///
+        pub fn lock_path(lock_dir: &str, ring_dir: &str) -> PathBuf {
+            let canonical = fs::canonicalize(ring_dir).unwrap_or_else(|_| PathBuf::from(ring_dir));
+            let key = canonical
+                .to_string_lossy()
+                .chars()
+                .map(|c| if c.is_ascii_alphanumeric() || c == '.' || c == '-' { c } else { '_' })
+                .collect::<String>();
+            Path::new(lock_dir).join(format!("ringmaster{}.pid", key))
+        }
+        ///
+        /// Lock a pidfile and write our pid into it.  If another live
+        /// process holds the lock, Err says who unless takeover is given;
+        /// then that process is sent SIGTERM and, if it has not exited
+        /// within the takeover grace period, SIGKILL, and we take the lock.
+        ///
+        pub fn acquire(path: &Path, takeover: Option<Duration>) -> Result<InstanceLock, String> {
+            let mut file = OpenOptions::new()
+                .read(true)
+                .write(true)
+                .create(true)
+                .truncate(false)
+                .open(path)
+                .map_err(|e| format!("Unable to open the lock file {}: {}", path.display(), e))?;
+            match file.try_lock() {
+                Ok(()) => {}
+                Err(TryLockError::WouldBlock) => {
+                    let holder = read_pid(&mut file);
+                    let grace = match takeover {
+                        Some(grace) => grace,
+                        None => return Err(held_message(path, holder)),
+                    };
+                    let pid = holder.ok_or_else(|| held_message(path, holder))?;
+                    warn!("Taking over from the ringmaster with pid {} ({})", pid, path.display());
+                    RingBufferInfo::terminate_pid(pid, ProcessIdentity::of(pid), KillPolicy::Kill, grace);
+                    wait_for_lock(&file, grace).map_err(|_| held_message(path, holder))?;
+                }
+                Err(TryLockError::Error(e)) => {
+                    return Err(format!("Unable to lock {}: {}", path.display(), e));
+                }
+            }
+            // We hold the lock.  Anything in the file is stale:
+
+            if let Some(pid) = read_pid(&mut file) {
+                info!("Replacing the stale lock of pid {} in {}", pid, path.display());
+            }
+            file.set_len(0)
+                .and_then(|_| file.seek(SeekFrom::Start(0)))
+                .and_then(|_| writeln!(file, "{}", std::process::id()))
+                .map_err(|e| format!("Unable to write our pid to {}: {}", path.display(), e))?;
+            Ok(InstanceLock {
+                path: PathBuf::from(path),
+                _file: file,
+            })
+        }
+        ///
+        /// The pidfile path.
+        ///
+        pub fn path(&self) -> &Path {
+            &self.path
+        }
+    }
+    // Read the pid in a pidfile (None if there isn't a valid one).
+
+    fn read_pid(file: &mut File) -> Option<u32> {
+        let mut contents = String::new();
+        file.seek(SeekFrom::Start(0)).ok()?;
+        file.read_to_string(&mut contents).ok()?;
+        contents.trim().parse::<u32>().ok()
+    }
+    // Describe who holds a lock.
+
+    fn held_message(path: &Path, holder: Option<u32>) -> String {
+        match holder {
+            Some(pid) => format!(
+                "Another ringmaster (pid {}) is serving this ring directory (lock file {})",
+                pid,
+                path.display()
+            ),
+            None => format!(
+                "Another ringmaster is serving this ring directory (lock file {})",
+                path.display()
+            ),
+        }
+    }
+    // Try for a lock for a while after its holder was killed.
+
+    fn wait_for_lock(file: &File, grace: Duration) -> Result<(), ()> {
+        let started = Instant::now();
+        while started.elapsed() < grace + Duration::from_secs(2) {
+            if file.try_lock().is_ok() {
+                return Ok(());
+            }
+            thread::sleep(Duration::from_millis(50));
+        }
+        Err(())
+    }
+    #[cfg(test)]
+    mod instance_tests {
+        use super::*;
+
+        fn temp_lock(name: &str) -> PathBuf {
+            let path = std::env::temp_dir().join(format!("instance_{}_{}.pid", name, std::process::id()));
+            let _ = fs::remove_file(&path);
+            path
+        }
+        #[test]
+        fn path_1() {
+            let path = InstanceLock::lock_path("/run/lock", "/no/such/dir");
+            assert_eq!(PathBuf::from("/run/lock/ringmaster_no_such_dir.pid"), path);
+            assert_eq!(
+                InstanceLock::lock_path("/tmp", "src"),
+                InstanceLock::lock_path("/tmp", "./src/")
+            );
+        }
+        #[test]
+        fn acquire_1() {
+            let path = temp_lock("acquire");
+            let lock = InstanceLock::acquire(&path, None).unwrap();
+            assert_eq!(
+                format!("{}\n", std::process::id()),
+                fs::read_to_string(lock.path()).unwrap()
+            );
+            // A second lock (on another open file) fails and says who
+            // has it:
+
+            let e = InstanceLock::acquire(&path, None).unwrap_err();
+            assert!(e.contains(&format!("pid {}", std::process::id())));
+
+            // Released when dropped:
+
+            drop(lock);
+            let lock = InstanceLock::acquire(&path, None).unwrap();
+            drop(lock);
+            fs::remove_file(&path).unwrap();
+        }
+        #[test]
+        fn stale_1() {
+            // A pidfile left behind is taken over:
+
+            let path = temp_lock("stale");
+            fs::write(&path, "4000000\n").unwrap();
+            let lock = InstanceLock::acquire(&path, None).unwrap();
+            assert_eq!(
+                format!("{}\n", std::process::id()),
+                fs::read_to_string(&path).unwrap()
+            );
+            drop(lock);
+            fs::remove_file(&path).unwrap();
+        }
+    }
+}
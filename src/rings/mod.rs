@@ -12,8 +12,38 @@
 //! those rings.  For the most part, that is the set of thread handles
 //! that represent threads that are monitoring client exits and
 //! the variable used to ask a thread to exit.  
+//! *  The identity of client processes and a reaper that frees slots
+//!    held by processes that are gone.
+//! *  Creation and removal of the ring buffer files themselves.
+//! *  Detailed status reports about individual rings.
+//! *  Alarms raised when rings fill or consumers fall behind.
+//! *  Events published as rings and clients come and go.
+//! *  The put and get positions of ring clients.
+//! *  Data rates of ring clients.
+//! *  Locking a ring directory so only one ringmaster serves it.
+//! *  Matching ring names against glob and regular expression patterns.
 //!
+pub mod alarms;
+pub mod events;
+pub mod instance;
 pub mod inventory;
+pub mod pattern;
+pub mod positions;
+pub mod process;
+pub mod reaper;
+pub mod ringfile;
 pub mod rings;
+pub mod stats;
+pub mod status;
+pub use self::alarms::alarms::*;
+pub use self::events::events::*;
+pub use self::instance::instance::*;
 pub use self::inventory::inventory::*;
+pub use self::pattern::pattern::*;
+pub use self::positions::positions::*;
+pub use self::process::process::*;
+pub use self::reaper::reaper::*;
+pub use self::ringfile::ringfile::*;
 pub use self::rings::rings::*;
+pub use self::stats::stats::*;
+pub use self::status::status::*;
@@ -0,0 +1,134 @@
+///
+/// The pattern module matches ring names against the patterns clients
+/// give to requests like LIST.  Two sorts of patterns are supported:
+///
+/// *  Glob patterns as in the shell: * matches any string, ? any single
+///    character and [...] any of a set of characters.
+/// *  Regular expressions, written between slashes, e.g. /^e17.*$/.
+///    Since ring names can't contain a /, this can't be confused with a
+///    glob.  Regular expressions are unanchored unless they say otherwise.
+///
+pub mod pattern {
+    use regex::Regex;
+
+    ///
+    /// A compiled ring name pattern.
+    ///
+    #[derive(Clone, Debug)]
+    pub struct RingPattern {
+        regex: Regex,
+    }
+    impl RingPattern {
+        ///
+        /// Compile a pattern.  Err describes what's wrong with it.
+        ///
+        pub fn parse(pattern: &str) -> Result<RingPattern, String> {
+            let source = if pattern.len() >= 2 && pattern.starts_with('/') && pattern.ends_with('/') {
+                String::from(&pattern[1..pattern.len() - 1])
+            } else {
+                glob_to_regex(pattern)?
+            };
+            match Regex::new(&source) {
+                Ok(regex) => Ok(RingPattern { regex }),
+                Err(e) => {
+                    // Regex errors are multi-line but replies are one line:
+
+                    let reason = e.to_string().split_whitespace().collect::<Vec<&str>>().join(" ");
+                    Err(format!("Invalid pattern {}: {}", pattern, reason))
+                }
+            }
+        }
+        ///
+        /// Determine if a ring name matches the pattern.
+        ///
+        pub fn matches(&self, name: &str) -> bool {
+            self.regex.is_match(name)
+        }
+    }
+    // Turn a glob into an equivalent anchored regular expression.
+
+    fn glob_to_regex(glob: &str) -> Result<String, String> {
+        let mut result = String::from("^");
+        let mut chars = glob.chars();
+        while let Some(c) = chars.next() {
+            match c {
+                '*' => result.push_str(".*"),
+                '?' => result.push('.'),
+                '[' => {
+                    // Copy the set through to ] - a leading ! negates.
+
+                    let mut set = String::from("[");
+                    let mut first = true;
+                    loop {
+                        match chars.next() {
+                            Some(']') if !first => break,
+                            Some('!') if first => set.push('^'),
+                            Some('\\') => set.push_str("\\\\"),
+                            Some('[') => set.push_str("\\["),
+                            Some(c) => set.push(c),
+                            None => return Err(format!("Unterminated [ in {}", glob)),
+                        }
+                        first = false;
+                    }
+                    set.push(']');
+                    result.push_str(&set);
+                }
+                _ => result.push_str(&regex::escape(&c.to_string())),
+            }
+        }
+        result.push('$');
+        Ok(result)
+    }
+    #[cfg(test)]
+    mod pattern_tests {
+        use super::*;
+
+        #[test]
+        fn glob_1() {
+            let p = RingPattern::parse("e17*").unwrap();
+            assert!(p.matches("e17"));
+            assert!(p.matches("e17001"));
+            assert!(!p.matches("xe17"));
+        }
+        #[test]
+        fn glob_2() {
+            // ? and character sets:
+
+            let p = RingPattern::parse("ring?").unwrap();
+            assert!(p.matches("ring1"));
+            assert!(!p.matches("ring"));
+            assert!(!p.matches("ring12"));
+
+            let p = RingPattern::parse("ring[0-3]").unwrap();
+            assert!(p.matches("ring2"));
+            assert!(!p.matches("ring4"));
+
+            let p = RingPattern::parse("ring[!0-3]").unwrap();
+            assert!(!p.matches("ring2"));
+            assert!(p.matches("ring4"));
+        }
+        #[test]
+        fn glob_3() {
+            // Regex specials are literal in globs:
+
+            let p = RingPattern::parse("a.b+").unwrap();
+            assert!(p.matches("a.b+"));
+            assert!(!p.matches("aab"));
+            assert!(RingPattern::parse("ring[0-3").is_err());
+        }
+        #[test]
+        fn regex_1() {
+            let p = RingPattern::parse("/^e1[78]/").unwrap();
+            assert!(p.matches("e17001"));
+            assert!(p.matches("e18"));
+            assert!(!p.matches("e19"));
+
+            // Unanchored:
+
+            let p = RingPattern::parse("/raw/").unwrap();
+            assert!(p.matches("e17raw1"));
+            let e = RingPattern::parse("/(/").unwrap_err();
+            assert!(!e.contains('\n'));
+        }
+    }
+}
@@ -0,0 +1,303 @@
+///
+/// The positions module reads the put and get offsets of a ring's
+/// producer and consumers.  nscldaq_ringbuffer keeps these private so,
+/// once RingBufferMap has vouched for the file and told us its geometry,
+/// we read them straight from the ring buffer file.  The file starts with
+/// the #[repr(C)] RingHeader:
+///
+/// *  magic string - 32 bytes.
+/// *  max_consumer, data_bytes, producer_offset, consumer_offset,
+///    data_offset, top_offset - each a usize.
+///
+/// producer_offset and consumer_offset locate the #[repr(C)]
+/// ClientInformation structs, each a usize put/get offset followed by a
+/// u32 pid (padded to the usize alignment).  All offsets are from the
+/// start of the file.
+///
+/// Ring files are world writable so nothing in them is trusted: counts
+/// and offsets that don't fit the file are errors.
+///
+pub mod positions {
+    use crate::rings::ringfile::ringfile::MAX_CONSUMER_SLOTS;
+    use nscldaq_ringbuffer::ringbuffer;
+    use std::convert::TryInto;
+    use std::fs::File;
+    use std::mem::size_of;
+    use std::os::unix::fs::FileExt;
+
+    const MAGIC_SIZE: usize = 32;
+    const HEADER_FIELDS: usize = 6;
+    const PRODUCER_FIELD: usize = 2; // Indices of the fields RingBufferMap
+    const CONSUMER_FIELD: usize = 3; // does not give us.
+    const CLIENT_SIZE: usize = 2 * size_of::<usize>(); // offset + pid + padding.
+
+    ///
+    /// Where a client is in the ring:
+    ///
+    /// *  slot - consumer slot number (0 for the producer).
+    /// *  pid - the owning process.
+    /// *  offset - the put (producer) or get (consumer) offset.
+    ///
+    #[derive(Copy, Clone, Debug, PartialEq)]
+    pub struct ClientPosition {
+        pub slot: usize,
+        pub pid: u32,
+        pub offset: usize,
+    }
+    ///
+    /// The geometry of a ring and the positions of its clients.
+    /// Only slots in use are in consumers.  put_offset is where the
+    /// next data goes whether or not there's a producer now.
+    ///
+    #[derive(Clone, Debug, PartialEq)]
+    pub struct RingPositions {
+        pub max_consumers: usize,
+        pub data_bytes: usize,
+        pub data_offset: usize,
+        pub top_offset: usize,
+        pub put_offset: usize,
+        pub producer: Option<ClientPosition>,
+        pub consumers: Vec<ClientPosition>,
+    }
+    impl RingPositions {
+        ///
+        /// Read the positions from a ring buffer file.  Err if the file
+        /// can't be read or is not a ring buffer.
+        ///
+        pub fn read(path: &str) -> Result<RingPositions, String> {
+            let corrupt = |what: &str| format!("{} is corrupt: {}", path, what);
+
+            // RingBufferMap checks the magic string and gives us the geometry:
+
+            let (max_consumers, data_bytes, data_offset, top_offset) = {
+                let map = ringbuffer::RingBufferMap::new(path)?;
+                (map.max_consumers(), map.data_bytes(), map.data_offset(), map.top_offset())
+            };
+            let file = File::open(path).map_err(|e| format!("Unable to open {}: {}", path, e))?;
+            let file_size = file
+                .metadata()
+                .map_err(|e| format!("Unable to stat {}: {}", path, e))?
+                .len() as usize;
+            let mut header = vec![0u8; MAGIC_SIZE + HEADER_FIELDS * size_of::<usize>()];
+            file.read_exact_at(&mut header, 0)
+                .map_err(|e| format!("Unable to read the header of {}: {}", path, e))?;
+            let field = |n: usize| usize_at(&header, MAGIC_SIZE + n * size_of::<usize>());
+            let producer_offset = field(PRODUCER_FIELD);
+            let consumer_offset = field(CONSUMER_FIELD);
+
+            if max_consumers > MAX_CONSUMER_SLOTS {
+                return Err(corrupt(&format!("{} consumer slots", max_consumers)));
+            }
+            if data_offset > top_offset || top_offset > file_size {
+                return Err(corrupt("data area is outside the file"));
+            }
+            let fits = |offset: usize, size: usize| {
+                offset.checked_add(size).is_some_and(|end| end <= data_offset)
+            };
+            if !fits(producer_offset, CLIENT_SIZE)
+                || !fits(consumer_offset, max_consumers * CLIENT_SIZE)
+            {
+                return Err(corrupt("client slots are outside the header"));
+            }
+
+            let mut clients = vec![0u8; (max_consumers + 1) * CLIENT_SIZE];
+            file.read_exact_at(&mut clients[..CLIENT_SIZE], producer_offset as u64)
+                .and_then(|_| {
+                    file.read_exact_at(&mut clients[CLIENT_SIZE..], consumer_offset as u64)
+                })
+                .map_err(|e| format!("Unable to read the clients of {}: {}", path, e))?;
+            let client = |n: usize, slot: usize| {
+                let base = n * CLIENT_SIZE;
+                let pid = u32::from_ne_bytes(
+                    clients[base + size_of::<usize>()..base + size_of::<usize>() + 4]
+                        .try_into()
+                        .unwrap(),
+                );
+                if pid == ringbuffer::UNUSED_ENTRY {
+                    None
+                } else {
+                    Some(ClientPosition {
+                        slot,
+                        pid,
+                        offset: usize_at(&clients, base),
+                    })
+                }
+            };
+            let in_data = |offset: usize| offset >= data_offset && offset <= top_offset;
+            let put_offset = usize_at(&clients, 0);
+            let producer = client(0, 0);
+            let consumers = (0..max_consumers)
+                .filter_map(|slot| client(slot + 1, slot))
+                .collect::<Vec<ClientPosition>>();
+            if !in_data(put_offset) || consumers.iter().any(|c| !in_data(c.offset)) {
+                return Err(corrupt("a client offset is outside the data area"));
+            }
+            Ok(RingPositions {
+                max_consumers,
+                data_bytes,
+                data_offset,
+                top_offset,
+                put_offset,
+                producer,
+                consumers,
+            })
+        }
+        ///
+        /// Bytes between two offsets going forward around the ring:
+        /// how far a client at from has to go to get to to.
+        /// This mirrors RingBufferMap's internal distance computation.
+        /// Offsets outside the data area (e.g. kept from before the ring
+        /// was remade) don't underflow; they just give nonsense distances.
+        ///
+        pub fn distance(&self, from: usize, to: usize) -> usize {
+            if from <= to {
+                to - from
+            } else {
+                to.saturating_sub(self.data_offset)
+                    .saturating_add(self.top_offset.saturating_sub(from))
+                    .saturating_add(1)
+            }
+        }
+        ///
+        /// Bytes a consumer has yet to get (0 if there's no producer).
+        ///
+        pub fn backlog(&self, consumer: &ClientPosition) -> usize {
+            match self.producer {
+                Some(producer) => self.distance(consumer.offset, producer.offset),
+                None => 0,
+            }
+        }
+        ///
+        /// The usage of the ring as RingBufferMap::get_usage computes it.
+        /// consumer_usage has an entry for each element of consumers so
+        /// the slots and backlogs of a snapshot are always paired
+        /// correctly.  Like get_usage, the backlogs are computed whether
+        /// or not there's a producer.
+        ///
+        pub fn usage(&self) -> ringbuffer::RingStatus {
+            let consumer_usage = self
+                .consumers
+                .iter()
+                .map(|c| {
+                    let available = self.distance(c.offset, self.put_offset);
+                    ringbuffer::ConsumerUsage {
+                        pid: c.pid,
+                        free: self.data_bytes.saturating_sub(available),
+                        available,
+                    }
+                })
+                .collect::<Vec<_>>();
+            ringbuffer::RingStatus {
+                producer_pid: self.producer.map_or(ringbuffer::UNUSED_ENTRY, |p| p.pid),
+                free_space: consumer_usage.iter().map(|u| u.free).fold(self.data_bytes, usize::min),
+                max_queued: consumer_usage.iter().map(|u| u.available).fold(0, usize::max),
+                consumer_usage,
+            }
+        }
+    }
+    // Native endian usize at an offset in a buffer.
+
+    fn usize_at(buffer: &[u8], offset: usize) -> usize {
+        usize::from_ne_bytes(buffer[offset..offset + size_of::<usize>()].try_into().unwrap())
+    }
+    #[cfg(test)]
+    mod positions_tests {
+        use super::*;
+        use crate::rings::ringfile::ringfile;
+        use std::process;
+
+        #[test]
+        fn read_1() {
+            assert!(RingPositions::read("/no/such/ring").is_err());
+            assert!(RingPositions::read("Cargo.toml").is_err());
+        }
+        #[test]
+        fn read_2() {
+            let me = process::id();
+            let path = std::env::temp_dir().join(format!("positions_{}", me));
+            let path = path.to_str().unwrap();
+            ringfile::create_ring(path, 1000, 4, 0o666).unwrap();
+
+            let positions = RingPositions::read(path).unwrap();
+            assert_eq!(1000, positions.data_bytes);
+            assert!(positions.producer.is_none());
+            assert!(positions.consumers.is_empty());
+            {
+                let mut map = ringbuffer::RingBufferMap::new(path).unwrap();
+                map.set_producer(me).unwrap();
+                map.set_consumer(3, me).unwrap();
+                map.produce(&[0u8; 100]).unwrap();
+            }
+            let positions = RingPositions::read(path).unwrap();
+            let producer = positions.producer.unwrap();
+            assert_eq!(me, producer.pid);
+            assert_eq!(positions.data_offset + 100, producer.offset);
+            assert_eq!(1, positions.consumers.len());
+            assert_eq!(3, positions.consumers[0].slot);
+            assert_eq!(100, positions.backlog(&positions.consumers[0]));
+
+            // usage agrees with the ring's own idea of its usage:
+
+            let expected = ringbuffer::RingBufferMap::new(path).unwrap().get_usage();
+            let usage = positions.usage();
+            assert_eq!(expected.producer_pid, usage.producer_pid);
+            assert_eq!(expected.free_space, usage.free_space);
+            assert_eq!(expected.max_queued, usage.max_queued);
+            assert_eq!(expected.consumer_usage, usage.consumer_usage);
+
+            ringfile::delete_ring(path).unwrap();
+        }
+        #[test]
+        fn read_3() {
+            // Corrupt headers and offsets are errors, not panics:
+
+            let path = std::env::temp_dir().join(format!("positions_bad_{}", process::id()));
+            let path = path.to_str().unwrap();
+            let corrupt = |field: usize, value: usize| {
+                ringfile::create_ring(path, 1000, 4, 0o666).unwrap();
+                let file = std::fs::OpenOptions::new().write(true).open(path).unwrap();
+                file.write_all_at(&value.to_ne_bytes(), (MAGIC_SIZE + field * size_of::<usize>()) as u64)
+                    .unwrap();
+                let result = RingPositions::read(path);
+                ringfile::delete_ring(path).unwrap();
+                result
+            };
+            assert!(corrupt(0, usize::MAX).is_err()); // max_consumers
+            assert!(corrupt(0, MAX_CONSUMER_SLOTS + 1).is_err());
+            assert!(corrupt(PRODUCER_FIELD, usize::MAX - 4).is_err());
+            assert!(corrupt(CONSUMER_FIELD, 1 << 40).is_err());
+            assert!(corrupt(4, 0).is_err()); // data_offset below the clients.
+            assert!(corrupt(4, 1 << 40).is_err()); // data_offset past top_offset.
+
+            // A consumer whose get offset is outside the data area:
+
+            ringfile::create_ring(path, 1000, 4, 0o666).unwrap();
+            ringbuffer::RingBufferMap::new(path).unwrap().set_consumer(1, process::id()).unwrap();
+            let consumers = usize_at(
+                &std::fs::read(path).unwrap(),
+                MAGIC_SIZE + CONSUMER_FIELD * size_of::<usize>(),
+            );
+            let file = std::fs::OpenOptions::new().write(true).open(path).unwrap();
+            file.write_all_at(&3usize.to_ne_bytes(), (consumers + CLIENT_SIZE) as u64).unwrap();
+            assert!(RingPositions::read(path).is_err());
+            ringfile::delete_ring(path).unwrap();
+        }
+        #[test]
+        fn distance_1() {
+            let positions = RingPositions {
+                max_consumers: 100,
+                data_bytes: 100,
+                data_offset: 1000,
+                top_offset: 1099,
+                put_offset: 1000,
+                producer: None,
+                consumers: vec![],
+            };
+            assert_eq!(0, positions.distance(1010, 1010));
+            assert_eq!(10, positions.distance(1010, 1020));
+            assert_eq!(90, positions.distance(1020, 1010));
+            assert_eq!(0, positions.distance(10, 10));
+            positions.distance(5000, 10); // Doesn't panic.
+        }
+    }
+}
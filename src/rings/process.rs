@@ -0,0 +1,249 @@
+///
+/// This module provides a way to identify a process in a manner
+/// that survives pid reuse.  A pid alone is not enough on long running
+/// systems, since once a process exits its pid can be handed out to
+/// some unrelated process.  The pair (pid, start time) is, however,
+/// unique for the lifetime of the system.
+///
+pub mod process {
+    use std::collections::HashMap;
+    use std::fs;
+    use sysinfo::{Pid, ProcessRefreshKind, ProcessesToUpdate, System, UpdateKind, Users};
+
+    ///
+    /// Identifies a process:
+    ///
+    /// *  pid - the process id.
+    /// *  start_time - the time the process started in clock ticks since
+    ///    system boot (field 22 of /proc/pid/stat).
+    ///
+    #[derive(Copy, Clone, Debug, PartialEq)]
+    pub struct ProcessIdentity {
+        pub pid: u32,
+        pub start_time: u64,
+    }
+    impl ProcessIdentity {
+        ///
+        /// Get the identity of the process that currently has the
+        /// pid.  None is returned if there's no such process.  Zombies
+        /// have exited so they don't count as processes either.
+        ///
+        pub fn of(pid: u32) -> Option<ProcessIdentity> {
+            let stat = fs::read_to_string(format!("/proc/{}/stat", pid)).ok()?;
+            let start_time = parse_start_time(&stat)?;
+            Some(ProcessIdentity { pid, start_time })
+        }
+        ///
+        /// Determine if the process this identifies is still alive.
+        /// This is false if the pid no longer exists or if it has been
+        /// reused by a process that started at a different time.
+        ///
+        pub fn is_alive(&self) -> bool {
+            ProcessIdentity::of(self.pid) == Some(*self)
+        }
+    }
+    ///
+    /// Describes a process for humans:
+    ///
+    /// *  name - the name of the executable.
+    /// *  command - the command line words.
+    /// *  user - the name of the user running the process if known.
+    ///
+    #[derive(Clone, Debug, PartialEq)]
+    pub struct ProcessDescription {
+        pub name: String,
+        pub command: Vec<String>,
+        pub user: Option<String>,
+    }
+    ///
+    /// Describe the process with the pid.  None if there's no such process.
+    ///
+    pub fn describe_process(pid: u32) -> Option<ProcessDescription> {
+        describe_processes(&[pid]).remove(&pid)
+    }
+    ///
+    /// Describe several processes at once.  This is cheaper than
+    /// describe_process for each as the processes and the user database
+    /// are only read once.  Pids with no process are not in the result.
+    ///
+    pub fn describe_processes(pids: &[u32]) -> HashMap<u32, ProcessDescription> {
+        let mut result = HashMap::<u32, ProcessDescription>::new();
+        if pids.is_empty() {
+            return result;
+        }
+        let sys_pids = pids.iter().map(|p| Pid::from_u32(*p)).collect::<Vec<Pid>>();
+        let mut sys = System::new();
+        sys.refresh_processes_specifics(
+            ProcessesToUpdate::Some(&sys_pids),
+            true,
+            ProcessRefreshKind::nothing()
+                .with_cmd(UpdateKind::Always)
+                .with_user(UpdateKind::Always),
+        );
+        let users = Users::new_with_refreshed_list();
+        for pid in pids {
+            if let Some(process) = sys.process(Pid::from_u32(*pid)) {
+                let user = process
+                    .user_id()
+                    .and_then(|uid| users.get_user_by_id(uid))
+                    .map(|user| user.name().to_string());
+                result.insert(
+                    *pid,
+                    ProcessDescription {
+                        name: process.name().to_string_lossy().to_string(),
+                        command: process
+                            .cmd()
+                            .iter()
+                            .map(|word| word.to_string_lossy().to_string())
+                            .collect(),
+                        user,
+                    },
+                );
+            }
+        }
+        result
+    }
+    ///
+    /// Determine if any process with the pid exists.
+    ///
+    pub fn process_exists(pid: u32) -> bool {
+        ProcessIdentity::of(pid).is_some()
+    }
+    ///
+    /// Determine if a slot the ring shows as owned by pid may be freed on
+    /// behalf of the client whose identity we recorded.  That's the case
+    /// if no process has the pid any more (the client is gone) or the
+    /// process that has it is our client.  If the pid has been reused by
+    /// some other process, the slot is not ours to free.  Without a
+    /// recorded identity all we can do is trust the pid.
+    ///
+    pub fn may_free(pid: u32, identity: Option<ProcessIdentity>) -> bool {
+        match identity {
+            Some(id) => {
+                id.pid == pid
+                    && match ProcessIdentity::of(pid) {
+                        Some(now) => now == id,
+                        None => true,
+                    }
+            }
+            None => true,
+        }
+    }
+    ///
+    /// Pull the start time out of the contents of a /proc/pid/stat file.
+    /// The command name (field 2) is in parentheses and can contain
+    /// spaces and parens so we count fields from the last ')'.
+    /// The first field after that is field 3 (the state) so the start
+    /// time, field 22, is the 20th.  Zombie and dead processes give None.
+    ///
+    fn parse_start_time(stat: &str) -> Option<u64> {
+        let tail = &stat[stat.rfind(')')? + 1..];
+        let fields = tail.split_whitespace().collect::<Vec<&str>>();
+        match *fields.first()? {
+            "Z" | "X" => None,
+            _ => fields.get(19)?.parse::<u64>().ok(),
+        }
+    }
+    #[cfg(test)]
+    mod process_tests {
+        use super::*;
+        use std::process;
+
+        #[test]
+        fn parse_1() {
+            let stat = "1234 (ring2stdout) S 1 1234 1234 0 -1 4194560 100 0 0 0 1 2 0 0 20 0 1 0 98765 1000 10";
+            assert_eq!(Some(98765), parse_start_time(stat));
+        }
+        #[test]
+        fn parse_2() {
+            // Command names with spaces and parens:
+
+            let stat = "1234 (a (b) c) S 1 1234 1234 0 -1 4194560 100 0 0 0 1 2 0 0 20 0 1 0 555 1000 10";
+            assert_eq!(Some(555), parse_start_time(stat));
+        }
+        #[test]
+        fn parse_3() {
+            // truncated:
+            assert!(parse_start_time("1234 (x) S 1 2").is_none());
+            assert!(parse_start_time("garbage").is_none());
+        }
+        #[test]
+        fn parse_4() {
+            // Zombies don't count:
+
+            let stat = "1234 (defunct) Z 1 1234 1234 0 -1 4194560 100 0 0 0 1 2 0 0 20 0 1 0 98765 1000 10";
+            assert!(parse_start_time(stat).is_none());
+        }
+        #[test]
+        fn of_1() {
+            let me = ProcessIdentity::of(process::id());
+            assert!(me.is_some());
+            let me = me.unwrap();
+            assert_eq!(process::id(), me.pid);
+            assert!(me.is_alive());
+            assert!(process_exists(process::id()));
+        }
+        #[test]
+        fn reused_1() {
+            // Same pid different start time is not the same process:
+
+            let mut me = ProcessIdentity::of(process::id()).unwrap();
+            me.start_time += 1;
+            assert!(!me.is_alive());
+        }
+        #[test]
+        fn describe_1() {
+            let child = process::Command::new("sleep").arg("10").spawn();
+            let mut child = child.unwrap();
+            std::thread::sleep(std::time::Duration::from_millis(200)); // Let it exec.
+            let description = describe_process(child.id()).unwrap();
+            assert_eq!("sleep", description.name);
+            assert_eq!(vec!["sleep", "10"], description.command);
+            assert_eq!(Some(whoami::username().unwrap()), description.user);
+
+            child.kill().unwrap();
+            child.wait().unwrap();
+            assert!(describe_process(0xfffffff0).is_none());
+        }
+        #[test]
+        fn describe_2() {
+            // Several at once, missing ones are left out:
+
+            let me = process::id();
+            let descriptions = describe_processes(&[me, 0xfffffff0]);
+            assert_eq!(1, descriptions.len());
+            assert!(descriptions.contains_key(&me));
+            assert!(describe_processes(&[]).is_empty());
+        }
+        #[test]
+        fn may_free_1() {
+            // Our own slot while we're alive, or with no identity to check:
+
+            let me = ProcessIdentity::of(process::id());
+            assert!(may_free(process::id(), me));
+            assert!(may_free(process::id(), None));
+        }
+        #[test]
+        fn may_free_2() {
+            // pid reused by someone else:
+
+            let mut me = ProcessIdentity::of(process::id()).unwrap();
+            me.start_time += 1;
+            assert!(!may_free(process::id(), Some(me)));
+
+            // Slot owned by some other pid entirely:
+
+            assert!(!may_free(process::id() + 1, ProcessIdentity::of(process::id())));
+        }
+        #[test]
+        fn may_free_3() {
+            // Dead processes' slots can always be freed:
+
+            let gone = ProcessIdentity {
+                pid: 0xfffffff0,
+                start_time: 1234,
+            };
+            assert!(may_free(0xfffffff0, Some(gone)));
+        }
+    }
+}
@@ -0,0 +1,130 @@
+///
+/// The reaper module finds producer and consumer slots in a ring
+/// that are held by processes that no longer exist and frees them.
+/// This happens when a client crashes without ever having talked to the
+/// ringmaster or when clients were attached before the ringmaster
+/// was restarted.
+///
+pub mod reaper {
+    use crate::rings::process::process::{process_exists, ProcessIdentity};
+    use crate::rings::rings::rings::Client;
+    use nscldaq_ringbuffer::ringbuffer;
+
+    ///
+    /// Determine if the owner of a slot is gone.  If we know the identity
+    /// the pid had when it became our client, pid reuse is detected by
+    /// the start time changing.  Otherwise all we can do is check that
+    /// some process with that pid exists.
+    ///
+    pub fn is_orphaned(pid: u32, known: Option<ProcessIdentity>) -> bool {
+        match known {
+            Some(identity) if identity.pid == pid => !identity.is_alive(),
+            _ => !process_exists(pid),
+        }
+    }
+    ///
+    /// Sweep the slots of a single ring, freeing those whose owners are
+    /// orphaned (see is_orphaned).
+    ///
+    /// *  ring_file - path to the ring buffer file.
+    /// *  known - returns the identity we recorded for a pid if any.
+    /// *  dry_run - if true, nothing is freed, we just report what
+    ///    would have been.
+    ///
+    /// On success the slots that were (or would have been) reclaimed are
+    /// returned. Err is returned if the ring could not be mapped.
+    ///
+    pub fn reap_ring(
+        ring_file: &str,
+        known: &dyn Fn(u32) -> Option<ProcessIdentity>,
+        dry_run: bool,
+    ) -> Result<Vec<Client>, String> {
+        let mut map = ringbuffer::RingBufferMap::new(ring_file)?;
+        let mut reclaimed = Vec::<Client>::new();
+
+        let pid = map.producer().get_pid();
+        if pid != ringbuffer::UNUSED_ENTRY
+            && is_orphaned(pid, known(pid))
+            && (dry_run || map.free_producer(pid).is_ok())
+        {
+            reclaimed.push(Client::Producer { pid });
+        }
+        for slot in 0..map.max_consumers() {
+            let pid = map.consumer(slot)?.get_pid();
+            if pid != ringbuffer::UNUSED_ENTRY
+                && is_orphaned(pid, known(pid))
+                && (dry_run || map.free_consumer(slot, pid).is_ok())
+            {
+                reclaimed.push(Client::Consumer {
+                    pid,
+                    slot: slot as u32,
+                });
+            }
+        }
+        Ok(reclaimed)
+    }
+    #[cfg(test)]
+    mod reaper_tests {
+        use super::*;
+        use std::process;
+
+        #[test]
+        fn orphaned_1() {
+            // We're alive:
+
+            assert!(!is_orphaned(process::id(), None));
+            assert!(!is_orphaned(
+                process::id(),
+                ProcessIdentity::of(process::id())
+            ));
+        }
+        #[test]
+        fn orphaned_2() {
+            // A reused pid is orphaned:
+
+            let mut me = ProcessIdentity::of(process::id()).unwrap();
+            me.start_time += 1;
+            assert!(is_orphaned(process::id(), Some(me)));
+        }
+        #[test]
+        fn orphaned_3() {
+            // pids larger than pid_max can't exist:
+
+            assert!(is_orphaned(0xfffffff0, None));
+        }
+        #[test]
+        fn reap_1() {
+            // Not a ring:
+
+            assert!(reap_ring("/no/such/ring", &|_| None, true).is_err());
+        }
+        #[test]
+        fn reap_2() {
+            // Live producer, dead consumer in slot 3:
+
+            let ring = std::env::temp_dir().join(format!("reaper_test_{}", process::id()));
+            let ring = ring.to_str().unwrap();
+            ringbuffer::RingBufferMap::create(ring, 4096).unwrap();
+            {
+                let mut map = ringbuffer::RingBufferMap::new(ring).unwrap();
+                map.set_producer(process::id()).unwrap();
+                map.set_consumer(3, 0xfffffff0).unwrap();
+            }
+            // Dry run reports but leaves the slot alone:
+
+            let dry = reap_ring(ring, &|_| None, true).unwrap();
+            assert_eq!(vec![Client::Consumer { pid: 0xfffffff0, slot: 3 }], dry);
+            let mut map = ringbuffer::RingBufferMap::new(ring).unwrap();
+            assert_eq!(0xfffffff0, map.consumer(3).unwrap().get_pid());
+
+            // For real frees the slot and leaves us as producer:
+
+            let wet = reap_ring(ring, &|_| None, false).unwrap();
+            assert_eq!(dry, wet);
+            assert_eq!(ringbuffer::UNUSED_ENTRY, map.consumer(3).unwrap().get_pid());
+            assert_eq!(process::id(), map.producer().get_pid());
+
+            ringbuffer::RingBufferMap::delete(ring).unwrap();
+        }
+    }
+}
@@ -0,0 +1,188 @@
+///
+/// The ringfile module creates and removes ring buffer backing files.
+/// nscldaq_ringbuffer does the actual formatting; what we add is
+/// control over the number of consumers and the file permissions.
+///
+/// Ring buffer files start with the RingHeader struct of nscldaq_ringbuffer
+/// which is #[repr(C)]: a 32 byte magic string followed by usize fields
+/// the first of which is the maximum number of consumers.  That layout is
+/// private to nscldaq_ringbuffer so the field is checked against what
+/// RingBufferMap reports before it is written and the result is checked
+/// again after.
+///
+pub mod ringfile {
+    use nscldaq_ringbuffer::ringbuffer;
+    use std::fs;
+    use std::io::{Read, Seek, SeekFrom, Write};
+    use std::os::unix::fs::PermissionsExt;
+
+    /// Number of consumer slots nscldaq_ringbuffer formats a ring with.
+    ///
+    pub const MAX_CONSUMER_SLOTS: usize = 100;
+
+    const MAX_CONSUMER_OFFSET: u64 = 32; // Just past the magic string.
+
+    ///
+    /// Determine if a ring name is acceptable as a file in the ring
+    /// directory.  Names must not be paths, or contain whitespace
+    /// (requests are split on whitespace).
+    ///
+    pub fn valid_ring_name(name: &str) -> bool {
+        !name.is_empty()
+            && name != "."
+            && name != ".."
+            && !name.contains('/')
+            && !name.contains(char::is_whitespace)
+    }
+    ///
+    /// Create a ring buffer file:
+    ///
+    /// *  path - the path to the file, which must not exist.
+    /// *  data_size - bytes in the data area of the ring.
+    /// *  max_consumers - number of consumer slots in 1..=MAX_CONSUMER_SLOTS.
+    /// *  mode - the permissions given the file.
+    ///
+    /// Err payloads are human readable reasons.
+    ///
+    pub fn create_ring(
+        path: &str,
+        data_size: u32,
+        max_consumers: usize,
+        mode: u32,
+    ) -> Result<(), String> {
+        if max_consumers == 0 || max_consumers > MAX_CONSUMER_SLOTS {
+            return Err(format!(
+                "The number of consumers must be between 1 and {}",
+                MAX_CONSUMER_SLOTS
+            ));
+        }
+        if data_size == 0 {
+            return Err(String::from("The ring size must be positive"));
+        }
+        if fs::symlink_metadata(path).is_ok() {
+            return Err(format!("{} already exists", path));
+        }
+        ringbuffer::RingBufferMap::create(path, data_size)?;
+
+        let finish = || -> Result<(), String> {
+            if max_consumers != MAX_CONSUMER_SLOTS {
+                set_max_consumers(path, max_consumers)?;
+            }
+            fs::set_permissions(path, fs::Permissions::from_mode(mode))
+                .map_err(|e| format!("Unable to set permissions of {}: {}", path, e))?;
+            let map = ringbuffer::RingBufferMap::new(path)?;
+            if map.max_consumers() != max_consumers || map.data_bytes() != data_size as usize {
+                return Err(format!(
+                    "{} was not formatted as requested; the nscldaq_ringbuffer header layout may have changed",
+                    path
+                ));
+            }
+            Ok(())
+        };
+        if let Err(e) = finish() {
+            let _ = fs::remove_file(path);
+            return Err(e);
+        }
+        Ok(())
+    }
+    ///
+    /// Remove a ring buffer file.  The file must be a ring buffer.
+    ///
+    pub fn delete_ring(path: &str) -> Result<(), String> {
+        ringbuffer::RingBufferMap::new(path)?;
+        ringbuffer::RingBufferMap::delete(path)
+    }
+    // Overwrite the maximum consumer count in a freshly formatted ring.
+    // Fewer than the formatted number of slots is always safe as the
+    // slots beyond the count just never get used.
+    //
+    // We only write if what's at the offset is the count RingBufferMap
+    // reports; otherwise the header is not laid out the way we think.
+    //
+    fn set_max_consumers(path: &str, max_consumers: usize) -> Result<(), String> {
+        let formatted = ringbuffer::RingBufferMap::new(path)?.max_consumers();
+        let mut file = fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(path)
+            .map_err(|e| e.to_string())?;
+        let mut current = [0u8; std::mem::size_of::<usize>()];
+        file.seek(SeekFrom::Start(MAX_CONSUMER_OFFSET))
+            .and_then(|_| file.read_exact(&mut current))
+            .map_err(|e| format!("Unable to read consumer count of {}: {}", path, e))?;
+        if usize::from_ne_bytes(current) != formatted {
+            return Err(format!(
+                "Unable to set consumer count of {}: unexpected nscldaq_ringbuffer header layout",
+                path
+            ));
+        }
+        file.seek(SeekFrom::Start(MAX_CONSUMER_OFFSET))
+            .and_then(|_| file.write_all(&max_consumers.to_ne_bytes()))
+            .map_err(|e| format!("Unable to set consumer count of {}: {}", path, e))
+    }
+    #[cfg(test)]
+    mod ringfile_tests {
+        use super::*;
+        use std::process;
+
+        fn temp_ring(name: &str) -> String {
+            let path = std::env::temp_dir().join(format!("{}_{}", name, process::id()));
+            String::from(path.to_str().unwrap())
+        }
+        #[test]
+        fn name_1() {
+            assert!(valid_ring_name("fox"));
+            assert!(valid_ring_name("e17001.data"));
+            assert!(!valid_ring_name(""));
+            assert!(!valid_ring_name(".."));
+            assert!(!valid_ring_name("../etc/passwd"));
+            assert!(!valid_ring_name("two words"));
+        }
+        #[test]
+        fn create_1() {
+            let path = temp_ring("create_1");
+            create_ring(&path, 8192, 10, 0o640).unwrap();
+            let map = ringbuffer::RingBufferMap::new(&path).unwrap();
+            assert_eq!(8192, map.data_bytes());
+            assert_eq!(10, map.max_consumers());
+            let mode = fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(0o640, mode & 0o777);
+            delete_ring(&path).unwrap();
+            assert!(fs::metadata(&path).is_err());
+        }
+        #[test]
+        fn create_2() {
+            // Full complement of consumers:
+
+            let path = temp_ring("create_2");
+            create_ring(&path, 1024, MAX_CONSUMER_SLOTS, 0o666).unwrap();
+            let map = ringbuffer::RingBufferMap::new(&path).unwrap();
+            assert_eq!(MAX_CONSUMER_SLOTS, map.max_consumers());
+            delete_ring(&path).unwrap();
+        }
+        #[test]
+        fn create_3() {
+            // Bad parameters and existing files are errors:
+
+            let path = temp_ring("create_3");
+            assert!(create_ring(&path, 1024, 0, 0o666).is_err());
+            assert!(create_ring(&path, 1024, MAX_CONSUMER_SLOTS + 1, 0o666).is_err());
+            assert!(create_ring(&path, 0, 10, 0o666).is_err());
+            assert!(fs::metadata(&path).is_err());
+
+            create_ring(&path, 1024, 10, 0o666).unwrap();
+            assert!(create_ring(&path, 1024, 10, 0o666).is_err());
+            delete_ring(&path).unwrap();
+        }
+        #[test]
+        fn delete_1() {
+            // Only rings get deleted:
+
+            let path = temp_ring("delete_1");
+            fs::write(&path, "not a ring").unwrap();
+            assert!(delete_ring(&path).is_err());
+            assert!(fs::metadata(&path).is_ok());
+            fs::remove_file(&path).unwrap();
+        }
+    }
+}
@@ -2,9 +2,10 @@ pub mod rings {
     use std::collections::HashMap;
     use std::sync::{Arc, Mutex};
     use std::thread;
-    use sysinfo::{Pid, System, Signal};
-    
-    //use log::info;
+    use std::time::{Duration, Instant};
+    use sysinfo::{Pid, ProcessesToUpdate, System, Signal};
+    use crate::rings::process::process::ProcessIdentity;
+    use log::warn;
     ///
     /// This enum provides information about the
     /// way a client is attached to a ring:
@@ -12,11 +13,44 @@ pub mod rings {
     /// *  pid is the process id of the client.
     /// *  slot is the consumer slot for a consumer client.
     ///
-    #[derive(Copy, Clone, Debug, PartialEq)]
+    #[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
     pub enum Client {
         Producer { pid: u32 },
         Consumer { pid: u32, slot: u32 },
     }
+    ///
+    /// What to do to the client processes of a ring whose clients
+    /// are being removed (e.g. on UNREGISTER):
+    ///
+    /// *  Kill - SIGTERM, then SIGKILL if it's still alive after a grace period.
+    /// *  Signal - SIGTERM only.  The process is left to decide what to do.
+    /// *  Detach - Forget about the client without signalling it.
+    ///
+    #[derive(Copy, Clone, Debug, PartialEq)]
+    pub enum KillPolicy {
+        Kill,
+        Signal,
+        Detach,
+    }
+    impl KillPolicy {
+        ///
+        /// Parse a policy name.  The names are case insensitive and
+        /// are KILL, SIGNAL and DETACH (NOKILL is a synonym for DETACH).
+        ///
+        pub fn parse(name: &str) -> Option<KillPolicy> {
+            match name.to_uppercase().as_str() {
+                "KILL" => Some(KillPolicy::Kill),
+                "SIGNAL" => Some(KillPolicy::Signal),
+                "DETACH" | "NOKILL" => Some(KillPolicy::Detach),
+                _ => None,
+            }
+        }
+    }
+    /// The grace period used when removing clients without an
+    /// explicit one.
+    ///
+    pub const DEFAULT_KILL_GRACE: Duration = Duration::from_secs(2);
+
     ///
     /// provides the information we need to know about a
     /// ringmaster client monitor thread.
@@ -24,12 +58,15 @@ pub mod rings {
     /// *   handle -is the join handle for a monitor thread.
     /// *   should_run - is the flag that will be initialized to ```true```
     /// and set to false to request the thread exit.
+    /// *   identity - identifies the client process as of the time it
+    ///     became known to us (None if it was already gone).
     ///
 
     pub struct ClientMonitorInfo {
         handle: Option<thread::JoinHandle<()>>,
         pub should_run: bool,
         pub client_info: Client,
+        pub identity: Option<ProcessIdentity>,
     }
     impl ClientMonitorInfo {
         ///
@@ -42,10 +79,15 @@ pub mod rings {
         /// its own thread handle.
         ///
         pub fn new(client: Client) -> ClientMonitorInfo {
+            let pid = match client {
+                Client::Producer { pid } => pid,
+                Client::Consumer { pid, slot: _slot } => pid,
+            };
             ClientMonitorInfo {
                 handle: None,
                 should_run: true,
                 client_info: client,
+                identity: ProcessIdentity::of(pid),
             }
         }
         ///
@@ -104,19 +146,77 @@ pub mod rings {
         client_monitors: HashMap<u32, Arc<Mutex<ClientMonitorInfo>>>,
     }
     impl RingBufferInfo {
-        
-        fn kill_pid(pid: u32) {
-            
-            let sys = System::new_all();
-            let process = sys.process(Pid::from_u32(pid));
-            if let Some(proc) = process {
-                if let None = proc.kill_with(Signal::Term) {
-                    if let None = proc.kill_with(Signal::Interrupt) {
-                        proc.kill();
+        ///
+        /// Send a signal to a pid.  Returns true if the signal was sent.
+        ///
+        fn send_signal(pid: u32, signal: Signal) -> bool {
+            let mut sys = System::new();
+            let pid = Pid::from_u32(pid);
+            sys.refresh_processes(ProcessesToUpdate::Some(&[pid]), true);
+            match sys.process(pid) {
+                Some(process) => process.kill_with(signal).unwrap_or(false),
+                None => false,
+            }
+        }
+        ///
+        /// Apply a kill policy to the process identified.  If the pid no
+        /// longer belongs to that process (it exited and the pid was reused)
+        /// we must not touch whatever now has it.  Clients whose identity
+        /// we don't know are not signalled either.
+        ///
+        /// With KillPolicy::Kill, the process gets SIGTERM and then,
+        /// if it has not exited within the grace period, SIGKILL.
+        ///
+        /// Returns true if the process was signalled.
+        ///
+        pub(crate) fn terminate_pid(
+            pid: u32,
+            identity: Option<ProcessIdentity>,
+            policy: KillPolicy,
+            grace: Duration,
+        ) -> bool {
+            !Self::terminate_pids(&[(pid, identity)], policy, grace).is_empty()
+        }
+        ///
+        /// Apply a kill policy to several processes at once (see
+        /// terminate_pid).  All of them get SIGTERM first so, with
+        /// KillPolicy::Kill, we wait out a single grace period however
+        /// many there are before sending SIGKILL to the survivors.
+        ///
+        /// Returns the pids that were signalled.
+        ///
+        pub(crate) fn terminate_pids(
+            targets: &[(u32, Option<ProcessIdentity>)],
+            policy: KillPolicy,
+            grace: Duration,
+        ) -> Vec<u32> {
+            if policy == KillPolicy::Detach {
+                return Vec::new();
+            }
+            let mut signalled = Vec::<ProcessIdentity>::new();
+            for (pid, identity) in targets {
+                match identity {
+                    Some(id) if id.pid == *pid && id.is_alive() => {
+                        if Self::send_signal(*pid, Signal::Term) {
+                            signalled.push(*id);
+                        } else {
+                            warn!("Unable to send SIGTERM to {}", pid);
+                        }
                     }
+                    _ => warn!("Not signalling {}: the process that was our client is gone", pid),
                 }
-            } 
-
+            }
+            if policy == KillPolicy::Kill {
+                let started = Instant::now();
+                while signalled.iter().any(|id| id.is_alive()) && started.elapsed() < grace {
+                    thread::sleep(Duration::from_millis(50));
+                }
+                for id in signalled.iter().filter(|id| id.is_alive()) {
+                    warn!("{} survived SIGTERM for {:?}, sending SIGKILL", id.pid, grace);
+                    Self::send_signal(id.pid, Signal::Kill);
+                }
+            }
+            signalled.iter().map(|id| id.pid).collect()
         }
         
         ///
@@ -138,6 +238,34 @@ pub mod rings {
             self.client_monitors.contains_key(&pid)
         }
         ///
+        /// Get the identity recorded for a client pid.  None if
+        /// the pid is not a client or its identity could not be determined.
+        ///
+        pub fn client_identity(&self, pid: u32) -> Option<ProcessIdentity> {
+            self.client_monitors
+                .get(&pid)
+                .and_then(|info| info.lock().unwrap().identity)
+        }
+        ///
+        /// Get what we know about each client: how it's attached and
+        /// its process identity.  The records are in pid order.
+        ///
+        pub fn client_records(&self) -> Vec<(Client, Option<ProcessIdentity>)> {
+            let mut result = self
+                .client_monitors
+                .values()
+                .map(|info| {
+                    let info = info.lock().unwrap();
+                    (info.client_info, info.identity)
+                })
+                .collect::<Vec<(Client, Option<ProcessIdentity>)>>();
+            result.sort_by_key(|(client, _)| match client {
+                Client::Producer { pid } => *pid,
+                Client::Consumer { pid, slot: _slot } => *pid,
+            });
+            result
+        }
+        ///
         /// Get the client information associated with a pid in the ringL
         ///
         pub fn get_client_info(&mut self, pid: &u32) -> Option<&Arc<Mutex<ClientMonitorInfo>>> {
@@ -187,31 +315,55 @@ pub mod rings {
         /// Remove a client from the ring buffer given its
         /// PID.  
         /// *  The monitor's thread is halted.
-        /// *  If possible, the process is killed.
+        /// *  If possible, the process is sent SIGTERM.
         ///
         pub fn remove_client(&mut self, pid: u32) -> &mut RingBufferInfo {
+            self.remove_client_with_policy(pid, KillPolicy::Signal, DEFAULT_KILL_GRACE);
+            self
+        }
+        ///
+        /// Remove a client from the ring buffer given its PID,
+        /// applying a kill policy to the process.  Returns true if the
+        /// process was signalled.
+        ///
+        pub fn remove_client_with_policy(
+            &mut self,
+            pid: u32,
+            policy: KillPolicy,
+            grace: Duration,
+        ) -> bool {
             let info = self.client_monitors.remove(&pid);
             if let Some(mut client) = info {
                 ClientMonitorInfo::stop_monitor(&mut client);
-                Self::kill_pid(pid);
+                let identity = client.lock().unwrap().identity;
+                Self::terminate_pid(pid, identity, policy, grace)
+            } else {
+                false
             }
-            self
         }
-        /// Convenience method to kill all clients.
+        /// Convenience method to remove all clients, sending each of
+        /// them SIGTERM.
         ///
         pub fn remove_all(&mut self) -> &mut RingBufferInfo {
-            let mut pids: Vec<u32> = Vec::new();
-            // Collect the pids:
-            for pid in self.client_monitors.keys() {
-                pids.push(*pid);
-            }
-            
-            for pid in pids {
-                self.remove_client(pid);
-            }
-
+            self.remove_all_with_policy(KillPolicy::Signal, DEFAULT_KILL_GRACE);
             self
         }
+        ///
+        /// Remove all clients applying a kill policy to each of them.
+        /// The grace period of KillPolicy::Kill is waited out once for
+        /// all of them, not once per client.
+        /// The pids that were signalled are returned.
+        ///
+        pub fn remove_all_with_policy(&mut self, policy: KillPolicy, grace: Duration) -> Vec<u32> {
+            let mut targets = Vec::<(u32, Option<ProcessIdentity>)>::new();
+            for (pid, mut client) in self.client_monitors.drain() {
+                ClientMonitorInfo::stop_monitor(&mut client);
+                let identity = client.lock().unwrap().identity;
+                targets.push((pid, identity));
+            }
+            targets.sort_by_key(|(pid, _)| *pid);
+            Self::terminate_pids(&targets, policy, grace)
+        }
     }
     #[cfg(test)]
     // Tests for ClienMonitorInfo:
@@ -249,6 +401,15 @@ pub mod rings {
             assert!(info.should_run);
         }
         #[test]
+        fn new_3() {
+            // Live processes get their identity recorded:
+
+            let me = std::process::id();
+            let info = ClientMonitorInfo::new(Client::Producer { pid: me });
+            assert_eq!(ProcessIdentity::of(me), info.identity);
+            assert!(info.identity.is_some());
+        }
+        #[test]
         fn set_monitor_1() {
             let client = Client::Producer { pid: 1234 };
             let mut info = ClientMonitorInfo::new(client);
@@ -399,12 +560,138 @@ pub mod rings {
             }
         }
         #[test]
+        fn identity_1() {
+            let me = std::process::id();
+            let mut info = RingBufferInfo::new("ringbuffer");
+            info.add_client(&Arc::new(Mutex::new(ClientMonitorInfo::new(
+                Client::Consumer { pid: me, slot: 1 },
+            ))));
+            assert_eq!(ProcessIdentity::of(me), info.client_identity(me));
+            assert!(info.client_identity(me + 1).is_none());
+        }
+        #[test]
+        fn records_1() {
+            let mut info = RingBufferInfo::new("ringbuffer");
+            assert!(info.client_records().is_empty());
+            info.add_client(&Arc::new(Mutex::new(ClientMonitorInfo::new(
+                Client::Consumer { pid: 0xfffffff1, slot: 1 },
+            ))))
+            .add_client(&Arc::new(Mutex::new(ClientMonitorInfo::new(
+                Client::Producer { pid: 0xfffffff0 },
+            ))));
+            assert_eq!(
+                vec![
+                    (Client::Producer { pid: 0xfffffff0 }, None),
+                    (Client::Consumer { pid: 0xfffffff1, slot: 1 }, None)
+                ],
+                info.client_records()
+            );
+        }
+        #[test]
         fn remove_1() {
             // Remove is ok if there's no client with that pid
             // to remove (silently does nothing)
             let mut info = RingBufferInfo::new("ring");
             info.remove_client(1234); // Should not panic.
         }
+        #[test]
+        fn remove_2() {
+            // A pid reused by another process is not killed:
+
+            let mut child = std::process::Command::new("sleep").arg("10").spawn().unwrap();
+            let mut client = ClientMonitorInfo::new(Client::Producer { pid: child.id() });
+            client.identity.as_mut().unwrap().start_time += 1;
+
+            let mut info = RingBufferInfo::new("ring");
+            info.add_client(&Arc::new(Mutex::new(client)));
+            info.remove_client(child.id());
+            assert!(!info.have_pid(child.id()));
+            assert!(child.try_wait().unwrap().is_none());
+
+            child.kill().unwrap();
+            child.wait().unwrap();
+        }
+        #[test]
+        fn remove_3() {
+            // The process we know is killed:
+
+            let mut child = std::process::Command::new("sleep").arg("10").spawn().unwrap();
+            let client = ClientMonitorInfo::new(Client::Producer { pid: child.id() });
+
+            let mut info = RingBufferInfo::new("ring");
+            info.add_client(&Arc::new(Mutex::new(client)));
+            info.remove_client(child.id());
+            assert!(!child.wait().unwrap().success());
+        }
+        #[test]
+        fn policy_1() {
+            assert_eq!(Some(KillPolicy::Kill), KillPolicy::parse("KILL"));
+            assert_eq!(Some(KillPolicy::Signal), KillPolicy::parse("signal"));
+            assert_eq!(Some(KillPolicy::Detach), KillPolicy::parse("NOKILL"));
+            assert_eq!(Some(KillPolicy::Detach), KillPolicy::parse("detach"));
+            assert!(KillPolicy::parse("maim").is_none());
+        }
+        #[test]
+        fn policy_2() {
+            // Detach leaves the process alone:
+
+            let mut child = std::process::Command::new("sleep").arg("10").spawn().unwrap();
+            let mut info = RingBufferInfo::new("ring");
+            info.add_client(&Arc::new(Mutex::new(ClientMonitorInfo::new(
+                Client::Producer { pid: child.id() },
+            ))));
+            let signalled = info.remove_all_with_policy(KillPolicy::Detach, DEFAULT_KILL_GRACE);
+            assert!(signalled.is_empty());
+            assert!(!info.have_pid(child.id()));
+            assert!(child.try_wait().unwrap().is_none());
+
+            child.kill().unwrap();
+            child.wait().unwrap();
+        }
+        #[test]
+        fn policy_3() {
+            // Kill escalates to SIGKILL for processes that ignore SIGTERM:
+
+            let mut child = std::process::Command::new("sh")
+                .args(["-c", "trap '' TERM; sleep 10"])
+                .spawn()
+                .unwrap();
+            thread::sleep(Duration::from_millis(200)); // let the trap get set.
+            let mut info = RingBufferInfo::new("ring");
+            info.add_client(&Arc::new(Mutex::new(ClientMonitorInfo::new(
+                Client::Consumer { pid: child.id(), slot: 0 },
+            ))));
+            let signalled =
+                info.remove_all_with_policy(KillPolicy::Kill, Duration::from_millis(200));
+            assert_eq!(vec![child.id()], signalled);
+            assert!(!child.wait().unwrap().success());
+        }
+        #[test]
+        fn policy_4() {
+            // The grace period is waited out once, not once per client:
+
+            let mut children = Vec::new();
+            let mut info = RingBufferInfo::new("ring");
+            for slot in 0..3 {
+                let child = std::process::Command::new("sh")
+                    .args(["-c", "trap '' TERM; sleep 10"])
+                    .spawn()
+                    .unwrap();
+                info.add_client(&Arc::new(Mutex::new(ClientMonitorInfo::new(
+                    Client::Consumer { pid: child.id(), slot },
+                ))));
+                children.push(child);
+            }
+            thread::sleep(Duration::from_millis(200)); // let the traps get set.
+            let started = Instant::now();
+            let signalled =
+                info.remove_all_with_policy(KillPolicy::Kill, Duration::from_millis(500));
+            assert!(started.elapsed() < Duration::from_millis(1000));
+            assert_eq!(3, signalled.len());
+            for mut child in children {
+                assert!(!child.wait().unwrap().success());
+            }
+        }
         
     }
 }
@@ -0,0 +1,438 @@
+///
+/// The stats module computes data rates from periodic samples of the
+/// positions of a ring's producer and consumers (see the positions
+/// module):
+///
+/// *  The rate at which the producer puts data into the ring.
+/// *  The rate at which each consumer gets data from the ring.
+/// *  How long each client has gone without moving.
+///
+/// A short history of rates is kept for each client.  Rates are computed
+/// from how far a client moved between samples, so a client that laps
+/// the ring more than once between samples is under-reported; sample
+/// faster than the ring can be filled.
+///
+pub mod stats {
+    use crate::rings::positions::positions::{ClientPosition, RingPositions};
+    use crate::tcllist::TclList;
+    use serde_json::{json, Value};
+    use std::collections::{HashMap, VecDeque};
+    use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+    ///
+    /// One rate measurement: when it was made (seconds since the epoch)
+    /// and the bytes per second moved since the previous one.
+    ///
+    #[derive(Copy, Clone, Debug, PartialEq)]
+    pub struct RateSample {
+        pub time: f64,
+        pub rate: f64,
+    }
+    ///
+    /// What we know about one client.  The client is identified by its
+    /// slot and pid; if either changes, it's a new client.
+    ///
+    #[derive(Clone, Debug)]
+    struct ClientHistory {
+        slot: usize,
+        pid: u32,
+        offset: usize,
+        last_progress: Instant,
+        total: u64,
+        history: VecDeque<RateSample>,
+    }
+    impl ClientHistory {
+        fn new(client: &ClientPosition, now: Instant) -> ClientHistory {
+            ClientHistory {
+                slot: client.slot,
+                pid: client.pid,
+                offset: client.offset,
+                last_progress: now,
+                total: 0,
+                history: VecDeque::new(),
+            }
+        }
+        // Add a new position, elapsed since the last one.
+
+        fn record(
+            &mut self,
+            positions: &RingPositions,
+            client: &ClientPosition,
+            now: Instant,
+            elapsed: Duration,
+            wall: f64,
+            keep: usize,
+        ) {
+            let moved = positions.distance(self.offset, client.offset);
+            if moved > 0 {
+                self.last_progress = now;
+            }
+            self.offset = client.offset;
+            self.total += moved as u64;
+            let seconds = elapsed.as_secs_f64();
+            let rate = if seconds > 0.0 { moved as f64 / seconds } else { 0.0 };
+            self.history.push_back(RateSample { time: wall, rate });
+            while self.history.len() > keep {
+                self.history.pop_front();
+            }
+        }
+    }
+    ///
+    /// The statistics for one client of a ring:
+    ///
+    /// *  slot, pid - the client (slot is 0 for producers).
+    /// *  rate - bytes/second as of the latest sample (0 until there are two).
+    /// *  average - average bytes/second over the history.
+    /// *  idle - seconds since the client last moved (or we started
+    ///    watching it).
+    /// *  total - bytes moved since we started watching it.
+    /// *  backlog - bytes a consumer has yet to get (0 for producers).
+    /// *  history - the recent rates, oldest first.
+    ///
+    #[derive(Clone, Debug, PartialEq)]
+    pub struct ClientStats {
+        pub slot: usize,
+        pub pid: u32,
+        pub rate: f64,
+        pub average: f64,
+        pub idle: f64,
+        pub total: u64,
+        pub backlog: usize,
+        pub history: Vec<RateSample>,
+    }
+    impl ClientStats {
+        fn from_history(history: &ClientHistory, backlog: usize, now: Instant) -> ClientStats {
+            let rates = history.history.iter().map(|s| s.rate).collect::<Vec<f64>>();
+            ClientStats {
+                slot: history.slot,
+                pid: history.pid,
+                rate: rates.last().copied().unwrap_or(0.0),
+                average: if rates.is_empty() {
+                    0.0
+                } else {
+                    rates.iter().sum::<f64>() / rates.len() as f64
+                },
+                idle: now.duration_since(history.last_progress).as_secs_f64(),
+                total: history.total,
+                backlog,
+                history: history.history.iter().copied().collect(),
+            }
+        }
+        ///
+        /// Render as a Tcl list of key value pairs.  The history is a
+        /// list of {time rate} pairs.
+        ///
+        pub fn to_tcl(&self) -> TclList {
+            let mut history = TclList::new();
+            for sample in &self.history {
+                let mut pair = TclList::new();
+                pair.add_element(&format!("{:.3}", sample.time))
+                    .add_element(&format!("{:.1}", sample.rate));
+                history.add_sublist(Box::new(pair));
+            }
+            let mut result = TclList::new();
+            result
+                .add_element("slot")
+                .add_element(&self.slot.to_string())
+                .add_element("pid")
+                .add_element(&self.pid.to_string())
+                .add_element("rate")
+                .add_element(&format!("{:.1}", self.rate))
+                .add_element("average")
+                .add_element(&format!("{:.1}", self.average))
+                .add_element("idle")
+                .add_element(&format!("{:.1}", self.idle))
+                .add_element("total")
+                .add_element(&self.total.to_string())
+                .add_element("backlog")
+                .add_element(&self.backlog.to_string())
+                .add_element("history")
+                .add_sublist(Box::new(history));
+            result
+        }
+        ///
+        /// Render as a JSON object with the keys of to_tcl.  The history
+        /// is an array of {time, rate} objects.
+        ///
+        pub fn to_json(&self) -> Value {
+            let history = self
+                .history
+                .iter()
+                .map(|s| json!({ "time": s.time, "rate": s.rate }))
+                .collect::<Vec<Value>>();
+            json!({
+                "slot": self.slot,
+                "pid": self.pid,
+                "rate": self.rate,
+                "average": self.average,
+                "idle": self.idle,
+                "total": self.total,
+                "backlog": self.backlog,
+                "history": history,
+            })
+        }
+    }
+    ///
+    /// The statistics of a ring.  The producer is None if the ring has
+    /// none.
+    ///
+    #[derive(Clone, Debug, PartialEq)]
+    pub struct RingStats {
+        pub ring: String,
+        pub producer: Option<ClientStats>,
+        pub consumers: Vec<ClientStats>,
+    }
+    impl RingStats {
+        ///
+        /// Render as a Tcl list of key value pairs: ring, producer
+        /// (empty if none) and consumers.
+        ///
+        pub fn to_tcl(&self) -> TclList {
+            let mut consumers = TclList::new();
+            for consumer in &self.consumers {
+                consumers.add_sublist(Box::new(consumer.to_tcl()));
+            }
+            let mut result = TclList::new();
+            result
+                .add_element("ring")
+                .add_quoted_element(&self.ring)
+                .add_element("producer")
+                .add_sublist(Box::new(
+                    self.producer.as_ref().map_or(TclList::new(), |p| p.to_tcl()),
+                ))
+                .add_element("consumers")
+                .add_sublist(Box::new(consumers));
+            result
+        }
+        ///
+        /// Render as a JSON object with the keys of to_tcl.  The producer
+        /// is null if there is none.
+        ///
+        pub fn to_json(&self) -> Value {
+            json!({
+                "ring": self.ring,
+                "producer": self.producer.as_ref().map(|p| p.to_json()),
+                "consumers": self.consumers.iter().map(|c| c.to_json()).collect::<Vec<Value>>(),
+            })
+        }
+    }
+    // The histories of one ring.
+
+    struct RingHistory {
+        positions: RingPositions,
+        sampled: Instant,
+        producer: Option<ClientHistory>,
+        consumers: Vec<ClientHistory>,
+    }
+
+    ///
+    /// Collects samples of ring positions and turns them into statistics.
+    ///
+    pub struct StatsCollector {
+        keep: usize,
+        rings: HashMap<String, RingHistory>,
+    }
+    impl StatsCollector {
+        ///
+        /// keep is the number of rate samples to keep for each client.
+        ///
+        pub fn new(keep: usize) -> StatsCollector {
+            StatsCollector {
+                keep: keep.max(1),
+                rings: HashMap::new(),
+            }
+        }
+        ///
+        /// Record the positions of a ring's clients sampled at now.
+        ///
+        pub fn record(&mut self, ring: &str, positions: RingPositions, now: Instant) {
+            let wall = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs_f64())
+                .unwrap_or(0.0);
+            let keep = self.keep;
+            match self.rings.get_mut(ring) {
+                None => {
+                    let history = RingHistory {
+                        producer: positions.producer.as_ref().map(|p| ClientHistory::new(p, now)),
+                        consumers: positions
+                            .consumers
+                            .iter()
+                            .map(|c| ClientHistory::new(c, now))
+                            .collect(),
+                        positions,
+                        sampled: now,
+                    };
+                    self.rings.insert(String::from(ring), history);
+                }
+                Some(history) => {
+                    let elapsed = now.duration_since(history.sampled);
+                    history.producer = positions.producer.as_ref().map(|p| {
+                        match history.producer.take() {
+                            Some(mut h) if h.pid == p.pid => {
+                                h.record(&positions, p, now, elapsed, wall, keep);
+                                h
+                            }
+                            _ => ClientHistory::new(p, now),
+                        }
+                    });
+                    let mut old = std::mem::take(&mut history.consumers);
+                    history.consumers = positions
+                        .consumers
+                        .iter()
+                        .map(|c| {
+                            match old.iter().position(|h| h.slot == c.slot && h.pid == c.pid) {
+                                Some(i) => {
+                                    let mut h = old.swap_remove(i);
+                                    h.record(&positions, c, now, elapsed, wall, keep);
+                                    h
+                                }
+                                None => ClientHistory::new(c, now),
+                            }
+                        })
+                        .collect();
+                    history.positions = positions;
+                    history.sampled = now;
+                }
+            }
+        }
+        ///
+        /// Forget the rings other than the ones listed.
+        ///
+        pub fn retain_rings(&mut self, rings: &[String]) {
+            self.rings.retain(|name, _| rings.contains(name));
+        }
+        ///
+        /// Get the statistics of a ring as of now.  None if the ring
+        /// has not been sampled.
+        ///
+        pub fn stats(&self, ring: &str, now: Instant) -> Option<RingStats> {
+            let history = self.rings.get(ring)?;
+            let positions = &history.positions;
+            Some(RingStats {
+                ring: String::from(ring),
+                producer: history
+                    .producer
+                    .as_ref()
+                    .map(|p| ClientStats::from_history(p, 0, now)),
+                consumers: history
+                    .consumers
+                    .iter()
+                    .map(|c| {
+                        let backlog = positions
+                            .consumers
+                            .iter()
+                            .find(|p| p.slot == c.slot && p.pid == c.pid)
+                            .map_or(0, |p| positions.backlog(p));
+                        ClientStats::from_history(c, backlog, now)
+                    })
+                    .collect(),
+            })
+        }
+    }
+    #[cfg(test)]
+    mod stats_tests {
+        use super::*;
+
+        // A 1000 byte ring whose data starts at 100.
+
+        fn positions(producer: Option<usize>, consumers: Vec<(usize, u32, usize)>) -> RingPositions {
+            RingPositions {
+                max_consumers: 100,
+                data_bytes: 1000,
+                data_offset: 100,
+                top_offset: 1099,
+                put_offset: producer.unwrap_or(100),
+                producer: producer.map(|offset| ClientPosition {
+                    slot: 0,
+                    pid: 1,
+                    offset,
+                }),
+                consumers: consumers
+                    .into_iter()
+                    .map(|(slot, pid, offset)| ClientPosition { slot, pid, offset })
+                    .collect(),
+            }
+        }
+        #[test]
+        fn record_1() {
+            // First sample has no rates:
+
+            let mut collector = StatsCollector::new(10);
+            let now = Instant::now();
+            assert!(collector.stats("fox", now).is_none());
+            collector.record("fox", positions(Some(100), vec![(2, 10, 100)]), now);
+            let stats = collector.stats("fox", now).unwrap();
+            let producer = stats.producer.unwrap();
+            assert_eq!(0.0, producer.rate);
+            assert!(producer.history.is_empty());
+            assert_eq!(1, stats.consumers.len());
+        }
+        #[test]
+        fn record_2() {
+            // Producer puts 500 bytes/s, the consumer gets 200 bytes/s:
+
+            let mut collector = StatsCollector::new(10);
+            let start = Instant::now();
+            collector.record("fox", positions(Some(100), vec![(2, 10, 100)]), start);
+            let later = start + Duration::from_secs(1);
+            collector.record("fox", positions(Some(600), vec![(2, 10, 300)]), later);
+            let stats = collector.stats("fox", later).unwrap();
+            let producer = stats.producer.unwrap();
+            assert_eq!(500.0, producer.rate);
+            assert_eq!(500, producer.total);
+            assert_eq!(0.0, producer.idle);
+            let consumer = &stats.consumers[0];
+            assert_eq!(200.0, consumer.rate);
+            assert_eq!(300, consumer.backlog);
+
+            // Wrapping around the ring, the producer moves 600 bytes:
+
+            let later2 = later + Duration::from_secs(2);
+            collector.record("fox", positions(Some(200), vec![(2, 10, 300)]), later2);
+            let stats = collector.stats("fox", later2 + Duration::from_secs(1)).unwrap();
+            let producer = stats.producer.unwrap();
+            assert_eq!(300.0, producer.rate);
+            assert_eq!(400.0, producer.average);
+            assert_eq!(2, producer.history.len());
+            let consumer = &stats.consumers[0];
+            assert_eq!(0.0, consumer.rate);
+            assert_eq!(3.0, consumer.idle);
+        }
+        #[test]
+        fn record_3() {
+            // New pid in a slot starts over; history is bounded:
+
+            let mut collector = StatsCollector::new(2);
+            let start = Instant::now();
+            for i in 0..5 {
+                let t = start + Duration::from_secs(i);
+                collector.record("fox", positions(Some(100 + i as usize), vec![(2, 10, 100)]), t);
+            }
+            let t = start + Duration::from_secs(5);
+            assert_eq!(2, collector.stats("fox", t).unwrap().producer.unwrap().history.len());
+
+            collector.record("fox", positions(Some(110), vec![(2, 11, 100)]), t);
+            let stats = collector.stats("fox", t).unwrap();
+            assert_eq!(11, stats.consumers[0].pid);
+            assert!(stats.consumers[0].history.is_empty());
+
+            collector.retain_rings(&[]);
+            assert!(collector.stats("fox", t).is_none());
+        }
+        #[test]
+        fn render_1() {
+            let mut collector = StatsCollector::new(10);
+            let start = Instant::now();
+            collector.record("fox", positions(None, vec![(2, 10, 100)]), start);
+            let stats = collector.stats("fox", start).unwrap();
+            assert_eq!(
+                "{ring fox producer {} consumers {{slot 2 pid 10 rate 0.0 average 0.0 idle 0.0 total 0 backlog 0 history {} } } }",
+                stats.to_tcl().to_string()
+            );
+            let json = stats.to_json();
+            assert!(json["producer"].is_null());
+            assert_eq!(10, json["consumers"][0]["pid"]);
+        }
+    }
+}
@@ -0,0 +1,350 @@
+///
+/// The status module assembles a detailed report about a single ring:
+/// what's in its backing file, who holds each of its slots and what the
+/// ringmaster knows about its clients.  The report can be rendered as a
+/// Tcl list (as LIST does) or as JSON.
+///
+pub mod status {
+    use crate::rings::positions::positions::RingPositions;
+    use crate::rings::process::process::{describe_process, ProcessDescription, ProcessIdentity};
+    use crate::rings::rings::rings::Client;
+    use crate::tcllist::TclList;
+    use serde_json::{json, Value};
+    use std::fs;
+    use std::time::UNIX_EPOCH;
+
+    ///
+    /// The producer of a ring and the process that it is, if
+    /// that process still exists.
+    ///
+    pub struct ProducerStatus {
+        pub pid: u32,
+        pub process: Option<ProcessDescription>,
+    }
+    ///
+    /// A used consumer slot:
+    ///
+    /// *  slot - the slot index.
+    /// *  pid, process - who holds the slot.
+    /// *  backlog - bytes the consumer has yet to get.
+    /// *  percent_full - backlog as a percentage of the ring data size.
+    ///
+    pub struct ConsumerStatus {
+        pub slot: usize,
+        pub pid: u32,
+        pub process: Option<ProcessDescription>,
+        pub backlog: usize,
+        pub percent_full: f64,
+    }
+    ///
+    /// Everything we can say about a ring.  mtime is in seconds
+    /// since the epoch.  clients are the ringmaster's own records of
+    /// the ring's clients.
+    ///
+    pub struct RingStatusReport {
+        pub name: String,
+        pub file: String,
+        pub file_size: u64,
+        pub mtime: u64,
+        pub data_bytes: usize,
+        pub max_consumers: usize,
+        pub free_space: usize,
+        pub producer: Option<ProducerStatus>,
+        pub consumers: Vec<ConsumerStatus>,
+        pub clients: Vec<(Client, Option<ProcessIdentity>)>,
+    }
+    impl RingStatusReport {
+        ///
+        /// Build the report for the ring name whose backing file is file.
+        /// Err is returned if the file is not (any longer) a ring buffer.
+        ///
+        pub fn collect(
+            name: &str,
+            file: &str,
+            clients: Vec<(Client, Option<ProcessIdentity>)>,
+        ) -> Result<RingStatusReport, String> {
+            // Slots, pids and backlogs all come from one snapshot of
+            // the client positions so they can't be mismatched by clients
+            // coming and going while we look.
+
+            let positions = RingPositions::read(file)?;
+            let metadata = fs::metadata(file).map_err(|e| e.to_string())?;
+            let mtime = metadata
+                .modified()
+                .ok()
+                .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+                .map(|d| d.as_secs())
+                .unwrap_or(0);
+
+            let usage = positions.usage();
+            let data_bytes = positions.data_bytes;
+            let producer = positions.producer.map(|p| ProducerStatus {
+                pid: p.pid,
+                process: describe_process(p.pid),
+            });
+            let consumers = positions
+                .consumers
+                .iter()
+                .zip(usage.consumer_usage.iter())
+                .map(|(c, u)| ConsumerStatus {
+                    slot: c.slot,
+                    pid: c.pid,
+                    process: describe_process(c.pid),
+                    backlog: u.available,
+                    percent_full: percent(u.available, data_bytes),
+                })
+                .collect::<Vec<ConsumerStatus>>();
+            Ok(RingStatusReport {
+                name: String::from(name),
+                file: String::from(file),
+                file_size: metadata.len(),
+                mtime,
+                data_bytes,
+                max_consumers: positions.max_consumers,
+                free_space: usage.free_space,
+                producer,
+                consumers,
+                clients,
+            })
+        }
+        ///
+        /// Render the report as a Tcl list of key value pairs (usable
+        /// as a Tcl dict).
+        ///
+        pub fn to_tcl(&self) -> TclList {
+            let mut result = TclList::new();
+            result
+                .add_element("name")
+                .add_quoted_element(&self.name)
+                .add_element("file")
+                .add_quoted_element(&self.file)
+                .add_element("file_size")
+                .add_element(&self.file_size.to_string())
+                .add_element("mtime")
+                .add_element(&self.mtime.to_string())
+                .add_element("data_bytes")
+                .add_element(&self.data_bytes.to_string())
+                .add_element("max_consumers")
+                .add_element(&self.max_consumers.to_string())
+                .add_element("free_space")
+                .add_element(&self.free_space.to_string());
+
+            let mut producer = TclList::new();
+            if let Some(p) = &self.producer {
+                producer.add_element("pid").add_element(&p.pid.to_string());
+                add_tcl_process(&mut producer, &p.process);
+            }
+            result.add_element("producer").add_sublist(Box::new(producer));
+
+            let mut consumers = TclList::new();
+            for c in &self.consumers {
+                let mut consumer = TclList::new();
+                consumer
+                    .add_element("slot")
+                    .add_element(&c.slot.to_string())
+                    .add_element("pid")
+                    .add_element(&c.pid.to_string());
+                add_tcl_process(&mut consumer, &c.process);
+                consumer
+                    .add_element("backlog")
+                    .add_element(&c.backlog.to_string())
+                    .add_element("percent_full")
+                    .add_element(&format!("{:.1}", c.percent_full));
+                consumers.add_sublist(Box::new(consumer));
+            }
+            result.add_element("consumers").add_sublist(Box::new(consumers));
+
+            let mut clients = TclList::new();
+            for (client, identity) in &self.clients {
+                let mut record = TclList::new();
+                match client {
+                    Client::Producer { pid } => {
+                        record
+                            .add_element("type")
+                            .add_element("producer")
+                            .add_element("pid")
+                            .add_element(&pid.to_string());
+                    }
+                    Client::Consumer { pid, slot } => {
+                        record
+                            .add_element("type")
+                            .add_element("consumer")
+                            .add_element("slot")
+                            .add_element(&slot.to_string())
+                            .add_element("pid")
+                            .add_element(&pid.to_string());
+                    }
+                }
+                record.add_element("start_time");
+                match identity {
+                    Some(id) => record.add_element(&id.start_time.to_string()),
+                    None => record.add_element("-1"),
+                };
+                clients.add_sublist(Box::new(record));
+            }
+            result.add_element("clients").add_sublist(Box::new(clients));
+            result
+        }
+        ///
+        /// Render the report as a JSON object with the same keys as
+        /// the Tcl form.  Unknown values are null.
+        ///
+        pub fn to_json(&self) -> Value {
+            let producer = self.producer.as_ref().map(|p| {
+                let mut producer = json!({ "pid": p.pid });
+                add_json_process(&mut producer, &p.process);
+                producer
+            });
+            let consumers = self
+                .consumers
+                .iter()
+                .map(|c| {
+                    let mut consumer = json!({
+                        "slot": c.slot,
+                        "pid": c.pid,
+                        "backlog": c.backlog,
+                        "percent_full": c.percent_full,
+                    });
+                    add_json_process(&mut consumer, &c.process);
+                    consumer
+                })
+                .collect::<Vec<Value>>();
+            let clients = self
+                .clients
+                .iter()
+                .map(|(client, identity)| {
+                    let start_time = identity.map(|id| id.start_time);
+                    match client {
+                        Client::Producer { pid } => json!({
+                            "type": "producer", "pid": pid, "start_time": start_time
+                        }),
+                        Client::Consumer { pid, slot } => json!({
+                            "type": "consumer", "slot": slot, "pid": pid, "start_time": start_time
+                        }),
+                    }
+                })
+                .collect::<Vec<Value>>();
+            json!({
+                "name": self.name,
+                "file": self.file,
+                "file_size": self.file_size,
+                "mtime": self.mtime,
+                "data_bytes": self.data_bytes,
+                "max_consumers": self.max_consumers,
+                "free_space": self.free_space,
+                "producer": producer,
+                "consumers": consumers,
+                "clients": clients,
+            })
+        }
+    }
+    ///
+    /// Compute part as a percentage of whole (0 if whole is 0).
+    ///
+    pub fn percent(part: usize, whole: usize) -> f64 {
+        if whole == 0 {
+            0.0
+        } else {
+            100.0 * (part as f64) / (whole as f64)
+        }
+    }
+    // Add the name, user and command line of a process to a Tcl key value list.
+
+    fn add_tcl_process(list: &mut TclList, process: &Option<ProcessDescription>) {
+        let mut command = TclList::new();
+        let (name, user) = match process {
+            Some(p) => {
+                for word in &p.command {
+                    command.add_quoted_element(word);
+                }
+                (p.name.as_str(), p.user.as_deref().unwrap_or(""))
+            }
+            None => ("", ""),
+        };
+        list.add_element("name")
+            .add_quoted_element(name)
+            .add_element("user")
+            .add_quoted_element(user)
+            .add_element("command")
+            .add_sublist(Box::new(command));
+    }
+    // Add the name, user and command line of a process to a JSON object.
+
+    fn add_json_process(object: &mut Value, process: &Option<ProcessDescription>) {
+        object["name"] = json!(process.as_ref().map(|p| p.name.clone()));
+        object["user"] = json!(process.as_ref().and_then(|p| p.user.clone()));
+        object["command"] = json!(process.as_ref().map(|p| p.command.clone()));
+    }
+    #[cfg(test)]
+    mod status_tests {
+        use super::*;
+        use nscldaq_ringbuffer::ringbuffer;
+        use crate::rings::ringfile::ringfile;
+        use std::process;
+
+        fn make_ring(name: &str) -> String {
+            let path = std::env::temp_dir().join(format!("{}_{}", name, process::id()));
+            let path = String::from(path.to_str().unwrap());
+            ringfile::create_ring(&path, 1000, 4, 0o666).unwrap();
+            path
+        }
+        #[test]
+        fn percent_1() {
+            assert_eq!(0.0, percent(10, 0));
+            assert_eq!(25.0, percent(250, 1000));
+        }
+        #[test]
+        fn collect_1() {
+            // Empty ring:
+
+            let path = make_ring("status_1");
+            let report = RingStatusReport::collect("status_1", &path, vec![]).unwrap();
+            assert_eq!(1000, report.data_bytes);
+            assert_eq!(4, report.max_consumers);
+            assert_eq!(fs::metadata(&path).unwrap().len(), report.file_size);
+            assert!(report.producer.is_none());
+            assert!(report.consumers.is_empty());
+            ringfile::delete_ring(&path).unwrap();
+        }
+        #[test]
+        fn collect_2() {
+            // We produce and consume on slot 2:
+
+            let me = process::id();
+            let path = make_ring("status_2");
+            {
+                let mut map = ringbuffer::RingBufferMap::new(&path).unwrap();
+                map.set_producer(me).unwrap();
+                map.set_consumer(2, me).unwrap();
+                map.produce(&[0u8; 100]).unwrap();
+            }
+            let clients = vec![(Client::Producer { pid: me }, ProcessIdentity::of(me))];
+            let report = RingStatusReport::collect("status_2", &path, clients).unwrap();
+            assert_eq!(me, report.producer.as_ref().unwrap().pid);
+            assert!(report.producer.as_ref().unwrap().process.is_some());
+            assert_eq!(1, report.consumers.len());
+            assert_eq!(2, report.consumers[0].slot);
+            assert_eq!(100, report.consumers[0].backlog);
+            assert_eq!(10.0, report.consumers[0].percent_full);
+
+            let json = report.to_json();
+            assert_eq!(me, json["producer"]["pid"].as_u64().unwrap() as u32);
+            assert_eq!(2, json["consumers"][0]["slot"].as_u64().unwrap());
+            assert_eq!("producer", json["clients"][0]["type"]);
+            assert!(json["clients"][0]["start_time"].is_u64());
+
+            let tcl = report.to_tcl().to_string();
+            assert!(tcl.contains("max_consumers 4 "));
+            assert!(tcl.contains("backlog 100 percent_full 10.0 "));
+            assert!(tcl.contains(&format!("{{type producer pid {} start_time ", me)));
+
+            ringfile::delete_ring(&path).unwrap();
+        }
+        #[test]
+        fn collect_3() {
+            // Not a ring:
+
+            assert!(RingStatusReport::collect("x", "/no/such/ring", vec![]).is_err());
+        }
+    }
+}
@@ -0,0 +1,15 @@
+//!
+//! The systemd module lets the ringmaster cooperate with systemd when it
+//! is run as a service:
+//!
+//! *   Notifier sends sd_notify messages (READY=1, STATUS=..., WATCHDOG=1)
+//!     to the datagram socket systemd names in NOTIFY_SOCKET.
+//! *   watchdog_interval says how often WATCHDOG=1 must be sent if the
+//!     service has WatchdogSec set.
+//! *   listeners takes the listening sockets passed by socket activation
+//!     (LISTEN_FDS and LISTEN_PID).
+//!
+//! Outside of systemd none of these variables are set and there is nothing
+//! to do.
+pub mod systemd;
+pub use self::systemd::*;
@@ -0,0 +1,223 @@
+use std::env;
+use std::io;
+use std::net::TcpListener;
+use std::os::fd::{FromRawFd, OwnedFd, RawFd};
+use std::os::linux::net::SocketAddrExt;
+use std::os::unix::net::{SocketAddr, UnixDatagram};
+use std::time::Duration;
+
+///
+/// Sends state changes to systemd (or anything else listening on the
+/// notification socket).
+///
+pub struct Notifier {
+    socket: UnixDatagram,
+    address: SocketAddr,
+}
+impl Notifier {
+    ///
+    /// Make a notifier for the socket in NOTIFY_SOCKET.  None if it's not
+    /// set (we're not run by systemd) or the socket address is bad.
+    /// NOTIFY_SOCKET is removed from the environment so that processes
+    /// we start don't notify on our behalf.
+    ///
+    pub fn from_environment() -> Option<Notifier> {
+        let path = env::var("NOTIFY_SOCKET").ok()?;
+        env::remove_var("NOTIFY_SOCKET");
+        Notifier::new(&path).ok()
+    }
+    ///
+    /// Make a notifier for a socket path.  A leading @ means an abstract
+    /// socket name as in NOTIFY_SOCKET.
+    ///
+    pub fn new(path: &str) -> io::Result<Notifier> {
+        let address = match path.strip_prefix('@') {
+            Some(name) => SocketAddr::from_abstract_name(name.as_bytes())?,
+            None => SocketAddr::from_pathname(path)?,
+        };
+        Ok(Notifier {
+            socket: UnixDatagram::unbound()?,
+            address,
+        })
+    }
+    ///
+    /// Send newline separated VARIABLE=value assignments.
+    ///
+    pub fn notify(&self, state: &str) -> io::Result<()> {
+        self.socket.send_to_addr(state.as_bytes(), &self.address)?;
+        Ok(())
+    }
+    ///
+    /// Say we're ready to serve, along with a status line.
+    ///
+    pub fn ready(&self, status: &str) -> io::Result<()> {
+        self.notify(&format!("READY=1\nSTATUS={}", status))
+    }
+    ///
+    /// Update the status line systemctl status shows.
+    ///
+    pub fn status(&self, status: &str) -> io::Result<()> {
+        self.notify(&format!("STATUS={}", status))
+    }
+    ///
+    /// Tell the watchdog we're alive.
+    ///
+    pub fn watchdog(&self) -> io::Result<()> {
+        self.notify("WATCHDOG=1")
+    }
+}
+///
+/// How often to send WATCHDOG=1 according to the environment: half the
+/// WATCHDOG_USEC timeout.  None if there's no watchdog or it's meant for
+/// another process (WATCHDOG_PID).
+///
+pub fn watchdog_interval() -> Option<Duration> {
+    watchdog_interval_from(
+        env::var("WATCHDOG_USEC").ok().as_deref(),
+        env::var("WATCHDOG_PID").ok().as_deref(),
+        std::process::id(),
+    )
+}
+///
+/// watchdog_interval given the values of WATCHDOG_USEC and WATCHDOG_PID
+/// and our pid.
+///
+pub fn watchdog_interval_from(usec: Option<&str>, pid: Option<&str>, me: u32) -> Option<Duration> {
+    if let Some(pid) = pid {
+        if pid.parse::<u32>().ok()? != me {
+            return None;
+        }
+    }
+    match usec?.parse::<u64>().ok()? {
+        0 => None,
+        usec => Some(Duration::from_micros(usec / 2)),
+    }
+}
+///
+/// The first file descriptor systemd passes for socket activation.
+///
+pub const LISTEN_FDS_START: RawFd = 3;
+
+///
+/// Take the listening sockets systemd passed us (socket activation).
+/// Empty if there are none, e.g. if we were not socket activated.
+/// LISTEN_FDS, LISTEN_PID and LISTEN_FDNAMES are removed from the
+/// environment so processes we start don't think they were activated.
+/// Err if a passed descriptor is not a listening TCP socket.
+///
+pub fn listeners() -> Result<Vec<TcpListener>, String> {
+    let count = listen_fds_from(
+        env::var("LISTEN_FDS").ok().as_deref(),
+        env::var("LISTEN_PID").ok().as_deref(),
+        std::process::id(),
+    );
+    env::remove_var("LISTEN_FDS");
+    env::remove_var("LISTEN_PID");
+    env::remove_var("LISTEN_FDNAMES");
+    (LISTEN_FDS_START..LISTEN_FDS_START + count as RawFd)
+        .map(|fd| unsafe { listener_from_fd(fd) })
+        .collect()
+}
+///
+/// The number of sockets passed given LISTEN_FDS, LISTEN_PID and our
+/// pid.  0 unless both are set and LISTEN_PID is us.
+///
+pub fn listen_fds_from(fds: Option<&str>, pid: Option<&str>, me: u32) -> usize {
+    match (fds, pid.and_then(|p| p.parse::<u32>().ok())) {
+        (Some(fds), Some(pid)) if pid == me => fds.parse::<usize>().unwrap_or(0),
+        _ => 0,
+    }
+}
+///
+/// Turn a file descriptor into a TcpListener.  Err if it isn't a TCP
+/// socket.  The result is close on exec (unlike what systemd passes) so
+/// that processes we start don't hold our listener open.
+///
+/// # Safety
+///
+/// fd must be open and owned by nobody else; the result owns it.
+///
+pub unsafe fn listener_from_fd(fd: RawFd) -> Result<TcpListener, String> {
+    let listener = TcpListener::from(OwnedFd::from_raw_fd(fd));
+    let address = listener
+        .local_addr()
+        .map_err(|e| format!("Descriptor {} is not a TCP socket: {}", fd, e))?;
+    // try_clone's duplicate is close on exec; dropping the original
+    // closes fd.
+
+    listener
+        .try_clone()
+        .map_err(|e| format!("Unable to duplicate the listener on {}: {}", address, e))
+}
+#[cfg(test)]
+mod systemd_tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn notify_1() {
+        // A stand-in for systemd's socket:
+
+        let path = env::temp_dir().join(format!("notify_{}", std::process::id()));
+        let _ = fs::remove_file(&path);
+        let systemd = UnixDatagram::bind(&path).unwrap();
+        let notifier = Notifier::new(path.to_str().unwrap()).unwrap();
+        let mut buffer = [0u8; 256];
+
+        notifier.ready("Serving 2 rings").unwrap();
+        let n = systemd.recv(&mut buffer).unwrap();
+        assert_eq!(b"READY=1\nSTATUS=Serving 2 rings", &buffer[..n]);
+        notifier.watchdog().unwrap();
+        let n = systemd.recv(&mut buffer).unwrap();
+        assert_eq!(b"WATCHDOG=1", &buffer[..n]);
+        fs::remove_file(&path).unwrap();
+    }
+    #[test]
+    fn notify_2() {
+        // Abstract socket names:
+
+        let name = format!("notify_{}", std::process::id());
+        let address = SocketAddr::from_abstract_name(name.as_bytes()).unwrap();
+        let systemd = UnixDatagram::bind_addr(&address).unwrap();
+        let notifier = Notifier::new(&format!("@{}", name)).unwrap();
+        notifier.status("Busy").unwrap();
+        let mut buffer = [0u8; 256];
+        let n = systemd.recv(&mut buffer).unwrap();
+        assert_eq!(b"STATUS=Busy", &buffer[..n]);
+    }
+    #[test]
+    fn listen_1() {
+        assert_eq!(0, listen_fds_from(None, None, 10));
+        assert_eq!(0, listen_fds_from(Some("2"), None, 10));
+        assert_eq!(0, listen_fds_from(Some("2"), Some("11"), 10));
+        assert_eq!(2, listen_fds_from(Some("2"), Some("10"), 10));
+        assert_eq!(0, listen_fds_from(Some("two"), Some("10"), 10));
+    }
+    #[test]
+    fn listen_2() {
+        use std::os::fd::IntoRawFd;
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let listener = unsafe { listener_from_fd(listener.into_raw_fd()) }.unwrap();
+        assert_eq!(address, listener.local_addr().unwrap());
+
+        let file = fs::File::open("Cargo.toml").unwrap();
+        assert!(unsafe { listener_from_fd(file.into_raw_fd()) }.is_err());
+    }
+    #[test]
+    fn watchdog_1() {
+        assert_eq!(None, watchdog_interval_from(None, None, 10));
+        assert_eq!(None, watchdog_interval_from(Some("0"), None, 10));
+        assert_eq!(
+            Some(Duration::from_secs(5)),
+            watchdog_interval_from(Some("10000000"), None, 10)
+        );
+        assert_eq!(
+            Some(Duration::from_secs(5)),
+            watchdog_interval_from(Some("10000000"), Some("10"), 10)
+        );
+        assert_eq!(None, watchdog_interval_from(Some("10000000"), Some("11"), 10));
+        assert_eq!(None, watchdog_interval_from(Some("ten"), None, 10));
+    }
+}
@@ -5,6 +5,8 @@
 //! *   The main list is surrounded by {}.
 //! *   It is assumed each element of the list requires no quoting execpt:
 //! *   Sublists are surrounded by {} as well.
+//! *   Elements added with add_quoted_element, which are quoted if
+//!     needed (e.g. command lines that contain spaces).
 //!
 //! Normally you'd create a list and then add to it.   You can add either
 //! individual entries or sublists.  Formatting is supported e.g.
@@ -34,6 +34,17 @@ impl TclList {
         self
     }
     ///
+    /// Adds an element that may need quoting to remain a single list
+    /// element - e.g. one that has whitespace or braces in it or is
+    /// empty (command lines, user names and such).
+    /// Elements that don't need quoting are added unmodified.
+    ///
+    pub fn add_quoted_element(&mut self, element: &str) -> &mut TclList {
+        self.list
+            .push(TclListElement::Simple(quote_element(element)));
+        self
+    }
+    ///
     /// Adds a constructed sublist to the end of the list.
     /// Again a mutable reference to the sublist is retunred
     /// to support method chaining.
@@ -43,6 +54,53 @@ impl TclList {
         self
     }
 }
+// Quote a string so that Tcl sees it as a single list element.
+// Surrounding with braces is preferred.  That does not work for
+// unbalanced braces or backslashes, so in those cases each
+// special character is backslash escaped instead.
+//
+fn quote_element(element: &str) -> String {
+    const SPECIALS: &str = "{}[]$\";\\";
+    if element.is_empty() {
+        return String::from("{}");
+    }
+    if !element
+        .chars()
+        .any(|c| c.is_whitespace() || SPECIALS.contains(c))
+    {
+        return String::from(element);
+    }
+    let mut depth: i32 = 0;
+    let mut balanced = true;
+    for c in element.chars() {
+        match c {
+            '{' => depth += 1,
+            '}' => {
+                depth -= 1;
+                if depth < 0 {
+                    balanced = false;
+                }
+            }
+            _ => {}
+        }
+    }
+    if balanced && depth == 0 && !element.contains('\\') {
+        return format!("{{{}}}", element);
+    }
+    let mut result = String::new();
+    for c in element.chars() {
+        match c {
+            '\n' => result.push_str("\\n"),
+            '\t' => result.push_str("\\t"),
+            c if c.is_whitespace() || SPECIALS.contains(c) => {
+                result.push('\\');
+                result.push(c);
+            }
+            c => result.push(c),
+        }
+    }
+    result
+}
 // Implement trait Display for TclList so that
 // users can println! or format! it to turn it into
 // a string.
@@ -140,6 +198,33 @@ mod tests {
         assert_eq!("{outer1 {1 2 3 } outer2 {a b c } final }", format!("{}", l));
     }
     #[test]
+    fn quoted_1() {
+        // Things that don't need quoting aren't quoted:
+
+        let mut l = TclList::new();
+        l.add_quoted_element("ring2stdout").add_quoted_element("1234");
+        assert_eq!("{ring2stdout 1234 }", format!("{}", l));
+    }
+    #[test]
+    fn quoted_2() {
+        // Whitespace and empty elements get braces:
+
+        let mut l = TclList::new();
+        l.add_quoted_element("--comment Hoisting to x")
+            .add_quoted_element("");
+        assert_eq!("{{--comment Hoisting to x} {} }", format!("{}", l));
+    }
+    #[test]
+    fn quoted_3() {
+        // Unbalanced braces and backslashes get backslashed:
+
+        let mut l = TclList::new();
+        l.add_quoted_element("a{b")
+            .add_quoted_element("c\\d e")
+            .add_quoted_element("$x");
+        assert_eq!("{a\\{b c\\\\d\\ e {$x} }", format!("{}", l));
+    }
+    #[test]
     fn nested() {
         let mut l = TclList::new();
         let mut sub1 = TclList::new();